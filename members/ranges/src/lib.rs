@@ -43,6 +43,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn key_ranges() {
+        use std::ops::Bound::*;
+
+        let valid_cases = vec![
+            ((Included("'a'"), Excluded("'m'")), "'a'..'m'"),
+            ((Included("'a'"), Included("\"m\"")), "'a'...\"m\""),
+            ((Included("'a'"), Included("'m'")), "'a'..='m'"),
+            ((Included("'a.b'"), Unbounded), "'a.b'.."),
+            ((Unbounded, Excluded("'m'")), "..'m'"),
+            ((Unbounded, Included("'m..n'")), "..='m..n'"),
+        ];
+
+        for (range, string) in valid_cases {
+            assert_eq!(Some(range), parse_key_range(string));
+        }
+
+        let invalid_cases = vec!["..", "0..3", "a..m", "'a'..m", "'a'...", "'a..m'", "'a'"];
+
+        for range in invalid_cases {
+            assert_eq!(None, parse_key_range(range))
+        }
+    }
+
+    #[test]
+    fn stepped_index_ranges() {
+        let stepped = |range: Range, step| range.with_step(step).unwrap();
+        let valid_cases = vec![
+            (stepped(Range::exclusive(Index::Forward(0), Index::Forward(10)), 2), "0..10..2"),
+            (stepped(Range::inclusive(Index::Forward(1), Index::Forward(9)), 4), "1...9..4"),
+            (stepped(Range::inclusive(Index::Forward(0), Index::Backward(0)), -1), "0..=-1..-1"),
+            (stepped(Range::from(Index::Forward(3)), 3), "3....3"),
+            (stepped(Range::to(Index::Forward(5)), 2), "..5..2"),
+            (stepped(Range::from(Index::Forward(0)), -1), "....-1"),
+        ];
+
+        for (range, string) in valid_cases {
+            assert_eq!(Some(range), parse_index_range(string));
+        }
+
+        let invalid_cases = vec!["0..10..0", "0..10..a", "0..10..2..3", "0..10..", "0..a..2"];
+
+        for range in invalid_cases {
+            assert_eq!(None, parse_index_range(range))
+        }
+    }
+
+    #[test]
+    fn stepped_selection() {
+        let select = |range: &str| {
+            let selection = range.parse::<Select<String>>().unwrap();
+            (0..10).select::<Vec<_>, _>(&selection, 10)
+        };
+        assert_eq!(select("0..10..2"), vec![0, 2, 4, 6, 8]);
+        assert_eq!(select("1...9..4"), vec![1, 5, 9]);
+        assert_eq!(select("....-1"), vec![9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+        assert_eq!(select("2..8..-3"), vec![7, 4]);
+        assert_eq!(select("-3....5"), vec![7]);
+    }
+
     fn test_range<T: Iterator<Item = i8>>(range: &str, expected: T) {
         let actual: Vec<String> = parse_range(range).unwrap().collect();
         let expected: Vec<_> = expected.map(|i| i.to_string()).collect();
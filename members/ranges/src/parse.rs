@@ -1,5 +1,5 @@
 use super::{Index, Range};
-use std::{cmp::Ordering, u8};
+use std::{cmp::Ordering, ops::Bound, u8};
 
 fn numeric_range<'a, K: From<String>>(
     start: isize,
@@ -115,7 +115,77 @@ pub fn parse_range<K: From<String>>(input: &str) -> Option<Box<dyn Iterator<Item
     }
 }
 
+// In an index range we allow the following syntax:
+//      Exclusive nonstepped: [start..end]
+//      Inclusive nonstepped: [start...end]
+//      Exclusive stepped: [start..end..step]
+//      Inclusive stepped: [start...end..step]
+// where start and end may be omitted.
 pub fn parse_index_range(input: &str) -> Option<Range> {
+    let parts = input.split("..").collect::<Vec<_>>();
+    match parts.len() {
+        2 => parse_unstepped_index_range(input),
+        3 => {
+            let step = parts[2].parse::<isize>().ok()?;
+            let range = &input[..input.len() - parts[2].len() - 2];
+            let range = if range == ".." {
+                Some(Range::from(Index::new(0)))
+            } else {
+                parse_unstepped_index_range(range)
+            };
+            range?.with_step(step)
+        }
+        _ => None,
+    }
+}
+
+// In a key range, the keys are quoted so that the range is not taken for a key or an index:
+//      Exclusive: ['start'..'end']
+//      Inclusive: ['start'...'end'] or ['start'..='end']
+// where start or end may be omitted. The keys are returned with their quotes.
+pub fn parse_key_range(input: &str) -> Option<(Bound<&str>, Bound<&str>)> {
+    let mut quote = None;
+    let mut separator = None;
+    for (id, character) in input.char_indices() {
+        match character {
+            '\'' | '"' if quote.is_none() => quote = Some(character),
+            _ if quote == Some(character) => quote = None,
+            '.' if quote.is_none() && input[id..].starts_with("..") => {
+                separator = Some(id);
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    let separator = separator?;
+    let start = &input[..separator];
+    let mut end = &input[separator + 2..];
+    let inclusive = end.starts_with('.') || end.starts_with('=');
+    if inclusive {
+        end = &end[1..];
+    }
+
+    let is_quoted = |key: &str| {
+        key.len() >= 2
+            && (key.starts_with('\'') && key.ends_with('\'')
+                || key.starts_with('"') && key.ends_with('"'))
+    };
+    let start = match start {
+        "" => Bound::Unbounded,
+        key if is_quoted(key) => Bound::Included(key),
+        _ => return None,
+    };
+    let end = match end {
+        "" if !inclusive && start != Bound::Unbounded => Bound::Unbounded,
+        key if is_quoted(key) && inclusive => Bound::Included(key),
+        key if is_quoted(key) => Bound::Excluded(key),
+        _ => return None,
+    };
+    Some((start, end))
+}
+
+fn parse_unstepped_index_range(input: &str) -> Option<Range> {
     let mut parts = input.splitn(2, "..");
     let first = parts.next()?;
     let mut end = parts.next()?;
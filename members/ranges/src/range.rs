@@ -1,4 +1,8 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    iter::{Rev, StepBy},
+    vec::IntoIter,
+};
 
 use super::Index;
 
@@ -12,11 +16,37 @@ pub struct Range {
     /// Is this range inclusive? If false, this object represents a half-open
     /// range of [start, end), otherwise [start, end]
     inclusive: bool,
+    /// Distance between the selected elements. A negative step walks the range
+    /// backwards, from its last element. Never zero.
+    step:      isize,
+}
+
+/// The elements selected by [`Range::step`]
+pub enum Stepped<I: Iterator> {
+    /// Every few elements, taken as they come
+    Forward(StepBy<I>),
+    /// Every few elements from the last, which are buffered to be walked backwards
+    Backward(StepBy<Rev<IntoIter<I::Item>>>),
+}
+
+impl<I: Iterator> Iterator for Stepped<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Stepped::Forward(elements) => elements.next(),
+            Stepped::Backward(elements) => elements.next(),
+        }
+    }
 }
 
 impl Display for Range {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({},{})", self.start, self.end)
+        if self.step == 1 {
+            write!(f, "({},{})", self.start, self.end)
+        } else {
+            write!(f, "({},{},{})", self.start, self.end, self.step)
+        }
     }
 }
 
@@ -44,11 +74,46 @@ impl Range {
         }
     }
 
-    pub fn exclusive(start: Index, end: Index) -> Range { Range { start, end, inclusive: false } }
+    /// Applies the step of this range to the elements within its bounds. Only a negative step
+    /// needs to collect the elements first.
+    /// ```ignore,rust
+    /// let vec = vec![0, 1, 2, 3, 4, 5, 6, 7, 8];
+    /// let range = Range::exclusive(Index::new(1), Index::new(8)).with_step(-3).unwrap();
+    /// let (start, size) = range.bounds(vec.len()).unwrap();
+    /// let selection = range.step(vec.iter().skip(start).take(size)).collect::<Vec<_>>();
+    /// assert_eq!(vec![&7, &4, &1], selection);
+    /// ```
+    pub fn step<I: Iterator>(&self, elements: I) -> Stepped<I> {
+        let step = self.step.unsigned_abs();
+        if self.step < 0 {
+            Stepped::Backward(elements.collect::<Vec<_>>().into_iter().rev().step_by(step))
+        } else {
+            Stepped::Forward(elements.step_by(step))
+        }
+    }
+
+    /// Sets the step of this range, which must not be zero.
+    pub fn with_step(self, step: isize) -> Option<Range> {
+        if step == 0 {
+            None
+        } else {
+            Some(Range { step, ..self })
+        }
+    }
+
+    pub fn exclusive(start: Index, end: Index) -> Range {
+        Range { start, end, inclusive: false, step: 1 }
+    }
 
-    pub fn inclusive(start: Index, end: Index) -> Range { Range { start, end, inclusive: true } }
+    pub fn inclusive(start: Index, end: Index) -> Range {
+        Range { start, end, inclusive: true, step: 1 }
+    }
 
-    pub fn from(start: Index) -> Range { Range { start, end: Index::new(-1), inclusive: true } }
+    pub fn from(start: Index) -> Range {
+        Range { start, end: Index::new(-1), inclusive: true, step: 1 }
+    }
 
-    pub fn to(end: Index) -> Range { Range { start: Index::new(0), end, inclusive: false } }
+    pub fn to(end: Index) -> Range {
+        Range { start: Index::new(0), end, inclusive: false, step: 1 }
+    }
 }
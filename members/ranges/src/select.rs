@@ -1,6 +1,7 @@
 use super::{parse_index_range, Index, Range};
 use std::{
     iter::{empty, FromIterator},
+    ops::Bound,
     str::FromStr,
 };
 
@@ -15,6 +16,8 @@ pub enum Select<K> {
     Range(Range),
     /// Select an element by mapped key
     Key(K),
+    /// Select the elements whose mapped keys are within the bounds, in the order of the keys
+    KeyRange(Bound<K>, Bound<K>),
 }
 
 pub trait SelectWithSize {
@@ -35,13 +38,13 @@ where
         O: FromIterator<Self::Item>,
     {
         match s {
-            Select::Key(_) => empty().collect(),
+            Select::Key(_) | Select::KeyRange(..) => empty().collect(),
             Select::All => self.collect(),
             Select::Index(Index::Forward(idx)) => self.nth(*idx).into_iter().collect(),
             Select::Index(Index::Backward(idx)) => self.rev().nth(*idx).into_iter().collect(),
             Select::Range(range) => range
                 .bounds(size)
-                .map(|(start, length)| self.skip(start).take(length).collect())
+                .map(|(start, length)| range.step(self.skip(start).take(length)).collect())
                 .unwrap_or_else(|| empty().collect()),
         }
     }
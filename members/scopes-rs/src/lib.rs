@@ -2,7 +2,7 @@ use std::{
     borrow::Borrow,
     collections::HashMap,
     hash::Hash,
-    ops::{Deref, DerefMut},
+    ops::{Deref, DerefMut, Range},
 };
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -36,6 +36,51 @@ impl<K: Hash + Eq, V> DerefMut for Scope<K, V> {
     fn deref_mut(&mut self) -> &mut Self::Target { &mut self.vars }
 }
 
+/// A stack of scopes, which can be entered for as long as a [`ScopeGuard`] lives.
+pub trait Scoped {
+    /// Create a new scope. If namespace is true, variables won't be droppable across the scope
+    /// boundary
+    fn new_scope(&mut self, namespace: bool);
+
+    /// Exit the current scope
+    fn pop_scope(&mut self);
+
+    /// Create a new scope that lasts as long as the returned guard, so that it is popped even on
+    /// early returns.
+    fn enter_scope(&mut self, namespace: bool) -> ScopeGuard<'_, Self> {
+        self.new_scope(namespace);
+        ScopeGuard { inner: self }
+    }
+}
+
+/// A scope entered with [`Scoped::enter_scope`], which is popped when the guard is dropped.
+///
+/// The scoped value is reachable through the guard while it lives.
+#[derive(Debug)]
+pub struct ScopeGuard<'a, T: Scoped + ?Sized> {
+    inner: &'a mut T,
+}
+
+impl<'a, T: Scoped + ?Sized> Deref for ScopeGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target { self.inner }
+}
+
+impl<'a, T: Scoped + ?Sized> DerefMut for ScopeGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target { self.inner }
+}
+
+impl<'a, T: Scoped + ?Sized> Drop for ScopeGuard<'a, T> {
+    fn drop(&mut self) { self.inner.pop_scope(); }
+}
+
+impl<K: Hash + Eq, V: Clone> Scoped for Scopes<K, V> {
+    fn new_scope(&mut self, namespace: bool) { Self::new_scope(self, namespace) }
+
+    fn pop_scope(&mut self) { Self::pop_scope(self) }
+}
+
 impl<K: Hash + Eq, V: Clone> Scopes<K, V> {
     pub fn with_capacity(cap: usize) -> Self {
         Self {
@@ -101,6 +146,56 @@ impl<K: Hash + Eq, V: Clone> Scopes<K, V> {
         self.scopes[0].insert(name.into(), value.into())
     }
 
+    /// Sets a variable in `namespace`, updating it in the scope of the namespace which has it.
+    ///
+    /// Otherwise, the variable is created in the outermost scope of the namespace, so that it
+    /// lasts as long as the namespace, except with `Namespace::Any` which creates it in the
+    /// current scope.
+    ///
+    /// Returns false if there is no such namespace, in which case nothing is set.
+    pub fn set_in<T: Into<K>, S: Into<V>>(
+        &mut self,
+        name: T,
+        value: S,
+        namespace: Namespace,
+    ) -> bool {
+        let scopes = match namespace {
+            Namespace::Global => self.namespace_scopes(self.namespaces() - 1),
+            Namespace::Specific(up) => self.namespace_scopes(up),
+            Namespace::Any => self.namespace_scopes(0),
+        };
+        let scopes = match scopes {
+            Some(scopes) => &mut self.scopes[scopes],
+            None => return false,
+        };
+
+        let name = name.into();
+        let id = scopes.iter().rposition(|scope| scope.contains_key(&name));
+        let created = if namespace == Namespace::Any { scopes.len() - 1 } else { 0 };
+        scopes[id.unwrap_or(created)].insert(name, value.into());
+        true
+    }
+
+    /// The number of namespaces the current scopes are part of.
+    fn namespaces(&self) -> usize {
+        1 + self.scopes[1..=self.current].iter().filter(|scope| scope.namespace).count()
+    }
+
+    /// The range of the scopes forming the namespace `up` levels above the current one.
+    fn namespace_scopes(&self, mut up: usize) -> Option<Range<usize>> {
+        let mut end = self.current + 1;
+        for id in (0..=self.current).rev() {
+            if id == 0 || self.scopes[id].namespace {
+                if up == 0 {
+                    return Some(id..end);
+                }
+                up -= 1;
+                end = id;
+            }
+        }
+        None
+    }
+
     pub fn get<Q: ?Sized>(&self, name: &Q, namespace: Namespace) -> Option<&V>
     where
         K: Borrow<Q>,
@@ -166,3 +261,66 @@ impl<K: Hash + Eq, V: Clone> Scopes<K, V> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_pops_scope() {
+        let mut scopes = Scopes::<&str, &str>::with_capacity(4);
+        scopes.set("outer", "1");
+        {
+            let mut scope = scopes.enter_scope(false);
+            scope.set("inner", "2");
+            assert_eq!(scope.get("outer", Namespace::Any), Some(&"1"));
+            assert_eq!(scope.get("inner", Namespace::Any), Some(&"2"));
+            assert_eq!(scope.scopes().count(), 2);
+        }
+        assert_eq!(scopes.scopes().count(), 1);
+        assert_eq!(scopes.get("inner", Namespace::Any), None);
+        assert_eq!(scopes.get("outer", Namespace::Any), Some(&"1"));
+    }
+
+    #[test]
+    fn guard_pops_scope_on_early_return() {
+        fn fails(scopes: &mut Scopes<&str, &str>) -> Result<(), ()> {
+            let mut scope = scopes.enter_scope(true);
+            scope.set("temporary", "3");
+            Err(())?;
+            Ok(())
+        }
+
+        let mut scopes = Scopes::with_capacity(4);
+        assert!(fails(&mut scopes).is_err());
+        assert_eq!(scopes.scopes().count(), 1);
+        assert_eq!(scopes.get("temporary", Namespace::Any), None);
+    }
+
+    #[test]
+    fn set_in_namespaces() {
+        let mut scopes = Scopes::<&str, &str>::with_capacity(4);
+        scopes.set("global", "0");
+        scopes.new_scope(false);
+        scopes.new_scope(true);
+        scopes.set("parent", "1");
+        scopes.new_scope(true);
+        scopes.new_scope(false);
+
+        assert!(scopes.set_in("global", "updated", Namespace::Global));
+        assert!(scopes.set_in("created", "global", Namespace::Global));
+        assert!(scopes.set_in("parent", "updated", Namespace::Specific(1)));
+        assert!(scopes.set_in("local", "2", Namespace::Any));
+        assert!(!scopes.set_in("nowhere", "3", Namespace::Specific(3)));
+
+        assert_eq!(scopes.get("local", Namespace::Any), Some(&"2"));
+        scopes.pop_scope();
+        assert_eq!(scopes.get("local", Namespace::Any), None);
+        scopes.pop_scope();
+        assert_eq!(scopes.get("parent", Namespace::Any), Some(&"updated"));
+        scopes.pop_scope();
+        scopes.pop_scope();
+        assert_eq!(scopes.scopes().next().unwrap().get("global"), Some(&"updated"));
+        assert_eq!(scopes.get("created", Namespace::Any), Some(&"global"));
+    }
+}
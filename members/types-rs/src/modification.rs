@@ -3,6 +3,8 @@ use super::Value;
 pub trait Modifications {
     fn append(&mut self, val: Self) -> bool;
     fn prepend(&mut self, val: Self) -> bool;
+    /// Replace the value with `val` if it is unset, or an empty string, array or map
+    fn default_to(&mut self, val: Self) -> bool;
 }
 
 impl<T> Modifications for Value<T> {
@@ -53,4 +55,20 @@ impl<T> Modifications for Value<T> {
             _ => false,
         }
     }
+
+    fn default_to(&mut self, val: Self) -> bool {
+        let empty = match self {
+            Value::Str(string) => string.is_empty(),
+            Value::Alias(alias) => alias.is_empty(),
+            Value::Array(array) => array.is_empty(),
+            Value::HashMap(map) => map.is_empty(),
+            Value::BTreeMap(map) => map.is_empty(),
+            Value::Function(_) => false,
+            Value::None => true,
+        };
+        if empty {
+            *self = val;
+        }
+        empty
+    }
 }
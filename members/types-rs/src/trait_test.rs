@@ -484,3 +484,23 @@ fn prepend_str_str() {
     assert!(a.prepend(b));
     assert_eq!(a, Value::Str("1.16".into()));
 }
+
+#[test]
+fn default_to_empty_values() {
+    let mut a = Value::<()>::Str("".into());
+    assert!(a.default_to(Value::Str("default".into())));
+    assert_eq!(a, Value::Str("default".into()));
+    assert!(!a.default_to(Value::Str("ignored".into())));
+    assert_eq!(a, Value::Str("default".into()));
+
+    let mut a = Value::<()>::Array(Vec::new());
+    assert!(a.default_to(Value::Array(vec!["1".into()])));
+    assert_eq!(a, Value::Array(vec!["1".into()]));
+    let mut a = Value::<()>::Array(vec!["".into()]);
+    assert!(!a.default_to(Value::Str("ignored".into())));
+
+    let mut a = Value::<()>::HashMap(types::HashMap::default());
+    assert!(a.default_to(Value::Str("default".into())));
+    assert!(Value::<()>::None.default_to(Value::Str("default".into())));
+    assert!(!Value::Function(()).default_to(Value::Str("default".into())));
+}
@@ -1,13 +1,15 @@
 #![allow(clippy::from_over_into)]
 use super::Value;
+use indexmap::IndexMap;
 use std::{
-    collections::{BTreeMap as StdBTreeMap, HashMap as StdHashMap},
+    collections::BTreeMap as StdBTreeMap,
     iter::FromIterator,
     ops::{Deref, DerefMut},
 };
 
 pub type Array<T> = Vec<Value<T>>;
-pub type HashMap<T> = StdHashMap<Str, Value<T>>;
+/// A map which is iterated in the order its keys were first inserted
+pub type HashMap<T> = IndexMap<Str, Value<T>>;
 pub type BTreeMap<T> = StdBTreeMap<Str, Value<T>>;
 pub type Str = small::String;
 
@@ -47,7 +47,8 @@ SYNOPSIS
     exit
 
 DESCRIPTION
-    Makes ion exit. The exit status will be that of the last command executed."
+    Makes ion exit. The exit status will be that of the last command executed. The EXIT trap runs \
+           before exiting."
 )]
 pub fn exit(args: &[Str], shell: &mut Shell<'_>) -> Status {
     // Kill all active background tasks before exiting the shell.
@@ -56,6 +57,8 @@ pub fn exit(args: &[Str], shell: &mut Shell<'_>) -> Status {
         .get(1)
         .and_then(|status| status.parse::<i32>().ok())
         .unwrap_or_else(|| shell.previous_status().as_os_code());
+    shell.run_exit_trap();
+    let _ = shell.flush();
     std::process::exit(exit_code);
 }
 
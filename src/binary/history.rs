@@ -196,6 +196,10 @@ impl<'a> InteractiveShell<'a> {
             return false;
         }
 
+        if self.shell.borrow().is_history_ignored(command) {
+            return false;
+        }
+
         // default to true, as it's more likely that we want to save a command in
         // history
         true
@@ -21,7 +21,7 @@ use liner::{Buffer, Context, KeyBindings};
 use std::{
     cell::{Cell, RefCell},
     fs::{self, OpenOptions},
-    io::{self, Write},
+    io,
     os::unix::io::{AsRawFd, IntoRawFd},
     path::Path,
     rc::Rc,
@@ -37,6 +37,7 @@ USAGE:
 
 FLAGS:
     -f, --fake-interactive    Use a fake interactive mode, where errors don't exit the shell
+    -g, --no-glob             Do not expand glob patterns
     -h, --help                Prints help information
     -i, --interactive         Force interactive mode
     -n, --no-execute          Do not execute any commands, perform only syntax checking
@@ -46,16 +47,31 @@ FLAGS:
 OPTIONS:
     -c <command>             Evaluate given commands instead of reading from the commandline
     -o <key-bindings>        Shortcut layout. Valid options: "vi", "emacs"
+    --ctrl-c <behavior>      What Ctrl-C does at the prompt. Valid options: "abort" (default), "clear",
+                             "exit"
 
 ARGS:
     <args>...    Script arguments (@args). If the -c option is not specified, the first parameter is taken as a
                  filename to execute"#;
 
+/// What pressing Ctrl-C at the prompt does
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CtrlCBehavior {
+    /// Discard the line being edited and show a new prompt. Unfinished blocks are kept.
+    #[default]
+    Abort,
+    /// Discard the line being edited along with the unfinished blocks it continues
+    Clear,
+    /// Exit the shell, as Ctrl-D does on an empty line
+    Exit,
+}
+
 pub struct InteractiveShell<'a> {
     context:    Rc<RefCell<Context>>,
     shell:      RefCell<Shell<'a>>,
     terminated: Cell<bool>,
     huponexit:  Rc<Cell<bool>>,
+    ctrl_c:     CtrlCBehavior,
 }
 
 impl<'a> InteractiveShell<'a> {
@@ -69,6 +85,7 @@ impl<'a> InteractiveShell<'a> {
             shell:      RefCell::new(shell),
             terminated: Cell::new(true),
             huponexit:  Rc::new(Cell::new(false)),
+            ctrl_c:     CtrlCBehavior::default(),
         }
     }
 
@@ -123,6 +140,7 @@ impl<'a> InteractiveShell<'a> {
                 shell.background_send(Signal::SIGHUP).expect("Failed to prepare for exit");
             }
             context_bis.borrow_mut().history.commit_to_file();
+            let _ = shell.flush();
         };
 
         let exit = self.shell.borrow().builtins().get("exit").unwrap();
@@ -144,7 +162,7 @@ impl<'a> InteractiveShell<'a> {
         let set_huponexit: BuiltinFunction = &huponexit::builtin_huponexit(huponexit);
 
         // change the lifetime to allow adding local builtins
-        let InteractiveShell { context, shell, terminated, huponexit } = self;
+        let InteractiveShell { context, shell, terminated, huponexit, ctrl_c } = self;
         let mut shell = shell.into_inner();
         shell
             .builtins_mut()
@@ -162,7 +180,7 @@ impl<'a> InteractiveShell<'a> {
             Err(err) => eprintln!("ion: unable to get xdg base directory: {:?}", err),
         }
 
-        InteractiveShell { context, shell: RefCell::new(shell), terminated, huponexit }
+        InteractiveShell { context, shell: RefCell::new(shell), terminated, huponexit, ctrl_c }
             .exec(prep_for_exit)
     }
 
@@ -252,18 +270,16 @@ impl<'a> InteractiveShell<'a> {
 
     fn exec<T: Fn(&mut Shell<'_>)>(mut self, prep_for_exit: &T) -> ! {
         loop {
-            if let Err(err) = io::stdout().flush() {
-                eprintln!("ion: failed to flush stdio: {}", err);
-            }
-            if let Err(err) = io::stderr().flush() {
-                println!("ion: failed to flush stderr: {}", err);
+            if let Err(err) = self.shell.borrow_mut().flush() {
+                eprintln!("ion: failed to flush the output: {}", err);
             }
             match self.readln(prep_for_exit) {
                 Some(lines) => {
+                    let comment = self.shell.borrow().opts().comment;
                     for command in lines
                         .into_bytes()
                         .into_iter()
-                        .batching(|bytes| Terminator::new(bytes).terminate())
+                        .batching(|bytes| Terminator::new(bytes).with_comment(comment).terminate())
                     {
                         self.exec_single_command(&command);
                     }
@@ -291,6 +307,9 @@ impl<'a> InteractiveShell<'a> {
         Ok(out)
     }
 
+    /// Set what pressing Ctrl-C at the prompt does
+    pub fn set_ctrl_c_behavior(&mut self, behavior: CtrlCBehavior) { self.ctrl_c = behavior; }
+
     /// Set the keybindings of the underlying liner context
     pub fn set_keybindings(&mut self, key_bindings: KeyBindings) {
         self.context.borrow_mut().key_bindings = key_bindings;
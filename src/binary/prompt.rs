@@ -6,6 +6,25 @@ use ion_shell::{
 use liner::{KeyBindings::*, Prompt, ViPromptMode, ViStatus};
 
 impl<'a> InteractiveShell<'a> {
+    /// Runs the code of the `PROMPT_COMMAND` variable before a new prompt, keeping the status of
+    /// the last command. The prompts continuing an unfinished block do not run it.
+    pub fn run_prompt_command(&self) {
+        let mut shell = self.shell.borrow_mut();
+        if !self.terminated.get() || shell.block_len() > 0 {
+            return;
+        }
+        let code = match shell.variables().get_str("PROMPT_COMMAND") {
+            Ok(code) if !code.is_empty() => code,
+            _ => return,
+        };
+
+        let previous_status = shell.previous_status();
+        if let Err(err) = shell.execute_command(code.as_bytes()) {
+            eprintln!("ion: PROMPT_COMMAND: {}", err);
+        }
+        shell.set_previous_status(previous_status);
+    }
+
     /// Generates the prompt that will be used by Liner.
     pub fn prompt(&self) -> Prompt {
         let mut shell = self.shell.borrow_mut();
@@ -91,3 +110,43 @@ fn vi_prompt_indicator(shell: &mut Shell, mode: ViPromptMode) -> String {
             String::new()
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ion_shell::builtins::Status;
+
+    #[test]
+    fn prompt_command_runs_before_prompts() {
+        let interactive = InteractiveShell::new(Shell::new());
+        interactive
+            .shell
+            .borrow_mut()
+            .execute_command(
+                &b"let prompts = 0; let PROMPT_COMMAND = 'let prompts += 1'; false"[..],
+            )
+            .unwrap();
+
+        interactive.run_prompt_command();
+        interactive.run_prompt_command();
+        let shell = interactive.shell.borrow();
+        assert_eq!(shell.variables().get_str("prompts").unwrap().as_str(), "2");
+        // The status of the last command is still shown in the prompt
+        assert_eq!(shell.previous_status(), Status::FALSE);
+    }
+
+    #[test]
+    fn prompt_command_skips_unfinished_blocks() {
+        let interactive = InteractiveShell::new(Shell::new());
+        interactive
+            .shell
+            .borrow_mut()
+            .execute_command(&b"let prompts = 0; let PROMPT_COMMAND = 'let prompts += 1'"[..])
+            .unwrap();
+        interactive.shell.borrow_mut().on_command("for x in 1 2".bytes(), false).unwrap();
+
+        interactive.run_prompt_command();
+        let shell = interactive.shell.borrow();
+        assert_eq!(shell.variables().get_str("prompts").unwrap().as_str(), "0");
+    }
+}
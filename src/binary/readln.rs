@@ -1,4 +1,4 @@
-use super::{completer::IonCompleter, InteractiveShell};
+use super::{completer::IonCompleter, CtrlCBehavior, InteractiveShell};
 use ion_shell::Shell;
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use std::io::ErrorKind;
@@ -9,12 +9,26 @@ impl<'a> InteractiveShell<'a> {
         fcntl(fd, FcntlArg::F_SETFL(OFlag::O_RDWR)).unwrap();
     }
 
+    /// Applies the Ctrl-C behavior to the input read so far, returning whether the shell must
+    /// exit.
+    fn interrupt(&self) -> bool {
+        match self.ctrl_c {
+            CtrlCBehavior::Abort => false,
+            CtrlCBehavior::Clear => {
+                self.shell.borrow_mut().reset_flow();
+                false
+            }
+            CtrlCBehavior::Exit => true,
+        }
+    }
+
     /// Ion's interface to Liner's `read_line` method, which handles everything related to
     /// rendering, controlling, and getting input from the prompt.
     pub fn readln<T: Fn(&mut Shell<'_>)>(&self, prep_for_exit: &T) -> Option<String> {
         Self::change_blocking(0);
         Self::change_blocking(1);
         Self::change_blocking(2);
+        self.run_prompt_command();
         let prompt = self.prompt();
         let line = self.context.borrow_mut().read_line(
             prompt,
@@ -24,7 +38,7 @@ impl<'a> InteractiveShell<'a> {
 
         match line {
             Ok(line) => {
-                if line.bytes().next() != Some(b'#')
+                if line.bytes().next() != self.shell.borrow().opts().comment
                     && line.bytes().any(|c| !c.is_ascii_whitespace())
                 {
                     self.terminated.set(false);
@@ -32,11 +46,20 @@ impl<'a> InteractiveShell<'a> {
                 Some(line)
             }
             // Handles Ctrl + C
-            Err(ref err) if err.kind() == ErrorKind::Interrupted => None,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => {
+                if self.interrupt() {
+                    let mut shell = self.shell.borrow_mut();
+                    shell.run_exit_trap();
+                    prep_for_exit(&mut shell);
+                    std::process::exit(shell.previous_status().as_os_code())
+                }
+                None
+            }
             // Handles Ctrl + D
             Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
                 let mut shell = self.shell.borrow_mut();
                 if self.terminated.get() && shell.exit_block().is_err() {
+                    shell.run_exit_trap();
                     prep_for_exit(&mut shell);
                     std::process::exit(shell.previous_status().as_os_code())
                 }
@@ -49,3 +72,24 @@ impl<'a> InteractiveShell<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interrupt_in_block(behavior: CtrlCBehavior) -> (bool, usize) {
+        let mut interactive = InteractiveShell::new(Shell::new());
+        interactive.set_ctrl_c_behavior(behavior);
+        interactive.shell.borrow_mut().on_command("for x in 1 2".bytes(), false).unwrap();
+        let exit = interactive.interrupt();
+        let block_len = interactive.shell.borrow().block_len();
+        (exit, block_len)
+    }
+
+    #[test]
+    fn ctrl_c_behaviors() {
+        assert_eq!(interrupt_in_block(CtrlCBehavior::default()), (false, 1));
+        assert_eq!(interrupt_in_block(CtrlCBehavior::Clear), (false, 0));
+        assert_eq!(interrupt_in_block(CtrlCBehavior::Exit), (true, 1));
+    }
+}
@@ -5,11 +5,98 @@ use builtins_proc::builtin;
 use smallvec::SmallVec;
 use std::io::{self, BufWriter, Write};
 
+/// How the arguments of `echo` are written
+#[derive(Debug)]
+struct EchoOptions {
+    /// Interpret backslash escapes
+    escape:  bool,
+    /// Terminate the output
+    newline: bool,
+    /// Separate the arguments
+    spaces:  bool,
+    /// Use NUL bytes instead of spaces and newlines
+    null:    bool,
+}
+
+/// Writes the arguments to `buffer` as described by `options`.
+fn write_args<W: Write>(buffer: &mut W, args: &[&str], options: &EchoOptions) -> io::Result<()> {
+    let (separator, terminator): (&[u8], &[u8]) =
+        if options.null { (b"\0", b"\0") } else { (b" ", b"\n") };
+    let mut first = true;
+    for arg in args.iter().map(|x| x.as_bytes()) {
+        if options.spaces && !first {
+            buffer.write_all(separator)?;
+        }
+        first = false;
+
+        if options.escape {
+            let mut check = false;
+            for &byte in arg {
+                match byte {
+                    b'\\' if check => {
+                        buffer.write_all(&[byte])?;
+                        check = false;
+                    }
+                    b'\\' => check = true,
+                    b'a' if check => {
+                        buffer.write_all(&[7])?; // bell
+                        check = false;
+                    }
+                    b'b' if check => {
+                        buffer.write_all(&[8])?; // backspace
+                        check = false;
+                    }
+                    b'c' if check => {
+                        return Ok(());
+                    }
+                    b'e' if check => {
+                        buffer.write_all(&[27])?; // escape
+                        check = false;
+                    }
+                    b'f' if check => {
+                        buffer.write_all(&[12])?; // form feed
+                        check = false;
+                    }
+                    b'n' if check => {
+                        buffer.write_all(b"\n")?; // newline
+                        check = false;
+                    }
+                    b'r' if check => {
+                        buffer.write_all(b"\r")?;
+                        check = false;
+                    }
+                    b't' if check => {
+                        buffer.write_all(b"\t")?;
+                        check = false;
+                    }
+                    b'v' if check => {
+                        buffer.write_all(&[11])?; // vertical tab
+                        check = false;
+                    }
+                    _ if check => {
+                        buffer.write_all(&[b'\\', byte])?;
+                        check = false;
+                    }
+                    _ => {
+                        buffer.write_all(&[byte])?;
+                    }
+                }
+            }
+        } else {
+            buffer.write_all(arg)?;
+        }
+    }
+    if options.newline {
+        buffer.write_all(terminator)?;
+    }
+    Ok(())
+}
+
 #[builtin(
     desc = "display text",
     man = "
 SYNOPSIS
-    echo [ -h | --help ] [-e] [-n] [-s] [STRING]...
+    echo [ -h | --help ] [-e] [-E] [-n] [-s] [-0] [STRING]...
 
 DESCRIPTION
     Print the STRING(s) to standard output.
@@ -17,10 +104,16 @@ DESCRIPTION
 OPTIONS
     -e
         enable the interpretation of backslash escapes
+    -E
+        disable the interpretation of backslash escapes, which is the default unless the shell is \
+           set up otherwise. Of -e and -E, the last one given wins
     -n
         do not output the trailing newline
     -s
         do not separate arguments with spaces
+    -0, --null
+        separate and terminate the arguments with NUL bytes instead of spaces and a newline, as \
+           expected by `xargs -0`. With -n, the last argument is not terminated
 
     Escape Sequences
         When the -e argument is used, the following sequences will be interpreted:
@@ -35,30 +128,42 @@ OPTIONS
         \\t  horizontal tab (HT)
         \\v  vertical tab (VT)"
 )]
-pub fn echo(args: &[types::Str], _: &mut Shell<'_>) -> Status {
-    let mut escape = false;
-    let mut newline = true;
-    let mut spaces = true;
+pub fn echo(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    let (options, data) = parse_args(args, shell.opts().echo_escapes);
+    let stdout = io::stdout();
+    let mut buffer = BufWriter::new(stdout.lock());
+    write_args(&mut buffer, &data[1..], &options).and_then(|_| buffer.flush()).into()
+}
+
+/// Splits the arguments of `echo` into its options and the arguments to write, with escapes
+/// interpreted by default if `escape` is set.
+fn parse_args(args: &[types::Str], escape: bool) -> (EchoOptions, SmallVec<[&str; 16]>) {
+    let mut options = EchoOptions { escape, newline: true, spaces: true, null: false };
     let mut data: SmallVec<[&str; 16]> = SmallVec::with_capacity(16);
 
     for arg in args {
         match &**arg {
-            "--escape" => escape = true,
-            "--no-newline" => newline = false,
-            "--no-spaces" => spaces = false,
+            "--escape" => options.escape = true,
+            "--no-escape" => options.escape = false,
+            "--no-newline" => options.newline = false,
+            "--no-spaces" => options.spaces = false,
+            "--null" => options.null = true,
             _ if arg.starts_with('-') => {
                 let mut is_opts = true;
                 let opts = &arg[1..];
 
-                let mut short_escape = false;
+                let mut short_escape = None;
                 let mut short_newline = true;
                 let mut short_spaces = true;
+                let mut short_null = false;
 
                 for argopt in opts.bytes() {
                     match argopt {
-                        b'e' => short_escape = true,
+                        b'e' => short_escape = Some(true),
+                        b'E' => short_escape = Some(false),
                         b'n' => short_newline = false,
                         b's' => short_spaces = false,
+                        b'0' => short_null = true,
                         _ => {
                             is_opts = false;
                             break;
@@ -66,9 +171,10 @@ pub fn echo(args: &[types::Str], _: &mut Shell<'_>) -> Status {
                     }
                 }
                 if is_opts {
-                    escape = escape || short_escape;
-                    newline = newline && short_newline;
-                    spaces = spaces && short_spaces;
+                    options.escape = short_escape.unwrap_or(options.escape);
+                    options.newline = options.newline && short_newline;
+                    options.spaces = options.spaces && short_spaces;
+                    options.null = options.null || short_null;
                 } else {
                     data.push(arg);
                 }
@@ -78,80 +184,56 @@ pub fn echo(args: &[types::Str], _: &mut Shell<'_>) -> Status {
             }
         }
     }
+    (options, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(args: &[&str], newline: bool, null: bool) -> Vec<u8> {
+        let options = EchoOptions { escape: false, newline, spaces: true, null };
+        let mut output = Vec::new();
+        write_args(&mut output, args, &options).unwrap();
+        output
+    }
 
-    let stdout = io::stdout();
-    let mut buffer = BufWriter::new(stdout.lock());
+    #[test]
+    fn null_separators() {
+        assert_eq!(output(&["a", "b c", "d\ne"], true, false), b"a b c d\ne\n");
+        assert_eq!(output(&["a", "b c", "d\ne"], true, true), b"a\0b c\0d\ne\0");
+        assert_eq!(output(&["a", "b c", "d\ne"], false, true), b"a\0b c\0d\ne");
+        assert_eq!(output(&[], true, true), b"\0");
+        assert_eq!(output(&[], false, true), b"");
+    }
 
-    let mut inner = || -> std::io::Result<()> {
-        let mut first = true;
-        for arg in data[1..].iter().map(|x| x.as_bytes()) {
-            if spaces && !first {
-                buffer.write_all(b" ")?;
-            }
-            first = false;
-
-            if escape {
-                let mut check = false;
-                for &byte in arg {
-                    match byte {
-                        b'\\' if check => {
-                            buffer.write_all(&[byte])?;
-                            check = false;
-                        }
-                        b'\\' => check = true,
-                        b'a' if check => {
-                            buffer.write_all(&[7])?; // bell
-                            check = false;
-                        }
-                        b'b' if check => {
-                            buffer.write_all(&[8])?; // backspace
-                            check = false;
-                        }
-                        b'c' if check => {
-                            return Ok(());
-                        }
-                        b'e' if check => {
-                            buffer.write_all(&[27])?; // escape
-                            check = false;
-                        }
-                        b'f' if check => {
-                            buffer.write_all(&[12])?; // form feed
-                            check = false;
-                        }
-                        b'n' if check => {
-                            buffer.write_all(b"\n")?; // newline
-                            check = false;
-                        }
-                        b'r' if check => {
-                            buffer.write_all(b"\r")?;
-                            check = false;
-                        }
-                        b't' if check => {
-                            buffer.write_all(b"\t")?;
-                            check = false;
-                        }
-                        b'v' if check => {
-                            buffer.write_all(&[11])?; // vertical tab
-                            check = false;
-                        }
-                        _ if check => {
-                            buffer.write_all(&[b'\\', byte])?;
-                            check = false;
-                        }
-                        _ => {
-                            buffer.write_all(&[byte])?;
-                        }
-                    }
-                }
-            } else {
-                buffer.write_all(arg)?;
-            }
-        }
-        if newline {
-            buffer.write_all(&[b'\n'])?;
-        }
-        Ok(())
-    };
+    #[test]
+    fn last_escape_flag_wins() {
+        let escape = |args: &[&str]| {
+            let args: Vec<types::Str> = args.iter().map(|&arg| arg.into()).collect();
+            let (options, data) = parse_args(&args, false);
+            let mut output = Vec::new();
+            write_args(&mut output, &data[1..], &options).unwrap();
+            output
+        };
+        assert_eq!(escape(&["echo", "-e", "a\\tb"]), b"a\tb\n");
+        assert_eq!(escape(&["echo", "-E", "a\\tb"]), b"a\\tb\n");
+        assert_eq!(escape(&["echo", "-e", "-E", "a\\tb"]), b"a\\tb\n");
+        assert_eq!(escape(&["echo", "-eE", "a\\tb"]), b"a\\tb\n");
+        assert_eq!(escape(&["echo", "-Ee", "a\\tb"]), b"a\tb\n");
+        assert_eq!(escape(&["echo", "-nE", "-e", "a\\tb"]), b"a\tb");
+        // Unknown flags are written, without changing the options
+        assert_eq!(escape(&["echo", "-eX", "a\\tb"]), b"-eX a\\tb\n");
+    }
+
+    #[test]
+    fn default_escapes() {
+        let mut shell = Shell::new();
+        let (output, _) = shell.run_in_subshell(r"echo 'a\tb'").unwrap();
+        assert_eq!(output.as_str(), "a\\tb\n");
 
-    inner().and_then(|_| buffer.flush()).into()
+        shell.set_default_echo_escapes(true);
+        let (output, _) = shell.run_in_subshell(r"echo 'a\tb'; echo -E 'a\tb'").unwrap();
+        assert_eq!(output.as_str(), "a\tb\na\\tb\n");
+    }
 }
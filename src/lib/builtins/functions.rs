@@ -2,6 +2,7 @@ use super::Status;
 use crate as ion_shell;
 use crate::{types, Shell};
 use builtins_proc::builtin;
+use itertools::Itertools;
 use std::io::{self, Write};
 
 #[builtin(
@@ -27,3 +28,92 @@ pub fn fn_(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     }
     Status::SUCCESS
 }
+
+#[builtin(
+    desc = "list the defined functions",
+    man = "
+SYNOPSIS
+    functions [-v] [NAMES...]
+
+DESCRIPTION
+    Prints the names of the defined functions, sorted. When NAMES are given, only those functions \
+           are printed, and the status is 1 if any of them is not defined.
+
+OPTIONS
+    -v
+        print the signature of each function instead of its name"
+)]
+pub fn functions(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    let (verbose, names) = match args.get(1).map(AsRef::as_ref) {
+        Some("-v") => (true, &args[2..]),
+        _ => (false, &args[1..]),
+    };
+
+    let mut status = Status::SUCCESS;
+    let functions: Vec<_> = if names.is_empty() {
+        shell
+            .variables()
+            .functions()
+            .map(|(name, function)| (name, function.clone()))
+            .sorted_by(|a, b| a.0.cmp(b.0))
+            .collect()
+    } else {
+        names
+            .iter()
+            .filter_map(|name| {
+                let function = shell.get_func(name);
+                if function.is_none() {
+                    status = Status::error(format!("ion: functions: {}: not defined", name));
+                }
+                function.map(|function| (name, function))
+            })
+            .collect()
+    };
+
+    let stdout = io::stdout();
+    let stdout = &mut stdout.lock();
+    for (name, function) in functions {
+        let _ =
+            if verbose { writeln!(stdout, "{}", function) } else { writeln!(stdout, "{}", name) };
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::lexers::assignments::{KeyBuf, Primitive},
+        shell::flow_control::Function,
+    };
+
+    #[test]
+    fn lists_defined_functions() {
+        let mut shell = Shell::new();
+        shell.execute_command(&b"fn zeta\nend\nfn alpha\nend"[..]).unwrap();
+        let names: Vec<_> =
+            shell.variables().functions().map(|(name, _)| name.as_str()).sorted().collect();
+        assert_eq!(names, ["alpha", "zeta"]);
+
+        assert_eq!(builtin_functions(&args!["functions", "alpha"], &mut shell), Status::SUCCESS);
+        assert!(builtin_functions(&args!["functions", "alpha", "beta"], &mut shell).is_failure());
+    }
+
+    #[test]
+    fn function_signature() {
+        let mut shell = Shell::new();
+        shell
+            .execute_command(&b"fn greet name times:int list:[str] -- say hello\nend"[..])
+            .unwrap();
+        let function = shell.get_func("greet").unwrap();
+        assert_eq!(function.to_string(), "fn greet name times:int list:[str] -- say hello");
+
+        let function = Function::new(
+            None,
+            "plain".into(),
+            vec![KeyBuf { kind: Primitive::Boolean, name: "flag".into() }],
+            Vec::new(),
+        );
+        assert_eq!(function.to_string(), "fn plain flag:bool");
+    }
+}
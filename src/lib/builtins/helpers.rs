@@ -4,28 +4,38 @@ use std::{fmt::Display, rc::Rc};
 /// The exit status of a command
 ///
 /// Provides some helpers for defining builtins like error messages and semantic constants
+///
+/// A status tells apart processes that exited by themselves from processes ended by a signal,
+/// even though both are reported as an exit code, the latter being 128 plus the signal number.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
-pub struct Status(i32);
+pub struct Status {
+    code:     i32,
+    signaled: bool,
+}
 
 impl Status {
     /// Failed to execute a given command (a parsing/expansion error occured)
-    pub const COULD_NOT_EXEC: Self = Self(126);
+    pub const COULD_NOT_EXEC: Self = Self::from_exit_code(126);
     /// In builtins that output bools, indicates negation
-    pub const FALSE: Self = Self(1);
+    pub const FALSE: Self = Self::from_exit_code(1);
     /// The command does not exist
-    pub const NO_SUCH_COMMAND: Self = Self(127);
+    pub const NO_SUCH_COMMAND: Self = Self::from_exit_code(127);
     /// The execution succeeded
-    pub const SUCCESS: Self = Self(0);
+    pub const SUCCESS: Self = Self::from_exit_code(0);
     /// The process was killed
-    pub const TERMINATED: Self = Self(143);
+    pub const TERMINATED: Self = Self::signaled(nix::libc::SIGTERM);
     /// In builtins that outputs bools, indicates that the result is true
-    pub const TRUE: Self = Self(0);
+    pub const TRUE: Self = Self::from_exit_code(0);
+
+    /// The status of a process ended by a signal, whose exit code is 128 plus the signal number
+    pub const fn signaled(signal: i32) -> Self { Self { code: 128 + signal, signaled: true } }
 
     /// Make an exit code out of a signal
-    pub fn from_signal(signal: u8) -> Self { Self(i32::from(128 + signal)) }
+    #[deprecated(note = "use `Status::signaled`, which takes the signal number as an i32")]
+    pub fn from_signal(signal: u8) -> Self { Self::signaled(i32::from(signal)) }
 
     /// From a raw exit code (native commands)
-    pub const fn from_exit_code(code: i32) -> Self { Self(code) }
+    pub const fn from_exit_code(code: i32) -> Self { Self { code, signaled: false } }
 
     /// A generic error occured. Prints an helper text
     pub fn error<T: AsRef<str>>(err: T) -> Self {
@@ -33,7 +43,7 @@ impl Status {
         if !err.is_empty() {
             eprintln!("{}", err);
         }
-        Self(1)
+        Self::FALSE
     }
 
     /// Wrong arguments submitted to the builtin
@@ -42,24 +52,47 @@ impl Status {
         if !err.is_empty() {
             eprintln!("{}", err);
         }
-        Self(2)
+        Self::from_exit_code(2)
     }
 
     /// Indicates if the operation is successful
-    pub const fn is_success(self) -> bool { self.0 == 0 }
+    pub const fn is_success(self) -> bool { self.code == 0 }
 
     /// Indicates if the operation is unsuccessful
-    pub const fn is_failure(self) -> bool { self.0 != 0 }
+    pub const fn is_failure(self) -> bool { self.code != 0 }
+
+    /// Indicates if the process was ended by a signal
+    pub const fn is_signaled(self) -> bool { self.signaled }
+
+    /// The signal which ended the process, if any
+    pub const fn signal(self) -> Option<i32> {
+        if self.signaled {
+            Some(self.code - 128)
+        } else {
+            None
+        }
+    }
+
+    /// The code the process exited with, if it was not ended by a signal
+    pub const fn exit_code(self) -> Option<i32> {
+        if self.signaled {
+            None
+        } else {
+            Some(self.code)
+        }
+    }
 
     /// Convert to a raw OS exit code
-    pub const fn as_os_code(self) -> i32 { self.0 }
+    pub const fn as_os_code(self) -> i32 { self.code }
 
     /// Change true to false and false to true. Looses information
-    pub fn toggle(&mut self) { self.0 = if self.is_success() { 1 } else { 0 }; }
+    pub fn toggle(&mut self) { *self = Self::from(!self.is_success()); }
 }
 
 impl Display for Status {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "{}", self.0) }
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)
+    }
 }
 
 impl From<Status> for Value<Rc<types::Function>> {
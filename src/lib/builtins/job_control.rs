@@ -7,6 +7,16 @@ use crate::{
     types,
 };
 use smallvec::SmallVec;
+use std::io::{self, Write};
+
+/// Parses a job spec, which is a job ID optionally prefixed with `%`, as in `%1`, or `%%` and
+/// `%+` for the job most recently sent to the background.
+fn parse_jobspec(shell: &Shell<'_>, arg: &str) -> Option<usize> {
+    match arg {
+        "%%" | "%+" => shell.previous_job(),
+        _ => arg.strip_prefix('%').unwrap_or(arg).parse::<usize>().ok(),
+    }
+}
 
 /// Disowns given process job IDs, and optionally marks jobs to not receive SIGHUP signals.
 /// The `-a` flag selects all jobs, `-r` selects all running jobs, and `-h` specifies to mark
@@ -27,8 +37,8 @@ pub fn disown(shell: &mut Shell<'_>, args: &[types::Str]) -> Result<(), String>
             "-h" => no_sighup = true,
             "-r" => run_jobs = true,
             _ => {
-                let jobspec =
-                    arg.parse::<usize>().map_err(|_| format!("invalid jobspec: '{}'", arg))?;
+                let jobspec = parse_jobspec(shell, arg)
+                    .ok_or_else(|| format!("invalid jobspec: '{}'", arg))?;
                 collected_jobs.push(jobspec);
             }
         }
@@ -64,13 +74,16 @@ pub fn disown(shell: &mut Shell<'_>, args: &[types::Str]) -> Result<(), String>
     Ok(())
 }
 
-/// Display a list of all jobs running in the background.
-pub fn jobs(shell: &mut Shell<'_>) {
+/// Display a list of all jobs running in the background, with their process ID and state.
+pub fn jobs(shell: &mut Shell<'_>) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
     for (id, process) in shell.background_jobs().iter().enumerate() {
         if process.exists() {
-            eprintln!("[{}] {}", id, process);
+            writeln!(stdout, "[{}] {}", id, process)?;
         }
     }
+    Ok(())
 }
 
 /// Hands control of the foreground process to the specified jobs, recording their exit status.
@@ -99,11 +112,11 @@ pub fn fg(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
         }
     } else {
         for arg in args {
-            match arg.parse::<usize>() {
-                Ok(njob) => {
+            match parse_jobspec(shell, arg) {
+                Some(njob) => {
                     fg_job(shell, njob);
                 }
-                Err(_) => {
+                None => {
                     return Status::error(format!("ion: fg: {} is not a valid job number", arg))
                 }
             }
@@ -135,7 +148,7 @@ pub fn bg(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
         }
     } else {
         for arg in args {
-            if let Ok(njob) = arg.parse::<usize>() {
+            if let Some(njob) = parse_jobspec(shell, arg) {
                 let status = bg_job(shell, njob);
                 if !status.is_success() {
                     return status;
@@ -147,3 +160,30 @@ pub fn bg(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
         Status::SUCCESS
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::signal::{self, Signal};
+
+    #[test]
+    fn job_specs() {
+        let mut shell = Shell::new();
+        assert_eq!(parse_jobspec(&shell, "%%"), None);
+        shell.execute_command(&b"sleep 10 &"[..]).unwrap();
+        let pid = shell.background_jobs()[0].pid();
+
+        assert_eq!(parse_jobspec(&shell, "%0"), Some(0));
+        assert_eq!(parse_jobspec(&shell, "3"), Some(3));
+        assert_eq!(parse_jobspec(&shell, "%%"), Some(0));
+        assert_eq!(parse_jobspec(&shell, "%+"), Some(0));
+        assert_eq!(parse_jobspec(&shell, "%x"), None);
+
+        let process = shell.background_jobs()[0].to_string();
+        assert_eq!(process, format!("{} Running\tsleep 10 &", pid));
+
+        disown(&mut shell, &["%0".into()]).unwrap();
+        assert!(!shell.background_jobs()[0].exists());
+        let _ = signal::kill(pid, Signal::SIGKILL);
+    }
+}
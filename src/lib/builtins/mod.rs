@@ -11,11 +11,15 @@ mod helpers;
 mod is;
 mod job_control;
 mod math;
+mod printf;
 mod random;
+mod read;
+mod readarray;
 mod set;
 mod source;
 mod status;
 mod test;
+mod trap;
 mod variables;
 
 pub use self::{
@@ -23,31 +27,35 @@ pub use self::{
     conditionals::{builtin_contains, builtin_ends_with, builtin_starts_with},
     echo::builtin_echo,
     exists::builtin_exists,
-    functions::builtin_fn_,
+    functions::{builtin_fn_, builtin_functions},
     helpers::Status,
     is::builtin_is,
     man_pages::check_help,
     math::builtin_math,
+    printf::builtin_printf,
+    read::builtin_read,
+    readarray::builtin_readarray,
     set::builtin_set,
     source::builtin_source,
     status::builtin_status,
     test::builtin_test,
+    trap::builtin_trap,
     variables::{builtin_alias, builtin_drop, builtin_unalias},
 };
 use crate as ion_shell;
 use crate::{
-    shell::{Shell, Value},
+    shell::{IonError, Shell, Value},
     types,
 };
 use builtins_proc::builtin;
 use itertools::Itertools;
-use liner::{Completer, Context, Prompt};
+use liner::Completer;
 use mktemp::Temp;
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{BufRead, BufReader},
     path::{Path, PathBuf},
     process::{Command, Stdio},
 };
@@ -100,8 +108,9 @@ fn parse_numeric_arg(arg: &str) -> Option<(bool, usize)> {
 /// );
 /// // >> Hello world!
 pub struct BuiltinMap<'a> {
-    fcts: HashMap<&'static str, BuiltinFunction<'a>>,
-    help: HashMap<&'static str, &'static str>,
+    fcts:       HashMap<&'static str, BuiltinFunction<'a>>,
+    help:       HashMap<&'static str, &'static str>,
+    categories: HashMap<&'static str, &'static str>,
 }
 
 impl<'a> Default for BuiltinMap<'a> {
@@ -124,14 +133,27 @@ impl<'a> Default for BuiltinMap<'a> {
 //     return Status::SUCCESS
 // }
 impl<'a> BuiltinMap<'a> {
+    /// The category of the builtins added without one
+    pub const UNCATEGORIZED: &'static str = "uncategorized";
+
     /// Create a new, blank builtin map
     ///
     /// If you have a hint over the number of builtins, with_capacity is probably better
-    pub fn new() -> Self { BuiltinMap { fcts: HashMap::new(), help: HashMap::new() } }
+    pub fn new() -> Self {
+        BuiltinMap {
+            fcts:       HashMap::new(),
+            help:       HashMap::new(),
+            categories: HashMap::new(),
+        }
+    }
 
     /// Create a new, blank builtin map with a given capacity
     pub fn with_capacity(cap: usize) -> Self {
-        BuiltinMap { fcts: HashMap::with_capacity(cap), help: HashMap::with_capacity(cap) }
+        BuiltinMap {
+            fcts:       HashMap::with_capacity(cap),
+            help:       HashMap::with_capacity(cap),
+            categories: HashMap::new(),
+        }
     }
 
     /// Check if the given builtin exists
@@ -140,9 +162,37 @@ impl<'a> BuiltinMap<'a> {
     /// Get the list of builtins included
     pub fn keys(&self) -> impl Iterator<Item = &str> { self.fcts.keys().copied() }
 
+    /// Iterate over the names and descriptions of the builtins, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        let mut builtins: Vec<_> = self.help.iter().map(|(&name, &help)| (name, help)).collect();
+        builtins.sort_unstable_by_key(|&(name, _)| name);
+        builtins.into_iter()
+    }
+
+    /// Iterate over the categories of builtins, sorted by name, with the names and descriptions
+    /// of their builtins, sorted by name
+    pub fn by_category(&self) -> impl Iterator<Item = (&str, Vec<(&str, &str)>)> {
+        let mut categories = BTreeMap::new();
+        for (name, help) in self.iter() {
+            let category = self.get_category(name).unwrap_or(Self::UNCATEGORIZED);
+            categories.entry(category).or_insert_with(Vec::new).push((name, help));
+        }
+        categories.into_iter()
+    }
+
     /// Get the provided help for a given builtin
     pub fn get_help(&self, func: &str) -> Option<&str> { self.help.get(func).copied() }
 
+    /// Get the category of a given builtin, which is [`BuiltinMap::UNCATEGORIZED`] if it was
+    /// added without one
+    pub fn get_category(&self, func: &str) -> Option<&str> {
+        if self.contains(func) {
+            Some(self.categories.get(func).copied().unwrap_or(Self::UNCATEGORIZED))
+        } else {
+            None
+        }
+    }
+
     /// Get the function of a given builtin
     pub fn get(&self, func: &str) -> Option<BuiltinFunction<'a>> { self.fcts.get(func).cloned() }
 
@@ -155,18 +205,49 @@ impl<'a> BuiltinMap<'a> {
     ) -> &mut Self {
         self.fcts.insert(name, func);
         self.help.insert(name, help);
+        self.categories.remove(name);
+        self
+    }
+
+    /// Add a new builtin, returning the function of the builtin of the same name that it
+    /// replaces, if any, so that the new builtin can wrap it and it can be restored afterwards.
+    ///
+    /// Unlike [`BuiltinMap::add`], the category of the replaced builtin is kept.
+    pub fn replace(
+        &mut self,
+        name: &'static str,
+        func: BuiltinFunction<'a>,
+        help: &'static str,
+    ) -> Option<BuiltinFunction<'a>> {
+        self.help.insert(name, help);
+        self.fcts.insert(name, func)
+    }
+
+    /// Add a new builtin in a category, under which listings can group it
+    pub fn add_in_category(
+        &mut self,
+        category: &'static str,
+        name: &'static str,
+        func: BuiltinFunction<'a>,
+        help: &'static str,
+    ) -> &mut Self {
+        self.add(name, func, help);
+        self.categories.insert(name, category);
         self
     }
 
     /// Create and control variables
     ///
-    /// Contains `fn`, `alias`, `unalias`, `drop`, `read`
+    /// Contains `fn`, `functions`, `alias`, `unalias`, `drop`, `read`, `readarray`, `mapfile`
     pub fn with_variables(&mut self) -> &mut Self {
         self.add("fn", &builtin_fn_, "Print list of functions")
+            .add("functions", &builtin_functions, "List the defined functions")
             .add("alias", &builtin_alias, "View, set or unset aliases")
             .add("unalias", &builtin_unalias, "Delete an alias")
             .add("drop", &builtin_drop, "Delete a variable")
             .add("read", &builtin_read, "Read some variables\n    read <variable>")
+            .add("readarray", &builtin_readarray, "Read the lines of the input into an array")
+            .add("mapfile", &builtin_readarray, "Read the lines of the input into an array")
     }
 
     /// Control subrpocesses states
@@ -230,14 +311,20 @@ impl<'a> BuiltinMap<'a> {
 
     /// Basic utilities for any ion embedded library
     ///
-    /// Contains `help`, `source`, `status`, `echo`, `type`, `which`
+    /// Contains `help`, `source`, `status`, `echo`, `printf`, `type`, `which`, `trap`
     pub fn with_basic(&mut self) -> &mut Self {
         self.add("help", &builtin_help, HELP_DESC)
             .add("source", &builtin_source, SOURCE_DESC)
             .add("status", &builtin_status, "Evaluates the current runtime status")
             .add("echo", &builtin_echo, "Display a line of text")
+            .add("printf", &builtin_printf, "Format and print arguments")
             .add("which", &builtin_which, "indicates what would be called for a given command")
             .add("type", &builtin_which, "indicates what would be called for a given command")
+            .add(
+                "trap",
+                &builtin_trap,
+                "Run some code when the shell exits, before commands or when they fail",
+            )
     }
 
     /// Utilities that may be a security risk. Not included by default
@@ -379,6 +466,7 @@ pub fn cd(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
 
     match err {
         Ok(()) => {
+            shell.cwd_changed();
             if let Some(Value::Function(function)) = shell.variables().get("CD_CHANGE").cloned() {
                 let _ = shell.execute_function(&function, &["ion"]);
             }
@@ -630,41 +718,6 @@ impl Completer for EmptyCompleter {
     fn completions(&mut self, _start: &str) -> Vec<String> { Vec::new() }
 }
 
-#[builtin(
-    desc = "read a line of input into some variables",
-    man = "
-SYNOPSIS
-    read VARIABLES...
-
-DESCRIPTION
-    For each variable reads from standard input and stores the results in the variable.
-"
-)]
-pub fn read(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
-    if atty::is(atty::Stream::Stdin) {
-        let mut con = Context::new();
-        for arg in args.iter().skip(1) {
-            match con.read_line(Prompt::from(format!("{}=", arg.trim())), None, &mut EmptyCompleter)
-            {
-                Ok(buffer) => {
-                    shell.variables_mut().set(arg.as_ref(), buffer.trim());
-                }
-                Err(_) => return Status::FALSE,
-            }
-        }
-    } else {
-        let stdin = io::stdin();
-        let handle = stdin.lock();
-        let mut lines = handle.lines();
-        for arg in args.iter().skip(1) {
-            if let Some(Ok(line)) = lines.next() {
-                shell.variables_mut().set(arg.as_ref(), line.trim());
-            }
-        }
-    }
-    Status::SUCCESS
-}
-
 #[builtin(
     desc = "evaluates the specified commands",
     man = "
@@ -676,23 +729,35 @@ DESCRIPTION
     all arguments are joined using a space as a separator."
 )]
 pub fn eval(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
-    shell.execute_command(args[1..].join(" ").as_bytes()).unwrap_or_else(|_| {
-        Status::error("ion: supplied eval expression was not terminated".to_string())
-    })
+    match shell.recurse(|shell| shell.execute_command(args[1..].join(" ").as_bytes())) {
+        Ok(status) => status,
+        Err(why @ IonError::RecursionLimit(_)) => Status::error(format!("ion: eval: {}", why)),
+        Err(_) => Status::error("ion: supplied eval expression was not terminated".to_string()),
+    }
 }
 
 #[builtin(
     desc = "generate a random number",
     man = "
 SYNOPSIS
-    random
-    random START END
+    random [--seed SEED]
+    random [--seed SEED] START END
+    random [--seed SEED] --shuffle ARGS...
+    random [--seed SEED] --choice ARGS...
 
 DESCRIPTION
     random generates a pseudo-random integer. IT IS NOT SECURE.
     The range depends on what arguments you pass. If no arguments are given the range is [0, \
            32767].
-    If two arguments are given the range is [START, END]."
+    If two arguments are given the range is [START, END].
+
+OPTIONS
+    --seed SEED
+        seed the generator with the number SEED, so that the same output is generated each time
+    --shuffle
+        print the ARGS in a random order instead
+    --choice
+        print one of the ARGS, chosen uniformly, instead"
 )]
 pub fn random(args: &[types::Str], _: &mut Shell<'_>) -> Status {
     match random::random(&args[1..]) {
@@ -749,21 +814,23 @@ SYNOPSIS
     jobs
 
 DESCRIPTION
-    Prints a list of all jobs running in the background."
+    Prints a list of all jobs running in the background, with their job ID, process ID and state."
 )]
 pub fn jobs(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
-    job_control::jobs(shell);
-    Status::SUCCESS
+    job_control::jobs(shell).into()
 }
 
 #[builtin(
     desc = "sends jobs to background",
     man = "
 SYNOPSIS
-    bg PID
+    bg [JOB...]
 
 DESCRIPTION
-    bg sends the job to the background resuming it if it has stopped."
+    bg sends the job to the background resuming it if it has stopped.
+
+    Jobs are given by their job ID, which may be prefixed with %, as in %1. %% and %+ stand for \
+           the job most recently sent to the background, which is the default."
 )]
 pub fn bg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     job_control::bg(shell, &args[1..])
@@ -773,10 +840,13 @@ pub fn bg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "bring job to the foreground",
     man = "
 SYNOPSIS
-    fg PID
+    fg [JOB...]
 
 DESCRIPTION
-    fg brings the specified job to foreground resuming it if it has stopped."
+    fg brings the specified job to foreground resuming it if it has stopped.
+
+    Jobs are given by their job ID, which may be prefixed with %, as in %1. %% and %+ stand for \
+           the job most recently sent to the background, which is the default."
 )]
 pub fn fg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     job_control::fg(shell, &args[1..])
@@ -786,7 +856,7 @@ pub fn fg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "disown processes",
     man = "
 SYNOPSIS
-    disown [ --help | -r | -h | -a ][PID...]
+    disown [ --help | -r | -h | -a ][JOB...]
 
 DESCRIPTION
     Disowning a process removes that process from the shell's background process table.
@@ -811,7 +881,8 @@ SYNOPSIS
     help [BUILTIN]
 
 DESCRIPTION
-    Get the short description for BUILTIN. If no argument is provided, list all the builtins"
+    Get the short description for BUILTIN. If no argument is provided, list all the builtins, \
+           grouped by category if some were given one"
 )]
 pub fn help(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     if let Some(command) = args.get(1) {
@@ -821,7 +892,17 @@ pub fn help(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
             println!("Command helper not found [run 'help']...");
         }
     } else {
-        println!("{}", shell.builtins().keys().sorted().format("\n"));
+        let categories: Vec<_> = shell.builtins().by_category().collect();
+        if let [(_, builtins)] = categories.as_slice() {
+            println!("{}", builtins.iter().map(|(name, _)| name).format("\n"));
+        } else {
+            for (category, builtins) in categories {
+                println!("{}:", category);
+                for (name, _) in builtins {
+                    println!("    {}", name);
+                }
+            }
+        }
     }
     Status::SUCCESS
 }
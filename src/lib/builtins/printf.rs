@@ -0,0 +1,343 @@
+use super::Status;
+use crate as ion_shell;
+use crate::{shell::variables::Variables, types, Shell};
+use builtins_proc::builtin;
+use std::{
+    io::{self, Write},
+    iter::Peekable,
+    str::Chars,
+};
+
+/// The flags, width and precision of a conversion, such as the `-10` of `%-10s`
+#[derive(Debug, Default)]
+struct Spec {
+    /// Pad on the right instead of the left
+    left:      bool,
+    /// Pad numbers with zeros instead of spaces
+    zero:      bool,
+    /// Always write the sign of signed numbers
+    plus:      bool,
+    /// Write a space before positive signed numbers
+    space:     bool,
+    /// Prefix hexadecimal numbers with `0x` and octal numbers with `0`
+    alternate: bool,
+    /// The minimum width of the converted argument
+    width:     Option<usize>,
+    /// The maximum length of strings, the minimum digits of integers or the decimals of floats
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Parses the flags, width and precision that follow a `%`, up to the conversion character.
+    fn parse(chars: &mut Peekable<Chars<'_>>) -> Result<(Self, char), String> {
+        let mut spec = Self::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left = true,
+                '0' => spec.zero = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                _ => break,
+            }
+            chars.next();
+        }
+
+        spec.width = Self::number(chars);
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            spec.precision = Some(Self::number(chars).unwrap_or(0));
+        }
+
+        let conversion = chars.next().ok_or_else(|| "missing conversion after '%'".to_string())?;
+        Ok((spec, conversion))
+    }
+
+    fn number(chars: &mut Peekable<Chars<'_>>) -> Option<usize> {
+        let mut number = None;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            number = Some(number.unwrap_or(0) * 10 + digit as usize);
+            chars.next();
+        }
+        number
+    }
+
+    /// The sign written before a signed number
+    const fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    /// Writes `prefix` and `body` padded to the width. Only numbers are padded with zeros, which
+    /// go between the prefix and the digits.
+    fn pad(&self, output: &mut String, prefix: &str, body: &str, numeric: bool) {
+        let len = prefix.chars().count() + body.chars().count();
+        let fill = self.width.unwrap_or(0).saturating_sub(len);
+        if self.left {
+            output.push_str(prefix);
+            output.push_str(body);
+            output.push_str(&" ".repeat(fill));
+        } else if self.zero && numeric {
+            output.push_str(prefix);
+            output.push_str(&"0".repeat(fill));
+            output.push_str(body);
+        } else {
+            output.push_str(&" ".repeat(fill));
+            output.push_str(prefix);
+            output.push_str(body);
+        }
+    }
+
+    /// Writes `arg` with the `conversion` to the output. A missing argument is taken as an
+    /// empty string or zero.
+    fn convert(
+        &self,
+        output: &mut String,
+        conversion: char,
+        arg: Option<&str>,
+    ) -> Result<(), String> {
+        match conversion {
+            's' => {
+                let arg = arg.unwrap_or("");
+                match self.precision {
+                    Some(max) => {
+                        self.pad(output, "", &arg.chars().take(max).collect::<String>(), false)
+                    }
+                    None => self.pad(output, "", arg, false),
+                }
+            }
+            'd' | 'i' | 'x' | 'X' | 'o' => {
+                let number = match arg {
+                    Some(arg) => arg
+                        .trim()
+                        .parse::<i64>()
+                        .map_err(|_| format!("invalid number: '{}'", arg))?,
+                    None => 0,
+                };
+                let (prefix, digits) = match conversion {
+                    'x' => (
+                        if self.alternate && number != 0 { "0x" } else { "" },
+                        format!("{:x}", number),
+                    ),
+                    'X' => (
+                        if self.alternate && number != 0 { "0X" } else { "" },
+                        format!("{:X}", number),
+                    ),
+                    'o' => (
+                        if self.alternate && number != 0 { "0" } else { "" },
+                        format!("{:o}", number),
+                    ),
+                    _ => (self.sign(number < 0), number.unsigned_abs().to_string()),
+                };
+                // With a precision, the digits are padded with zeros rather than the width
+                let digits = match self.precision {
+                    Some(min) if digits.len() < min => format!("{:0>width$}", digits, width = min),
+                    _ => digits,
+                };
+                self.pad(output, prefix, &digits, self.precision.is_none());
+            }
+            'f' | 'F' => {
+                let number = match arg {
+                    Some(arg) => arg
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid number: '{}'", arg))?,
+                    None => 0.,
+                };
+                let digits = format!("{:.*}", self.precision.unwrap_or(6), number.abs());
+                self.pad(
+                    output,
+                    self.sign(number.is_sign_negative() && number != 0.),
+                    &digits,
+                    number.is_finite(),
+                );
+            }
+            _ => return Err(format!("unknown conversion: %{}", conversion)),
+        }
+        Ok(())
+    }
+}
+
+/// Pushes the character of the escape sequence that started with a backslash. Unknown escapes
+/// are kept as they are.
+fn escape(output: &mut String, chars: &mut Peekable<Chars<'_>>) {
+    let character = match chars.next() {
+        Some('\\') => '\\',
+        Some('a') => '\u{7}',
+        Some('b') => '\u{8}',
+        Some('e') => '\u{1b}',
+        Some('f') => '\u{c}',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('v') => '\u{b}',
+        Some(other) => {
+            output.push('\\');
+            other
+        }
+        None => '\\',
+    };
+    output.push(character);
+}
+
+/// Formats the arguments with the given format, reusing the format as long as arguments remain
+fn format(format: &str, args: &[types::Str]) -> Result<String, String> {
+    let mut output = String::new();
+    let mut args = args.iter();
+
+    loop {
+        let mut consumed = false;
+        let mut chars = format.chars().peekable();
+        while let Some(character) = chars.next() {
+            match character {
+                '\\' => escape(&mut output, &mut chars),
+                '%' if chars.peek() == Some(&'%') => {
+                    chars.next();
+                    output.push('%');
+                }
+                '%' => {
+                    let (spec, conversion) = Spec::parse(&mut chars)?;
+                    let arg = args.next();
+                    consumed |= arg.is_some();
+                    spec.convert(&mut output, conversion, arg.map(AsRef::as_ref))?;
+                }
+                _ => output.push(character),
+            }
+        }
+
+        if !consumed || args.len() == 0 {
+            return Ok(output);
+        }
+    }
+}
+
+#[builtin(
+    desc = "format and print arguments",
+    man = "
+SYNOPSIS
+    printf [-v VARIABLE] FORMAT [ARGUMENTS...]
+
+DESCRIPTION
+    Print the ARGUMENTS according to FORMAT. The format is reused as long as arguments remain.
+    The escapes \\n, \\t, \\r, \\a, \\b, \\e, \\f, \\v and \\\\ are interpreted in FORMAT.
+
+OPTIONS
+    -v VARIABLE
+        store the output in VARIABLE instead of printing it
+
+CONVERSIONS
+    %s      the argument as a string
+    %d, %i  the argument as a decimal integer
+    %x, %X  the argument as a hexadecimal integer, in lower or upper case
+    %o      the argument as an octal integer
+    %f      the argument as a floating point number, with 6 decimals by default
+    %%      a literal percent sign
+
+    Each conversion may be written as %[FLAGS][WIDTH][.PRECISION]CONVERSION. The flags are `-` to \
+           pad on the right, `0` to pad numbers with zeros, `+` or ` ` to write the sign of \
+           positive numbers and `#` to prefix hexadecimal numbers with 0x and octal numbers with \
+           0. The precision is the maximum length of a string, the minimum number of digits of an \
+           integer or the number of decimals of a float."
+)]
+pub fn printf(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    let (variable, args) = match args.get(1).map(AsRef::as_ref) {
+        Some("-v") => match args.get(2) {
+            Some(name) if Variables::is_valid_name(name) => (Some(name), &args[3..]),
+            Some(name) => {
+                return Status::bad_argument(format!(
+                    "ion: printf: invalid variable name: {}",
+                    name
+                ))
+            }
+            None => return Status::bad_argument("ion: printf: -v requires a variable name"),
+        },
+        _ => (None, &args[1..]),
+    };
+
+    let output = match args.split_first() {
+        Some((fmt, args)) => match format(fmt, args) {
+            Ok(output) => output,
+            Err(why) => return Status::error(format!("ion: printf: {}", why)),
+        },
+        None => return Status::bad_argument("ion: printf: missing format"),
+    };
+
+    match variable {
+        Some(name) => {
+            shell.variables_mut().set(name, output);
+            Status::SUCCESS
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            stdout.write_all(output.as_bytes()).and_then(|_| stdout.flush()).into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycles_format() {
+        assert_eq!(format("%s=%d\n", &args!["a", "1", "b", "2"]), Ok("a=1\nb=2\n".into()));
+        assert_eq!(format("%s %s,", &args!["a", "b", "c"]), Ok("a b,c ,".into()));
+        assert_eq!(format("100%%", &args![]), Ok("100%".into()));
+        assert!(format("%d", &args!["x"]).is_err());
+        assert!(format("%y", &args!["x"]).is_err());
+    }
+
+    #[test]
+    fn widths_and_flags() {
+        assert_eq!(
+            format("%05d|%-4s|%4s|", &args!["42", "ab", "cd"]),
+            Ok("00042|ab  |  cd|".into())
+        );
+        assert_eq!(
+            format("%+d % d %05d %.3d", &args!["7", "7", "-42", "-7"]),
+            Ok("+7  7 -0042 -007".into())
+        );
+        assert_eq!(
+            format("%x %X %#x %o %#o", &args!["255", "255", "255", "8", "8"]),
+            Ok("ff FF 0xff 10 010".into())
+        );
+        assert_eq!(
+            format("%.2f|%8.3f|%-7.1f|%f", &args!["3.14159", "-2.5", "1", "0.5"]),
+            Ok("3.14|  -2.500|1.0    |0.500000".into())
+        );
+        assert_eq!(format("%.2s", &args!["éclair"]), Ok("éc".into()));
+        assert_eq!(format("%d", &args![]), Ok("0".into()));
+        assert!(format("%5", &args!["1"]).is_err());
+        assert!(format("%f", &args!["pi"]).is_err());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(format("a\\tb\\n", &args![]), Ok("a\tb\n".into()));
+        assert_eq!(format("\\\\ \\q\\", &args![]), Ok("\\ \\q\\".into()));
+        // Escapes in the arguments are left alone
+        assert_eq!(format("%s", &args!["\\n"]), Ok("\\n".into()));
+    }
+
+    #[test]
+    fn store_in_variable() {
+        let mut shell = Shell::new();
+        let status = builtin_printf(
+            &args!["printf", "-v", "out", "[%s:%d]", "a", "1", "b", "2"],
+            &mut shell,
+        );
+        assert_eq!(status, Status::SUCCESS);
+        assert_eq!(shell.variables().get_str("out").unwrap().as_str(), "[a:1][b:2]");
+
+        let status = builtin_printf(&args!["printf", "-v", "1out", "%s", "a"], &mut shell);
+        assert!(status.is_failure());
+    }
+}
@@ -1,31 +1,62 @@
 use crate::types;
 use itertools::Itertools;
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    fmt::Display,
+    io::{self, Write},
+};
 
 const INVALID: &str = "Invalid argument for random";
 
-fn rand_list(args: &[types::Str]) -> Result<(), types::Str> {
+/// Writes `value` on a line of the standard output, which the shell may have redirected.
+fn print(value: impl Display) -> Result<(), types::Str> {
+    writeln!(io::stdout(), "{}", value).map_err(|why| why.to_string().into())
+}
+
+fn rand_list(args: &[types::Str], rng: &mut StdRng) -> Result<(), types::Str> {
     let num_random = args[0].parse::<usize>().map_err::<types::Str, _>(|_| INVALID.into())?;
     let mut output = Vec::with_capacity(num_random);
     while output.len() < num_random {
         for _ in 0..(num_random - output.len()) {
-            let rand_num = thread_rng().gen_range(1, args.len());
+            let rand_num = rng.gen_range(1, args.len());
             output.push(&*args[rand_num]);
         }
         output.dedup();
     }
-    println!("{}", output.iter().format(" "));
-    Ok(())
+    print(output.iter().format(" "))
 }
 
 pub fn random(args: &[types::Str]) -> Result<(), types::Str> {
+    let (mut rng, args) = match args {
+        [flag, seed, args @ ..] if flag == "--seed" => {
+            let seed = seed.parse().map_err::<types::Str, _>(|_| INVALID.into())?;
+            (StdRng::seed_from_u64(seed), args)
+        }
+        [flag] if flag == "--seed" => return Err("--seed requires a number".into()),
+        _ => (StdRng::from_entropy(), args),
+    };
+
+    match args.first().map(types::Str::as_str) {
+        Some("--shuffle") => {
+            let mut items: Vec<&str> = args[1..].iter().map(types::Str::as_str).collect();
+            items.shuffle(&mut rng);
+            print(items.iter().format(" "))
+        }
+        Some("--choice") => args[1..]
+            .choose(&mut rng)
+            .map_or_else(|| Err("--choice requires at least one argument".into()), print),
+        _ => rand_numbers(args, &mut rng),
+    }
+}
+
+fn rand_numbers(args: &[types::Str], rng: &mut StdRng) -> Result<(), types::Str> {
     match args.len() {
         0 => {
-            let rand_num = thread_rng().gen_range(0, 32767);
-            println!("{}", rand_num);
+            let rand_num = rng.gen_range(0, 32767);
+            print(rand_num)?;
         }
         1 => {
-            eprintln!("Ion Shell does not currently support changing the seed");
+            eprintln!("ion: random: the seed is changed with --seed SEED");
         }
         2 => {
             let start: u64 = args[0].parse().map_err::<types::Str, _>(|_| INVALID.into())?;
@@ -33,14 +64,14 @@ pub fn random(args: &[types::Str]) -> Result<(), types::Str> {
             if end <= start {
                 return Err("END must be greater than START".into());
             }
-            let rand_num = thread_rng().gen_range(start, end);
-            println!("{}", rand_num);
+            let rand_num = rng.gen_range(start, end);
+            print(rand_num)?;
         }
         3 => {
             let start: u64 = args[0].parse().map_err::<types::Str, _>(|_| INVALID.into())?;
             let step = match args[1].parse::<u64>() {
                 Ok(v) => v,
-                Err(_) => return rand_list(args),
+                Err(_) => return rand_list(args, rng),
             };
             match args[2].parse::<u64>() {
                 Ok(end) => {
@@ -51,14 +82,40 @@ pub fn random(args: &[types::Str]) -> Result<(), types::Str> {
                     if start / step >= end {
                         end += 1;
                     }
-                    let rand_num = thread_rng().gen_range(start / step, end);
-                    println!("{}", rand_num * step);
+                    let rand_num = rng.gen_range(start / step, end);
+                    print(rand_num * step)?;
                 }
-                Err(_) => return rand_list(args),
+                Err(_) => return rand_list(args, rng),
             };
         }
-        _ => return rand_list(args),
+        _ => return rand_list(args, rng),
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Shell;
+
+    #[test]
+    fn shuffle_and_choice() {
+        let mut shell = Shell::new();
+        let mut output = |code: &str| shell.run_in_subshell(code).unwrap().0;
+
+        let shuffled = output("random --seed 7 --shuffle a b c d e");
+        let mut items: Vec<_> = shuffled.trim_end().split(' ').collect();
+        items.sort_unstable();
+        assert_eq!(items, ["a", "b", "c", "d", "e"]);
+        // The same seed shuffles the same way
+        assert_eq!(output("random --seed 7 --shuffle a b c d e"), shuffled);
+
+        let chosen = output("random --seed 3 --choice a b c");
+        assert!(["a\n", "b\n", "c\n"].contains(&chosen.as_str()));
+        assert_eq!(output("random --seed 3 --choice a b c"), chosen);
+        assert_eq!(output("random --seed 3 10 20"), output("random --seed 3 10 20"));
+
+        assert_eq!(output("random --choice; echo $?").as_str(), "1\n");
+        assert_eq!(output("random --shuffle; echo $?").as_str(), "\n0\n");
+    }
+}
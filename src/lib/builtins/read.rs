@@ -0,0 +1,308 @@
+use super::{EmptyCompleter, Status};
+use crate as ion_shell;
+use crate::{
+    expansion::{split_fields, Expander},
+    shell::sys::TerminalMode,
+    types, Shell,
+};
+use builtins_proc::builtin;
+use liner::{Context, Prompt};
+use nix::{
+    errno::Errno,
+    libc::{c_int, STDIN_FILENO},
+    poll::{poll, PollFd, PollFlags},
+    unistd,
+};
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+    os::unix::io::RawFd,
+    str,
+    time::{Duration, Instant},
+};
+
+/// The status returned when no input was given before the timeout, as in bash
+const TIMED_OUT: Status = Status::from_exit_code(142);
+
+/// How the input is read
+#[derive(Debug, Default)]
+struct ReadOptions<'a> {
+    /// Written before reading
+    prompt:  Option<&'a str>,
+    /// Fail if the input could not be read in time
+    timeout: Option<Duration>,
+    /// Read this many characters for each variable instead of whole lines
+    chars:   Option<usize>,
+    /// Do not echo the input of a terminal
+    silent:  bool,
+}
+
+/// Reads a line from `input` one byte at a time, so that nothing past the newline is consumed.
+/// With `chars`, the read also stops once that many characters were read.
+///
+/// Fails with `TimedOut` if the line could not be read before `deadline`.
+fn read_line(
+    input: RawFd,
+    deadline: Option<Instant>,
+    chars: Option<usize>,
+) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let mut byte = [0; 1];
+    loop {
+        if let Some(chars) = chars {
+            if matches!(str::from_utf8(&line), Ok(line) if line.chars().count() >= chars) {
+                return Ok(Some(String::from_utf8_lossy(&line).into()));
+            }
+        }
+
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now()).as_millis();
+            let mut fds = [PollFd::new(input, PollFlags::POLLIN)];
+            if poll(&mut fds, c_int::try_from(remaining).unwrap_or(c_int::MAX))? == 0 {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+        }
+
+        match unistd::read(input, &mut byte) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => return Ok(Some(String::from_utf8_lossy(&line).into())),
+            Ok(_) => line.push(byte[0]),
+            Err(Errno::EINTR) => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(if line.is_empty() { None } else { Some(String::from_utf8_lossy(&line).into()) })
+}
+
+/// Reads up to `count` values from `input`, after writing the prompt to `prompt_out`.
+///
+/// Stops early at the end of the input, and fails if the values are not all read before the
+/// timeout. The mode of a terminal is restored before returning, even on failure.
+fn read_values<W: Write>(
+    input: RawFd,
+    prompt_out: &mut W,
+    options: &ReadOptions<'_>,
+    count: usize,
+) -> io::Result<Vec<String>> {
+    if let Some(prompt) = options.prompt {
+        prompt_out.write_all(prompt.as_bytes())?;
+        prompt_out.flush()?;
+    }
+
+    let _mode = if (options.chars.is_some() || options.silent) && unistd::isatty(input)? {
+        Some(TerminalMode::new(input, options.chars.is_none(), !options.silent)?)
+    } else {
+        None
+    };
+
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        match read_line(input, deadline, options.chars)? {
+            Some(value) => values.push(value),
+            None => break,
+        }
+    }
+    Ok(values)
+}
+
+/// Splits `line` into up to `count` fields on the characters of `ifs`, as `split_fields` does,
+/// except that the last field takes the rest of the line.
+fn split_line<'a>(line: &'a str, ifs: &'a str, count: usize) -> Vec<&'a str> {
+    let mut fields = Vec::with_capacity(count);
+    let mut rest = line;
+    while fields.len() + 1 < count {
+        match split_fields(rest, Some(ifs)).next() {
+            Some(field) => {
+                let end = field.as_ptr() as usize - rest.as_ptr() as usize + field.len();
+                fields.push(field);
+                rest = &rest[end..];
+            }
+            None => break,
+        }
+    }
+
+    let rest = rest.trim_start_matches(|c| ifs.contains(c));
+    if count > fields.len() && !rest.is_empty() {
+        fields.push(rest);
+    }
+    fields
+}
+
+#[builtin(
+    desc = "read a line of input into some variables",
+    man = "
+SYNOPSIS
+    read [-p PROMPT] [-t SECONDS] [-n COUNT] [-s] VARIABLES...
+
+DESCRIPTION
+    For each variable reads from standard input and stores the results in the variable.
+
+    When the IFS variable is set, a single line is read instead, and split into a field for each \
+           variable on any of the characters of IFS. The last variable gets the rest of the line, \
+           and the variables left without a field are set to an empty string. An empty IFS reads \
+           the whole line into the first variable.
+
+OPTIONS
+    -p PROMPT
+        print PROMPT to the standard error before reading
+    -t SECONDS
+        fail with the status 142 if the input is not read within SECONDS, leaving the variables \
+           unset
+    -n COUNT
+        read COUNT characters for each variable instead of a line, without waiting for a newline
+    -s
+        do not echo the input coming from a terminal"
+)]
+pub fn read(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    let mut options = ReadOptions::default();
+    let mut args = args[1..].iter();
+    let mut names = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" => match args.next() {
+                Some(text) => options.prompt = Some(text.as_str()),
+                None => return Status::bad_argument("ion: read: -p requires a prompt"),
+            },
+            "-t" => match args.next().map(|secs| secs.parse::<f64>()) {
+                Some(Ok(secs)) if secs >= 0. => {
+                    options.timeout = Some(Duration::from_secs_f64(secs))
+                }
+                _ => return Status::bad_argument("ion: read: -t requires a number of seconds"),
+            },
+            "-n" => match args.next().map(|count| count.parse::<usize>()) {
+                Some(Ok(count)) => options.chars = Some(count),
+                _ => return Status::bad_argument("ion: read: -n requires a number of characters"),
+            },
+            "-s" => options.silent = true,
+            _ => {
+                names.push(arg);
+                names.extend(args.by_ref());
+            }
+        }
+    }
+
+    let ifs = shell.field_separators();
+    // With IFS, the fields of a single line are read into the variables
+    let count = if ifs.is_some() { names.len().min(1) } else { names.len() };
+
+    let line_editing = options.timeout.is_none() && options.chars.is_none() && !options.silent;
+    let values = if line_editing && atty::is(atty::Stream::Stdin) {
+        let mut con = Context::new();
+        let mut values = Vec::with_capacity(count);
+        for name in names.iter().take(count) {
+            let prompt = options.prompt.map_or_else(|| format!("{}=", name.trim()), Into::into);
+            match con.read_line(Prompt::from(prompt), None, &mut EmptyCompleter) {
+                Ok(buffer) => values.push(buffer),
+                Err(_) => return Status::FALSE,
+            }
+        }
+        values
+    } else {
+        let stderr = io::stderr();
+        match read_values(STDIN_FILENO, &mut stderr.lock(), &options, count) {
+            Ok(values) => values,
+            Err(ref why) if why.kind() == io::ErrorKind::TimedOut => return TIMED_OUT,
+            Err(why) => return Status::error(format!("ion: read: {}", why)),
+        }
+    };
+
+    match (ifs, values.first()) {
+        (Some(ifs), Some(line)) => {
+            let fields = split_line(line, &ifs, names.len());
+            for (i, name) in names.into_iter().enumerate() {
+                shell.variables_mut().set(name.as_ref(), fields.get(i).copied().unwrap_or(""));
+            }
+        }
+        (Some(_), None) => (),
+        (None, _) => {
+            for (name, value) in names.into_iter().zip(values) {
+                shell.variables_mut().set(name.as_ref(), value.trim());
+            }
+        }
+    }
+    Status::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::{close, pipe, write};
+
+    #[test]
+    fn reads_lines_with_prompt() {
+        let (reader, writer) = pipe().unwrap();
+        write(writer, b"one\ntwo\nthree\n").unwrap();
+
+        let mut prompt = Vec::new();
+        let options = ReadOptions { prompt: Some("> "), ..ReadOptions::default() };
+        let lines = read_values(reader, &mut prompt, &options, 2).unwrap();
+        assert_eq!(lines, vec!["one", "two"]);
+        assert_eq!(prompt, b"> ");
+
+        // The remaining input is left unread
+        close(writer).unwrap();
+        let lines = read_values(reader, &mut Vec::new(), &ReadOptions::default(), 2).unwrap();
+        assert_eq!(lines, vec!["three"]);
+        close(reader).unwrap();
+    }
+
+    #[test]
+    fn times_out_without_input() {
+        let (reader, writer) = pipe().unwrap();
+        let options =
+            ReadOptions { timeout: Some(Duration::from_millis(50)), ..ReadOptions::default() };
+
+        let err = read_values(reader, &mut Vec::new(), &options, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // A partial line does not count as input either
+        write(writer, b"partial").unwrap();
+        let err = read_values(reader, &mut Vec::new(), &options, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        close(reader).unwrap();
+        close(writer).unwrap();
+    }
+
+    #[test]
+    fn reads_fixed_number_of_characters() {
+        let (reader, writer) = pipe().unwrap();
+        write(writer, "yéno\nabc".as_bytes()).unwrap();
+        close(writer).unwrap();
+
+        let options = ReadOptions { chars: Some(2), silent: true, ..ReadOptions::default() };
+        let values = read_values(reader, &mut Vec::new(), &options, 4).unwrap();
+        // A newline still ends the input early
+        assert_eq!(values, vec!["yé", "no", "", "ab"]);
+        close(reader).unwrap();
+    }
+
+    #[test]
+    fn splits_lines_on_ifs() {
+        assert_eq!(split_line("a:b:c", ":", 3), ["a", "b", "c"]);
+        assert_eq!(split_line("a::b:c:d", ":", 2), ["a", "b:c:d"]);
+        assert_eq!(split_line(":a:b", ":", 4), ["a", "b"]);
+        assert_eq!(split_line("  one two  three ", " ", 2), ["one", "two  three "]);
+        // An empty IFS keeps the line whole
+        assert_eq!(split_line("a:b c", "", 3), ["a:b c"]);
+        assert!(split_line("", ":", 2).is_empty());
+    }
+
+    #[test]
+    fn reads_fields_with_ifs() {
+        let mut shell = Shell::new();
+        let mut output = |code: &str| shell.run_in_subshell(code).unwrap().0;
+        assert_eq!(
+            output("let IFS = ':'; read x y z <<< 'a:b:c:d'; echo $x/$y/$z").as_str(),
+            "a/b/c:d\n"
+        );
+        assert_eq!(
+            output("let IFS = ''; read x y <<< 'a:b c'; echo \"$x/$y\"").as_str(),
+            "a:b c/\n"
+        );
+        // Without IFS, each variable reads a line
+        assert_eq!(output("read x y <<< $unescape('a b\\nc'); echo $x/$y").as_str(), "a b/c\n");
+    }
+}
@@ -0,0 +1,143 @@
+use super::Status;
+use crate as ion_shell;
+use crate::{
+    shell::{variables::Variables, Value},
+    types, Shell,
+};
+use builtins_proc::builtin;
+use nix::{errno::Errno, libc::STDIN_FILENO, unistd};
+use std::{io, os::unix::io::RawFd};
+
+/// Reads the elements separated by `delimiter` from `input`, up to `count` of them if given.
+///
+/// The input is read one byte at a time, so that nothing past the last element is consumed.
+fn read_elements(
+    input: RawFd,
+    delimiter: &[u8],
+    count: Option<usize>,
+) -> io::Result<Vec<types::Str>> {
+    let mut elements = Vec::new();
+    let mut element = Vec::new();
+    let mut byte = [0; 1];
+    while count != Some(elements.len()) {
+        match unistd::read(input, &mut byte) {
+            Ok(0) => {
+                if !element.is_empty() {
+                    elements.push(String::from_utf8_lossy(&element).into());
+                }
+                break;
+            }
+            Ok(_) => {
+                element.push(byte[0]);
+                if element.ends_with(delimiter) {
+                    element.truncate(element.len() - delimiter.len());
+                    elements.push(String::from_utf8_lossy(&element).into());
+                    element.clear();
+                }
+            }
+            Err(Errno::EINTR) => (),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(elements)
+}
+
+#[builtin(
+    names = "readarray, mapfile",
+    desc = "read the lines of the standard input into an array",
+    man = "
+SYNOPSIS
+    readarray [-d DELIMITER] [-n COUNT] ARRAY
+
+DESCRIPTION
+    Reads the standard input until its end and stores each line, without its newline, as an \
+           element of ARRAY.
+
+OPTIONS
+    -d DELIMITER
+        split the input on DELIMITER instead of newlines. An empty DELIMITER splits on NUL bytes
+    -n COUNT
+        stop after reading COUNT elements. A COUNT of 0 reads every element"
+)]
+pub fn readarray(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    let mut delimiter = "\n";
+    let mut count = None;
+    let mut name = None;
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" => match args.next() {
+                Some(text) if text.is_empty() => delimiter = "\0",
+                Some(text) => delimiter = text.as_str(),
+                None => return Status::bad_argument("ion: readarray: -d requires a delimiter"),
+            },
+            "-n" => match args.next().map(|count| count.parse::<usize>()) {
+                Some(Ok(0)) => count = None,
+                Some(Ok(max)) => count = Some(max),
+                _ => {
+                    return Status::bad_argument("ion: readarray: -n requires a number of elements")
+                }
+            },
+            _ if name.is_some() => {
+                return Status::bad_argument("ion: readarray: only one array can be read")
+            }
+            _ => name = Some(arg),
+        }
+    }
+
+    let name = match name {
+        Some(name) if Variables::is_valid_name(name) => name,
+        Some(name) => {
+            return Status::bad_argument(format!("ion: readarray: invalid array name: {}", name))
+        }
+        None => return Status::bad_argument("ion: readarray: an array name is required"),
+    };
+
+    match read_elements(STDIN_FILENO, delimiter.as_bytes(), count) {
+        Ok(elements) => {
+            shell
+                .variables_mut()
+                .set(name, Value::Array(elements.into_iter().map(Value::Str).collect()));
+            Status::SUCCESS
+        }
+        Err(why) => Status::error(format!("ion: readarray: {}", why)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::{close, pipe, write};
+
+    fn read(input: &[u8], delimiter: &str, count: Option<usize>) -> Vec<types::Str> {
+        let (reader, writer) = pipe().unwrap();
+        write(writer, input).unwrap();
+        close(writer).unwrap();
+        let elements = read_elements(reader, delimiter.as_bytes(), count).unwrap();
+        close(reader).unwrap();
+        elements
+    }
+
+    #[test]
+    fn reads_lines() {
+        assert_eq!(read(b"one\n  two  \n\nthree", "\n", None), ["one", "  two  ", "", "three"]);
+        assert_eq!(read(b"one\ntwo\n", "\n", None), ["one", "two"]);
+        assert!(read(b"", "\n", None).is_empty());
+    }
+
+    #[test]
+    fn custom_delimiter_and_count() {
+        assert_eq!(read(b"a, b,, c\n", ", ", None), ["a", "b,", "c\n"]);
+        assert_eq!(read(b"a\0b\0c\0", "\0", Some(2)), ["a", "b"]);
+    }
+
+    #[test]
+    fn count_leaves_input_unread() {
+        let (reader, writer) = pipe().unwrap();
+        write(writer, b"1\n2\n3\n").unwrap();
+        close(writer).unwrap();
+        assert_eq!(read_elements(reader, b"\n", Some(1)).unwrap(), ["1"]);
+        assert_eq!(read_elements(reader, b"\n", None).unwrap(), ["2", "3"]);
+        close(reader).unwrap();
+    }
+}
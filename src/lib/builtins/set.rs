@@ -1,9 +1,6 @@
 use super::Status;
 use crate as ion_shell;
-use crate::{
-    shell::{variables::Value, Shell},
-    types,
-};
+use crate::{shell::Shell, types};
 use builtins_proc::builtin;
 use std::iter;
 
@@ -17,19 +14,28 @@ enum PositionalArgs {
     desc = "Set or unset values of shell options and positional parameters.",
     man = "
 SYNOPSIS
-    set [ --help ] [-e | +e] [-p | +p] [- | --] [STRING]...
+    set [ --help ] [-e | +e] [-f | +f] [-p | +p] [--color=WHEN] [- | --] [STRING]...
 
 DESCRIPTION
     Shell options may be set using the '-' character, and unset using the '+' character.
 
 OPTIONS
     -e  Exit immediately if a command exits with a non-zero status.
+        Conditions, such as the expression of an if or the left side of && and ||,
+        do not exit.
     
+    -f  Do not expand glob patterns, which are then left as they are.
+
     -p  If any command in pipe exits with an non-zero code then pipe returns this non-zero code 
         instead of error code of the last command.
         It can be combined with the option -e to let a script fail 
         if an errors occures in a pipe
         
+    --color=WHEN
+        Whether the color namespace, as in ${c::red}, expands to ANSI escape codes.
+        WHEN is 'always', the default, 'never', or 'auto' to only color the output
+        written to a terminal.
+
     --  Following arguments will be set as positional arguments in the shell.
         If no argument are supplied, arguments will be unset.
 
@@ -57,8 +63,19 @@ pub fn set(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
             }
             "-e" => shell.opts_mut().err_exit = true,
             "+e" => shell.opts_mut().err_exit = false,
+            "-f" => shell.opts_mut().no_glob = true,
+            "+f" => shell.opts_mut().no_glob = false,
             "-p" => shell.opts_mut().pipe_fail = true,
             "+p" => shell.opts_mut().pipe_fail = false,
+            _ if arg.starts_with("--color=") => match arg["--color=".len()..].parse() {
+                Ok(mode) => shell.opts_mut().color = mode,
+                Err(()) => {
+                    return Status::bad_argument(format!(
+                        "set: color mode '{}' is not one of 'auto', 'always' or 'never'",
+                        &arg["--color=".len()..]
+                    ))
+                }
+            },
             _ => {
                 return Status::bad_argument(format!(
                     "set: argument '{}' is not recognized. Try adding `--` before it to pass it \
@@ -69,19 +86,11 @@ pub fn set(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
         }
     }
 
-    match positionals {
-        None => (),
-        Some(kind) => {
-            if let Some(Value::Array(array)) = shell.variables().get("args") {
-                let command = array[0].clone();
-                // This used to take a `&[String]` but cloned them all, so although
-                // this is non-ideal and could probably be better done with `Rc`, it
-                // hasn't got any slower.
-                let arguments: types::Array<_> =
-                    iter::once(command).chain(args_iter.cloned().map(Value::Str)).collect();
-                if !(kind == PositionalArgs::RetainIfNone && arguments.len() == 1) {
-                    shell.variables_mut().set("args", arguments);
-                }
+    if let Some(kind) = positionals {
+        // The name of the script is kept, and nothing is set without one
+        if let Some(name) = shell.args().first().cloned() {
+            if kind == PositionalArgs::UnsetIfNone || args_iter.len() > 0 {
+                shell.set_args(iter::once(name).chain(args_iter.cloned()).collect());
             }
         }
     }
@@ -8,17 +8,25 @@ use std::fs::File;
     desc = "evaluates given file",
     man = "
 SYNOPSIS
-    source FILEPATH
+    source FILEPATH [ARGUMENTS...]
 
 DESCRIPTION
     Evaluates the commands in a specified file in the current shell. All changes in shell
-    variables will affect the current shell because of this."
+    variables will affect the current shell because of this, except for @args: when ARGUMENTS
+    are given, they are available to the file as @args until it returns, and the changes of the
+    file to @args, as with set --, are undone once it returns."
 )]
 pub fn source(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     match args.get(1) {
         Some(argument) => {
             if let Ok(file) = File::open(argument.as_str()) {
-                if let Err(why) = shell.execute_command(file) {
+                let result = if args.len() > 2 {
+                    shell.execute_script(file, &args[1..])
+                } else {
+                    let args = shell.args().to_vec();
+                    shell.execute_script(file, &args)
+                };
+                if let Err(why) = result {
                     Status::error(format!("ion: {}", why))
                 } else {
                     Status::SUCCESS
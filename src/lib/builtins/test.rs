@@ -41,7 +41,8 @@ OPTIONS
         the strings are not equal
 
     INTEGER -eq INTEGER
-        the integers are equal
+        the integers are equal. The operands of every integer comparison must be integers, or the \
+           test fails with an error
 
     INTEGER -ge INTEGER
         the first INTEGER is greater than or equal to the second INTEGER
@@ -62,10 +63,10 @@ OPTIONS
         both files have the same device and inode numbers
 
     FILE -nt FILE
-        the first FILE is newer than the second FILE
+        the first FILE was modified after the second FILE, or only the first FILE exists
 
     FILE -ot FILE
-        the first file is older than the second FILE
+        the first FILE was modified before the second FILE, or only the second FILE exists
 
     -b FILE
         FILE exists and is a block device
@@ -103,6 +104,10 @@ OPTIONS
     -x FILE
         FILE exists and execute (or search) permission is granted
 
+EXIT STATUS
+    The status is 0 if the expression is true, 1 if it is false and 2 if it is invalid, as with \
+           an unknown operator or an operand that is not an integer.
+
 EXAMPLES
     Test if the file exists:
         test -e FILE && echo "The FILE exists" || echo "The FILE does not exist"
@@ -123,7 +128,7 @@ pub fn test(args: &[types::Str], _: &mut Shell<'_>) -> Status {
     match evaluate_arguments(&args[1..]) {
         Ok(true) => Status::TRUE,
         Ok(false) => Status::FALSE,
-        Err(why) => Status::error(why),
+        Err(why) => Status::bad_argument(why),
     }
 }
 
@@ -168,18 +173,18 @@ fn evaluate_expression(first: &str, operator: &str, second: &str) -> Result<bool
         "-ef" => Ok(files_have_same_device_and_inode_numbers(first, second)),
         "-nt" => Ok(file_is_newer_than(first, second)),
         "-ot" => Ok(file_is_newer_than(second, first)),
-        _ => {
-            let (left, right) = parse_integers(first, second)?;
-            match operator {
-                "-eq" => Ok(left == right),
-                "-ge" => Ok(left >= right),
-                "-gt" => Ok(left > right),
-                "-le" => Ok(left <= right),
-                "-lt" => Ok(left < right),
-                "-ne" => Ok(left != right),
-                _ => Err(format!("test: unknown condition: {:?}", operator).into()),
-            }
+        "-eq" | "-ge" | "-gt" | "-le" | "-lt" | "-ne" => {
+            let (left, right) = (parse_integer(first)?, parse_integer(second)?);
+            Ok(match operator {
+                "-eq" => left == right,
+                "-ge" => left >= right,
+                "-gt" => left > right,
+                "-le" => left <= right,
+                "-lt" => left < right,
+                _ => left != right,
+            })
         }
+        _ => Err(format!("test: unknown condition: {:?}", operator).into()),
     }
 }
 
@@ -201,19 +206,14 @@ fn get_dev_and_inode(filename: &str) -> Option<(u64, u64)> {
     fs::metadata(filename).map(|file| (file.dev(), file.ino())).ok()
 }
 
-/// Exits SUCCESS if the first file is newer than the second file.
+/// Exits SUCCESS if the first file is newer than the second file, or if only the first file
+/// exists.
 fn file_is_newer_than(first: &str, second: &str) -> bool {
-    // Obtain the modified file time of the first file or return FAILED
-    get_modified_file_time(first).map_or(false, |left| {
-        // Obtain the modified file time of the second file or return
-        // FAILED
-        get_modified_file_time(second).map_or(false, |right| {
-            // If the first file is newer
-            // than the right file,
-            // return SUCCESS
-            left > right
-        })
-    })
+    match (get_modified_file_time(first), get_modified_file_time(second)) {
+        (Some(left), Some(right)) => left > right,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
 }
 
 /// Obtain the time the file was last modified as a `SystemTime` type.
@@ -221,22 +221,11 @@ fn get_modified_file_time(filename: &str) -> Option<SystemTime> {
     fs::metadata(filename).ok().and_then(|file| file.modified().ok())
 }
 
-/// Attempt to parse a &str as a usize.
-fn parse_integers(left: &str, right: &str) -> Result<(Option<isize>, Option<isize>), types::Str> {
-    let parse_integer = |input: &str| -> Result<Option<isize>, types::Str> {
-        match input
-            .parse::<isize>()
-            .map_err(|_| format!("test: integer expression expected: {:?}", input))
-        {
-            Err(why) => Err(why.into()),
-            Ok(res) => Ok(Some(res)),
-        }
-    };
-
-    parse_integer(left).and_then(|left| match parse_integer(right) {
-        Ok(right) => Ok((left, right)),
-        Err(why) => Err(why),
-    })
+/// Attempt to parse an operand of an integer comparison.
+fn parse_integer(input: &str) -> Result<i64, types::Str> {
+    input
+        .parse::<i64>()
+        .map_err(|_| format!("test: integer expression expected: {:?}", input).into())
 }
 
 /// Matches flag arguments to their respective functionaity when the `-`
@@ -409,6 +398,60 @@ fn test_integers_arguments() {
     assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ne", "10"])), Ok(true));
 }
 
+#[test]
+fn test_integers_errors() {
+    fn vec_string(args: &[&str]) -> Vec<types::Str> { args.iter().map(|s| (*s).into()).collect() }
+    assert_eq!(
+        evaluate_arguments(&vec_string(&["10", "-lt", "ten"])),
+        Err("test: integer expression expected: \"ten\"".into())
+    );
+    assert_eq!(
+        evaluate_arguments(&vec_string(&["1.5", "-eq", "1.5"])),
+        Err("test: integer expression expected: \"1.5\"".into())
+    );
+    assert_eq!(
+        evaluate_arguments(&vec_string(&["a", "-is", "b"])),
+        Err("test: unknown condition: \"-is\"".into())
+    );
+    assert_eq!(
+        builtin_test(&vec_string(&["test", "", "-gt", "0"]), &mut Shell::new()),
+        Status::from_exit_code(2)
+    );
+}
+
+#[test]
+fn test_file_modification_times() {
+    use nix::sys::{
+        stat::utimes,
+        time::{TimeVal, TimeValLike},
+    };
+    use std::env;
+
+    let dir = env::temp_dir();
+    let old = dir.join(format!("ion-test-old-{}", std::process::id()));
+    let new = dir.join(format!("ion-test-new-{}", std::process::id()));
+    let missing = dir.join(format!("ion-test-missing-{}", std::process::id()));
+    for (path, secs) in &[(&old, 1_000_000), (&new, 2_000_000)] {
+        fs::write(path, "").unwrap();
+        let time = TimeVal::seconds(*secs);
+        utimes(path.as_path(), &time, &time).unwrap();
+    }
+    let (old_str, new_str) = (old.to_str().unwrap(), new.to_str().unwrap());
+    let missing = missing.to_str().unwrap();
+
+    assert_eq!(evaluate_expression(new_str, "-nt", old_str), Ok(true));
+    assert_eq!(evaluate_expression(old_str, "-nt", new_str), Ok(false));
+    assert_eq!(evaluate_expression(old_str, "-ot", new_str), Ok(true));
+    assert_eq!(evaluate_expression(new_str, "-ot", new_str), Ok(false));
+    assert_eq!(evaluate_expression(old_str, "-nt", missing), Ok(true));
+    assert_eq!(evaluate_expression(missing, "-nt", old_str), Ok(false));
+    assert_eq!(evaluate_expression(missing, "-ot", old_str), Ok(true));
+    assert_eq!(evaluate_expression(missing, "-ot", missing), Ok(false));
+
+    fs::remove_file(old).unwrap();
+    fs::remove_file(new).unwrap();
+}
+
 #[test]
 fn test_file_exists() {
     assert_eq!(file_exists("testing/empty_file"), true);
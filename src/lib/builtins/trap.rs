@@ -0,0 +1,54 @@
+use super::Status;
+use crate as ion_shell;
+use crate::{shell::Trap, types, Shell};
+use builtins_proc::builtin;
+
+#[builtin(
+    desc = "run some code when the shell exits, before commands or when they fail",
+    man = "
+SYNOPSIS
+    trap [CODE TRAPS...]
+    trap - TRAPS...
+
+DESCRIPTION
+    Runs CODE when one of the TRAPS happens, replacing the code set before. With `-` as the code, \
+           the traps are removed. Without arguments, the traps that are set are listed.
+
+TRAPS
+    EXIT, 0
+        the shell exits, at the end of its input or because of the exit builtin. The shell still \
+           exits with its status from before the trap
+    ERR
+        a command fails, with its status in $?. Conditions, such as the expression of an if or the \
+           left side of && and ||, are left out, and so are the calls of functions, whose own \
+           commands run the trap instead
+    DEBUG
+        a command is about to run. The command, once expanded, is in $COMMAND. The commands of the \
+           trap do not run it again"
+)]
+pub fn trap(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    let (code, names) = match args.get(1) {
+        Some(code) => (code, &args[2..]),
+        None => {
+            for (trap, code) in shell.traps() {
+                println!("trap '{}' {}", code, trap);
+            }
+            return Status::SUCCESS;
+        }
+    };
+    if names.is_empty() {
+        return Status::bad_argument("ion: trap: no trap given");
+    }
+
+    let traps: Result<Vec<Trap>, _> =
+        names.iter().map(|name| name.parse::<Trap>().map_err(|_| name)).collect();
+    let traps = match traps {
+        Ok(traps) => traps,
+        Err(name) => return Status::bad_argument(format!("ion: trap: unknown trap: {}", name)),
+    };
+    let code = if code == "-" { None } else { Some(code.clone()) };
+    for trap in traps {
+        shell.set_trap(trap, code.clone());
+    }
+    Status::SUCCESS
+}
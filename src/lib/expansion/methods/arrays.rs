@@ -1,15 +1,44 @@
 use super::{
     super::{is_expression, words::Select, Error, Expander, ExpanderInternal, Index},
     strings::unescape,
-    MethodError, Pattern,
+    MethodArgs, MethodError, Pattern,
 };
 use crate::{
     expansion::is_array_expression,
+    parser::lexers::ArgumentSplitter,
     types::{self, Args},
 };
-use std::char;
+use std::{char, cmp::Ordering, collections::HashSet};
 use unicode_segmentation::UnicodeSegmentation;
 
+/// Parses an element sorted numerically, rejecting NaN so that numbers have a total order
+fn parse_number(element: &str) -> Option<f64> {
+    element.trim().parse::<f64>().ok().filter(|number| !number.is_nan())
+}
+
+/// Compares the runs of digits of two versions by their value, and everything else as text
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (a_digits, b_digits) = (a.starts_with(is_digit), b.starts_with(is_digit));
+        let (a_run, a_rest) = a.split_at(a.find(|c| is_digit(c) != a_digits).unwrap_or(a.len()));
+        let (b_run, b_rest) = b.split_at(b.find(|c| is_digit(c) != b_digits).unwrap_or(b.len()));
+        let ordering = if a_digits && b_digits {
+            let (a_run, b_run) = (a_run.trim_start_matches('0'), b_run.trim_start_matches('0'));
+            a_run.len().cmp(&b_run.len()).then_with(|| a_run.cmp(b_run))
+        } else {
+            a_run.cmp(b_run)
+        };
+        if ordering != Ordering::Equal || (a_rest.is_empty() && b_rest.is_empty()) {
+            return ordering;
+        }
+        a = a_rest;
+        b = b_rest;
+    }
+}
+
+const fn is_digit(character: char) -> bool { character.is_ascii_digit() }
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct ArrayMethod<'a> {
     method:    &'a str,
@@ -34,6 +63,40 @@ impl<'a> ArrayMethod<'a> {
         Ok(result)
     }
 
+    /// Removes the duplicate elements, keeping the first occurrence of each element in place
+    fn unique<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
+        let array = self.resolve_array(expand_func)?;
+        let mut seen = HashSet::with_capacity(array.len());
+        Ok(array.iter().filter(|element| seen.insert(element.as_str())).cloned().collect())
+    }
+
+    fn sort<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
+        let mode = match self.pattern {
+            Pattern::StringPattern(pattern) => expand_func.expand_string(pattern)?.join(" "),
+            Pattern::Whitespace => String::new(),
+        };
+        let mut result = self.resolve_array(expand_func)?;
+        match mode.as_str() {
+            "" | "lexical" => result.sort(),
+            // Numbers come first, by value, followed by the other elements in lexical order
+            "numeric" => result.sort_by(|a, b| match (parse_number(a), parse_number(b)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => a.cmp(b),
+            }),
+            "version" => result.sort_by(|a, b| compare_versions(a, b)),
+            _ => {
+                return Err(MethodError::WrongArgument(
+                    "sort",
+                    "the mode must be lexical, numeric or version",
+                )
+                .into())
+            }
+        }
+        expand_func.slice_array(result.into_iter(), &self.selection)
+    }
+
     fn lines<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
         Ok(self.resolve_var(expand_func)?.lines().map(types::Str::from).collect())
     }
@@ -58,6 +121,11 @@ impl<'a> ArrayMethod<'a> {
         expand_func.slice_array(expand_func.map_values(self.variable)?.into_iter(), &self.selection)
     }
 
+    fn map_entries<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
+        expand_func
+            .slice_array(expand_func.map_entries(self.variable)?.into_iter(), &self.selection)
+    }
+
     fn graphemes<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
         let variable = self.resolve_var(expand_func)?;
         let graphemes = UnicodeSegmentation::graphemes(variable.as_str(), true);
@@ -101,9 +169,34 @@ impl<'a> ArrayMethod<'a> {
                 expand_func.slice_array(data, &self.selection)
             }
             Pattern::StringPattern(pattern) => {
-                let escape = unescape(&expand_func.expand_string(pattern)?.join(" "));
-                let data = variable.split(escape.as_str());
-                expand_func.slice_array(data, &self.selection)
+                let mut args = ArgumentSplitter::new(pattern);
+                let separator = args.next().unwrap_or_default();
+                let escape = unescape(&expand_func.expand_string(separator)?.join(" "));
+                match (args.next(), args.next()) {
+                    (None, _) => {
+                        let data = variable.split(escape.as_str());
+                        expand_func.slice_array(data, &self.selection)
+                    }
+                    // The limit caps the number of splits, the last part keeping the rest
+                    (Some(limit), None) => {
+                        let limit =
+                            expand_func.expand_string(limit)?.join(" ").parse::<usize>().map_err(
+                                |_| {
+                                    MethodError::WrongArgument(
+                                        "split",
+                                        "limit is not a valid positive integer",
+                                    )
+                                },
+                            )?;
+                        let data = variable.splitn(limit + 1, escape.as_str());
+                        expand_func.slice_array(data, &self.selection)
+                    }
+                    (Some(_), Some(_)) => Err(MethodError::WrongArgument(
+                        "split",
+                        "takes at most a separator and a limit",
+                    )
+                    .into()),
+                }
             }
         }
     }
@@ -149,6 +242,82 @@ impl<'a> ArrayMethod<'a> {
         }
     }
 
+    fn map<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
+        let command = match self.pattern {
+            Pattern::StringPattern(pattern) => expand_func.expand_string(pattern)?.join(" "),
+            Pattern::Whitespace => {
+                return Err(MethodError::WrongArgument("map", "requires a command").into())
+            }
+        };
+
+        let mut mapped = Args::new();
+        for element in self.resolve_array(expand_func)? {
+            let output = expand_func.with_vars(&[("_", &element)], |expand_func| {
+                expand_func.command(&command, false)
+            })?;
+            mapped.push(output.trim_end_matches('\n').into());
+        }
+        expand_func.slice_array(mapped.into_iter(), &self.selection)
+    }
+
+    fn filter_cmd<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
+        let command = match self.pattern {
+            Pattern::StringPattern(pattern) => expand_func.expand_string(pattern)?.join(" "),
+            Pattern::Whitespace => {
+                return Err(MethodError::WrongArgument("filter_cmd", "requires a command").into())
+            }
+        };
+
+        let mut kept = Args::new();
+        for item in self.resolve_array(expand_func)? {
+            let succeeded = expand_func.with_vars(&[("item", &item)], |expand_func| {
+                expand_func.command_succeeds(&command)
+            })?;
+            if succeeded {
+                kept.push(item);
+            }
+        }
+        expand_func.slice_array(kept.into_iter(), &self.selection)
+    }
+
+    fn reduce<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
+        let (expression, initial) = match self.pattern {
+            Pattern::StringPattern(pattern) => {
+                let mut args = MethodArgs::new(pattern, expand_func);
+                args.allow_third_args_empty();
+                let mut args = args.array();
+                match (args.next(), args.next(), args.next()) {
+                    (Some(expression), Some(initial), None) => (expression, initial),
+                    _ => {
+                        return Err(MethodError::WrongArgument(
+                            "reduce",
+                            "requires an expression and an initial value",
+                        )
+                        .into())
+                    }
+                }
+            }
+            Pattern::Whitespace => {
+                return Err(MethodError::WrongArgument(
+                    "reduce",
+                    "requires an expression and an initial value",
+                )
+                .into())
+            }
+        };
+
+        let mut acc = initial;
+        for item in self.resolve_array(expand_func)? {
+            acc = expand_func
+                .with_vars(&[("acc", &acc), ("item", &item)], |expand_func| {
+                    expand_func.expand_string(&expression)
+                })?
+                .join(" ")
+                .into();
+        }
+        Ok(args![acc])
+    }
+
     #[inline]
     fn resolve_array<E: Expander>(&self, expand_func: &mut E) -> Result<Args, Error<E::Error>> {
         match expand_func.array(self.variable, &Select::All) {
@@ -191,10 +360,16 @@ impl<'a> ArrayMethod<'a> {
         match self.method {
             "bytes" => self.bytes(expand_func),
             "chars" => self.chars(expand_func),
+            "entries" => self.map_entries(expand_func),
             "graphemes" => self.graphemes(expand_func),
             "keys" => self.map_keys(expand_func).map_err(Error::from),
             "lines" => self.lines(expand_func),
+            "filter_cmd" => self.filter_cmd(expand_func),
+            "map" => self.map(expand_func),
+            "reduce" => self.reduce(expand_func),
             "reverse" => self.reverse(expand_func),
+            "sort" => self.sort(expand_func),
+            "unique" => self.unique(expand_func),
             "split_at" => self.split_at(expand_func),
             "split" => self.split(expand_func),
             "values" => self.map_values(expand_func).map_err(Error::from),
@@ -239,6 +414,17 @@ mod test {
         assert_eq!(&*output, "FOO BAR");
     }
 
+    #[test]
+    fn test_split_string_limit() {
+        let method =
+            ArrayMethod::new("split", "\"a/b/c/d\"", Pattern::StringPattern("'/' 2"), None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["a", "b", "c/d"]);
+        let method = ArrayMethod::new("split", "\"a/b\"", Pattern::StringPattern("'/' 0"), None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["a/b"]);
+        let method = ArrayMethod::new("split", "\"a/b\"", Pattern::StringPattern("'/' -1"), None);
+        assert!(method.handle_as_array(&mut DummyExpander).is_err());
+    }
+
     #[test]
     fn test_split_string_index_forward() {
         let mut output = types::Str::new();
@@ -359,6 +545,80 @@ mod test {
         assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["c", "b", "a"]);
     }
 
+    #[test]
+    fn test_unique() {
+        let method = ArrayMethod::new("unique", "[b a b c a]", Pattern::Whitespace, None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["b", "a", "c"]);
+        let method = ArrayMethod::new("unique", "@ARRAY", Pattern::Whitespace, None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["a", "b", "c"]);
+        // A pattern is ignored
+        let method = ArrayMethod::new("unique", "[b a b]", Pattern::StringPattern("a"), None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["b", "a"]);
+    }
+
+    #[test]
+    fn test_sort() {
+        let sort = |variable, mode: &'static str| {
+            let pattern =
+                if mode.is_empty() { Pattern::Whitespace } else { Pattern::StringPattern(mode) };
+            ArrayMethod::new("sort", variable, pattern, None).handle_as_array(&mut DummyExpander)
+        };
+        let numbers = "[10 9 -1.5 b 2e1 a 0x3 nan 07]";
+        assert_eq!(
+            sort(numbers, "").unwrap(),
+            args!["-1.5", "07", "0x3", "10", "2e1", "9", "a", "b", "nan"]
+        );
+        assert_eq!(
+            sort(numbers, "numeric").unwrap(),
+            args!["-1.5", "07", "9", "10", "2e1", "0x3", "a", "b", "nan"]
+        );
+        assert_eq!(
+            sort("[1.10.0 1.9.2 1.9 v2 1.10.0-rc1 01.2]", "version").unwrap(),
+            args!["01.2", "1.9", "1.9.2", "1.10.0", "1.10.0-rc1", "v2"]
+        );
+        assert!(sort("@ARRAY", "random").is_err());
+    }
+
+    #[test]
+    fn test_map() {
+        let method = ArrayMethod::new("map", "@ARRAY", Pattern::StringPattern("'echo $_'"), None);
+        assert_eq!(
+            method.handle_as_array(&mut DummyExpander).unwrap(),
+            args!["echo $_", "echo $_", "echo $_"]
+        );
+
+        let method = ArrayMethod::new("map", "[]", Pattern::StringPattern("'echo $_'"), None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args![]);
+    }
+
+    #[test]
+    fn test_map_fail_command_missing() {
+        let method = ArrayMethod::new("map", "@ARRAY", Pattern::Whitespace, None);
+        assert!(method.handle_as_array(&mut DummyExpander).is_err());
+    }
+
+    #[test]
+    fn test_filter_cmd() {
+        let method =
+            ArrayMethod::new("filter_cmd", "@ARRAY", Pattern::StringPattern("'true'"), None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["a", "b", "c"]);
+
+        let method =
+            ArrayMethod::new("filter_cmd", "@ARRAY", Pattern::StringPattern("'false'"), None);
+        assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args![]);
+
+        let method = ArrayMethod::new("filter_cmd", "@ARRAY", Pattern::Whitespace, None);
+        assert!(method.handle_as_array(&mut DummyExpander).is_err());
+    }
+
+    #[test]
+    fn test_reduce_fail_arguments() {
+        let method = ArrayMethod::new("reduce", "@ARRAY", Pattern::StringPattern("'$acc'"), None);
+        assert!(method.handle_as_array(&mut DummyExpander).is_err());
+        let method = ArrayMethod::new("reduce", "@ARRAY", Pattern::Whitespace, None);
+        assert!(method.handle_as_array(&mut DummyExpander).is_err());
+    }
+
     #[test]
     fn test_subst_variable_over_default() {
         let method = ArrayMethod::new("subst", "@ARRAY", Pattern::StringPattern("[2, 3]"), None);
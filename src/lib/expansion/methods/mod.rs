@@ -19,7 +19,7 @@ pub struct MethodArgs<'a, 'b, E: Expander> {
     args:                       &'a str,
     expand:                     &'b mut E,
     /// If true then the third argument may be an empty string.
-    /// Currently used for method replace, replacen and regex_replace
+    /// Currently used for method replace, replacen, regex_replace and replace_all
     /// Need to use this ad hoc approach because several other integration tests
     /// fail if empty string arguments are allowed always.
     is_empty_third_arg_allowed: bool,
@@ -44,9 +44,9 @@ pub enum MethodError {
     #[error("{0}: {1}")]
     WrongArgument(&'static str, &'static str),
 
-    /// An invalid regex was provided. This is specific to the `matches` method
-    #[error("regex_replace: error in regular expression '{0}': {1}")]
-    InvalidRegex(String, #[source] regex::Error),
+    /// An invalid regex was provided to the given method
+    #[error("{0}: error in regular expression '{1}': {2}")]
+    InvalidRegex(&'static str, String, #[source] regex::Error),
 }
 
 impl<'a, 'b, E: 'b + Expander> MethodArgs<'a, 'b, E> {
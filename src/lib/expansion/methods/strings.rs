@@ -76,6 +76,81 @@ fn escape(input: &str) -> String {
     output
 }
 
+/// The command of `input` if it is a single `$(command)` substitution, and nothing else
+fn substituted_command(input: &str) -> Option<&str> {
+    let inner = input.strip_prefix("$(")?;
+    if inner.starts_with('(') {
+        return None;
+    }
+    let (mut depth, mut quote) = (1, None);
+    let mut escaped = false;
+    for (id, character) in inner.char_indices() {
+        match character {
+            _ if escaped => escaped = false,
+            '\\' if quote != Some('\'') => escaped = true,
+            '\'' | '"' if quote.is_none() => quote = Some(character),
+            _ if quote == Some(character) => quote = None,
+            '(' if quote.is_none() => depth += 1,
+            ')' if quote.is_none() => {
+                depth -= 1;
+                if depth == 0 {
+                    return if id + 1 == inner.len() { Some(&inner[..id]) } else { None };
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Strips the graphemes found in `set` from the start and/or the end of `input`
+fn trim_graphemes<'a>(input: &'a str, set: &str, start: bool, end: bool) -> &'a str {
+    let set: Vec<_> = set.graphemes(true).collect();
+    let kept = |&(_, grapheme): &(usize, &str)| !set.contains(&grapheme);
+    let mut trimmed = input;
+    if start {
+        trimmed = trimmed.grapheme_indices(true).find(kept).map_or("", |(id, _)| &trimmed[id..]);
+    }
+    if end {
+        trimmed = trimmed
+            .grapheme_indices(true)
+            .rev()
+            .find(kept)
+            .map_or("", |(id, grapheme)| &trimmed[..id + grapheme.len()]);
+    }
+    trimmed
+}
+
+fn hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut output = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        output.push(DIGITS[usize::from(byte >> 4)] as char);
+        output.push(DIGITS[usize::from(byte & 0xf)] as char);
+    }
+    output
+}
+
+/// Encodes with the standard base64 alphabet, with padding
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(bytes.len() / 3 * 4 + 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (id, &byte)| group | u32::from(byte) << (16 - 8 * id));
+        for id in 0..4 {
+            if id <= chunk.len() {
+                output.push(ALPHABET[(group >> (18 - 6 * id) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
 /// Represents a method that operates on and returns a string
 #[derive(Debug, PartialEq, Clone)]
 pub struct StringMethod<'a> {
@@ -154,6 +229,13 @@ impl<'a> StringMethod<'a> {
             "parent" => path_eval!(parent),
             "to_lowercase" => string_case!(to_lowercase),
             "to_uppercase" => string_case!(to_uppercase),
+            "trim" | "trim_end" | "trim_start" if !self.pattern.is_empty() => {
+                let value = get_var!();
+                let set = MethodArgs::new(self.pattern, expand).join("")?;
+                let start = self.method != "trim_end";
+                let end = self.method != "trim_start";
+                output.push_str(trim_graphemes(&value, &set, start, end));
+            }
             "trim" => output.push_str(get_var!().trim()),
             "trim_end" => output.push_str(get_var!().trim_end()),
             "trim_start" => output.push_str(get_var!().trim_start()),
@@ -213,7 +295,9 @@ impl<'a> StringMethod<'a> {
                     }
                 }
             }
-            "regex_replace" => {
+            "regex_replace" | "replace_all" => {
+                let method =
+                    if self.method == "replace_all" { "replace_all" } else { "regex_replace" };
                 let params = {
                     let mut args = MethodArgs::new(self.pattern, expand);
                     args.allow_third_args_empty();
@@ -224,15 +308,15 @@ impl<'a> StringMethod<'a> {
                     (Some(replace), Some(with)) => match Regex::new(&replace) {
                         Ok(re) => output.push_str(&re.replace_all(&get_var!(), &with[..])),
                         Err(why) => {
-                            return Err(MethodError::InvalidRegex(replace.to_string(), why).into())
+                            return Err(
+                                MethodError::InvalidRegex(method, replace.to_string(), why).into()
+                            )
                         }
                     },
                     _ => {
-                        return Err(MethodError::WrongArgument(
-                            "regex_replace",
-                            ERR_MSG_REPLACE_METHOD,
+                        return Err(
+                            MethodError::WrongArgument(method, ERR_MSG_REPLACE_METHOD).into()
                         )
-                        .into())
                     }
                 }
             }
@@ -275,6 +359,20 @@ impl<'a> StringMethod<'a> {
                 }
                 Err(why) => return Err(why),
             },
+            "line_count" | "word_count" => {
+                // The output of a substitution is counted before its trailing newline is removed
+                let text = match substituted_command(variable) {
+                    Some(command) => expand.command(command, true)?,
+                    None => get_var!(),
+                };
+                // A trailing newline ends the last line instead of starting an empty one
+                let count = if self.method == "line_count" {
+                    text.lines().count()
+                } else {
+                    text.split_whitespace().count()
+                };
+                output.push_str(&count.to_string());
+            }
             "reverse" => match expand.string(variable) {
                 Ok(value) => {
                     let rev_graphs = UnicodeSegmentation::graphemes(value.as_str(), true).rev();
@@ -298,6 +396,36 @@ impl<'a> StringMethod<'a> {
                 };
                 output.push_str(&out.map_or(-1, |i| i as isize).to_string());
             }
+            "keyat" => {
+                let position = MethodArgs::new(self.pattern, expand).join(" ")?;
+                let position = position.parse::<usize>().map_err(|_| {
+                    MethodError::WrongArgument("keyat", "argument is not a valid positive integer")
+                })?;
+                if let Some(key) = expand.map_key_at(variable, position)? {
+                    output.push_str(&key);
+                }
+            }
+            "hex" | "base64" => {
+                // The output of a substitution is encoded as is, without decoding it as UTF-8
+                let bytes = match substituted_command(variable) {
+                    Some(command) => expand.command_bytes(command)?,
+                    None => get_var!().as_bytes().to_vec(),
+                };
+                output.push_str(&if self.method == "hex" { hex(&bytes) } else { base64(&bytes) });
+            }
+            "cached" => match substituted_command(variable) {
+                Some(command) => {
+                    let result = expand.cached_command(command)?;
+                    expand.slice(output, result.trim_end_matches('\n'), &self.selection)?
+                }
+                None => {
+                    return Err(MethodError::WrongArgument(
+                        "cached",
+                        "argument must be a command substitution",
+                    )
+                    .into())
+                }
+            },
             "unescape" => {
                 let out = match expand.string(variable) {
                     Ok(value) => value,
@@ -632,6 +760,32 @@ mod test {
         assert_eq!(&*output, "FOOBAR");
     }
 
+    #[test]
+    fn test_replace_all_with_captures() {
+        let mut output = types::Str::new();
+        let method = StringMethod {
+            method:    "replace_all",
+            variable:  "\"a1 b22 c333\"",
+            pattern:   "['([0-9]+)' 'n$1']",
+            selection: None,
+        };
+        method.handle(&mut output, &mut DummyExpander).unwrap();
+        assert_eq!(&*output, "an1 bn22 cn333");
+    }
+
+    #[test]
+    fn test_replace_all_invalid_regex() {
+        let mut output = types::Str::new();
+        let method = StringMethod {
+            method:    "replace_all",
+            variable:  "$FOO",
+            pattern:   "['(' 'x']",
+            selection: None,
+        };
+        let err = method.handle(&mut output, &mut DummyExpander).unwrap_err();
+        assert!(matches!(err, Error::MethodError(MethodError::InvalidRegex("replace_all", ..))));
+    }
+
     #[test]
     fn test_join_with_string() {
         let mut output = types::Str::new();
@@ -685,6 +839,24 @@ mod test {
         assert_eq!(&*output, "6");
     }
 
+    #[test]
+    fn test_line_and_word_counts() {
+        let count = |method, variable| {
+            let mut output = types::Str::new();
+            let method = StringMethod { method, variable, pattern: "", selection: None };
+            method.handle(&mut output, &mut DummyExpander).unwrap();
+            output
+        };
+        assert_eq!(&*count("line_count", "$(one two\nthree\n)"), "2");
+        assert_eq!(&*count("line_count", "$(one two\nthree)"), "2");
+        assert_eq!(&*count("line_count", "$(one\n\n)"), "2");
+        assert_eq!(&*count("line_count", "$()"), "0");
+        assert_eq!(&*count("word_count", "$(one two\nthree\n)"), "3");
+        assert_eq!(&*count("word_count", "$(  )"), "0");
+        assert_eq!(&*count("line_count", "$FOO"), "1");
+        assert_eq!(&*count("word_count", "\"one  two\""), "2");
+    }
+
     #[test]
     fn test_len_bytes_with_variable() {
         let mut output = types::Str::new();
@@ -724,6 +896,19 @@ mod test {
         assert_eq!(&*output, "RABOOF");
     }
 
+    #[test]
+    fn test_reverse_keeps_graphemes() {
+        let mut output = types::Str::new();
+        let method = StringMethod {
+            method:    "reverse",
+            variable:  "$pkmn2",
+            pattern:   "",
+            selection: None,
+        };
+        method.handle(&mut output, &mut DummyExpander).unwrap();
+        assert_eq!(&*output, "nome\u{0301}koP");
+    }
+
     #[test]
     fn test_reverse_with_string() {
         let mut output = types::Str::new();
@@ -836,4 +1021,56 @@ mod test {
         method.handle(&mut output, &mut DummyExpander).unwrap();
         assert_eq!(&*output, "FOOBAR");
     }
+
+    #[test]
+    fn test_trim_graphemes() {
+        assert_eq!(trim_graphemes("//usr/lib/", "/", true, true), "usr/lib");
+        assert_eq!(trim_graphemes("//usr/lib/", "/", true, false), "usr/lib/");
+        assert_eq!(trim_graphemes("//usr/lib/", "/", false, true), "//usr/lib");
+        assert_eq!(trim_graphemes("-.-a.b-", ".-", true, true), "a.b");
+        assert_eq!(trim_graphemes("///", "/", true, true), "");
+        // Combining marks are part of the grapheme they follow
+        assert_eq!(trim_graphemes("ee\u{301}e", "e", true, true), "e\u{301}");
+        assert_eq!(trim_graphemes("e\u{301}eée\u{301}", "e\u{301}", true, true), "eé");
+
+        let mut output = types::Str::new();
+        let method = StringMethod {
+            method:    "trim_end",
+            variable:  "$FOO",
+            pattern:   "'RA'",
+            selection: None,
+        };
+        method.handle(&mut output, &mut DummyExpander).unwrap();
+        assert_eq!(&*output, "FOOB");
+    }
+
+    #[test]
+    fn test_hex_and_base64() {
+        assert_eq!(hex(b""), "");
+        assert_eq!(hex(&[0, 0x7f, 0xff, b'\n']), "007fff0a");
+        for (input, encoded) in
+            &[("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg==")]
+        {
+            assert_eq!(base64(input.as_bytes()), *encoded);
+        }
+        assert_eq!(base64(&[0xfb, 0xff, 0xfe]), "+//+");
+
+        let mut output = types::Str::new();
+        let method =
+            StringMethod { method: "base64", variable: "$FOO", pattern: "", selection: None };
+        method.handle(&mut output, &mut DummyExpander).unwrap();
+        assert_eq!(&*output, "Rk9PQkFS");
+    }
+
+    #[test]
+    fn test_substituted_command() {
+        assert_eq!(substituted_command("$(cat file)"), Some("cat file"));
+        assert_eq!(
+            substituted_command("$(echo \"(\" $(echo ')'))"),
+            Some("echo \"(\" $(echo ')')")
+        );
+        assert_eq!(substituted_command("$(a) $(b)"), None);
+        assert_eq!(substituted_command("$((1 + 2))"), None);
+        assert_eq!(substituted_command("$FOO"), None);
+    }
 }
@@ -10,20 +10,21 @@ use self::braces::BraceToken;
 pub use self::{
     loops::ForValueExpression,
     methods::MethodError,
-    words::{unescape, Select, SelectWithSize, WordIterator, WordToken},
+    words::{unescape, Select, SelectWithSize, WordError, WordIterator, WordToken},
 };
 use crate::{
     parser::lexers::assignments::TypeError,
-    ranges::{parse_range, Index, Range},
+    ranges::{parse_key_range, parse_range, Index, Range},
     types::{self, Args},
 };
 use auto_enums::auto_enum;
-use glob::glob;
+use glob::{glob_with, MatchOptions};
 use itertools::Itertools;
 use std::{
     borrow::Cow,
     error,
     fmt::{self, Write},
+    ops::Bound,
     str,
 };
 use thiserror::Error;
@@ -38,6 +39,9 @@ pub enum Error<T: fmt::Debug + error::Error + fmt::Display + 'static> {
     /// Wrong type was given
     #[error("{0}")]
     TypeError(#[source] TypeError),
+    /// The words could not be split because of unterminated syntax
+    #[error("{0}")]
+    WordError(#[source] WordError),
     /// Indexed out of the array bounds.
     #[error("Invalid index {index} for sequence with length {length}")]
     OutOfBound {
@@ -103,6 +107,23 @@ pub enum Error<T: fmt::Debug + error::Error + fmt::Display + 'static> {
     /// Mixed types between maps and scalar/array value
     #[error("variable '{0}' is not a map-like value")]
     NotAMap(String),
+
+    /// A command substituted in an arithmetic expression did not output a number
+    #[error("'{output}' given by '$({command})' is not a number")]
+    NotANumber {
+        /// The substituted command
+        command: String,
+        /// The output of the command
+        output:  String,
+    },
+
+    /// The keys of a map without an order were accessed by position
+    #[error("variable '{0}' is not an ordered map")]
+    NotAnOrderedMap(String),
+
+    /// The expander does not provide the operation
+    #[error("{0} is not supported by this expander")]
+    Unsupported(&'static str),
 }
 
 impl<T: fmt::Display + fmt::Debug + error::Error> From<TypeError> for Error<T> {
@@ -113,6 +134,10 @@ impl<T: fmt::Display + fmt::Debug + error::Error> From<MethodError> for Error<T>
     fn from(cause: MethodError) -> Self { Self::MethodError(cause) }
 }
 
+impl<T: fmt::Display + fmt::Debug + error::Error> From<WordError> for Error<T> {
+    fn from(cause: WordError) -> Self { Self::WordError(cause) }
+}
+
 /// The result of expansion with a given expander
 pub type Result<T, E> = std::result::Result<T, Error<E>>;
 
@@ -127,6 +152,49 @@ pub fn is_expression(s: &str) -> bool {
 /// bare word. For example, strings starting with '@' or '['
 pub fn is_array_expression(s: &str) -> bool { s.starts_with('@') || s.starts_with('[') }
 
+/// Splits `text` into fields on any of the characters of `ifs`, or on whitespace if there is no
+/// `ifs`. Empty fields are dropped, and an empty `ifs` keeps the text whole.
+pub(crate) fn split_fields<'a>(
+    text: &'a str,
+    ifs: Option<&'a str>,
+) -> impl Iterator<Item = &'a str> {
+    text.split(move |c: char| ifs.map_or_else(|| c.is_whitespace(), |ifs| ifs.contains(c)))
+        .filter(|field| !field.is_empty())
+}
+
+/// Options controlling how glob patterns are matched against paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlobOptions {
+    /// Whether letters must match with the same case, `true` by default.
+    pub case_sensitive:              bool,
+    /// Whether path separators may only be matched by a literal `/`, and not by `*`, `?` or a
+    /// `[...]` sequence.
+    pub require_literal_separator:   bool,
+    /// Whether a leading `.` in a file name may only be matched by a literal `.`, which hides
+    /// dotfiles from wildcards.
+    pub require_literal_leading_dot: bool,
+}
+
+impl Default for GlobOptions {
+    fn default() -> Self {
+        Self {
+            case_sensitive:              true,
+            require_literal_separator:   false,
+            require_literal_leading_dot: false,
+        }
+    }
+}
+
+impl From<GlobOptions> for MatchOptions {
+    fn from(options: GlobOptions) -> Self {
+        Self {
+            case_sensitive:              options.case_sensitive,
+            require_literal_separator:   options.require_literal_separator,
+            require_literal_leading_dot: options.require_literal_leading_dot,
+        }
+    }
+}
+
 // TODO: Make array expansions iterators instead of arrays.
 // TODO: Use Cow<'a, types::Str> for hashmap values.
 /// Trait representing different elements of string expansion.
@@ -140,16 +208,65 @@ pub trait Expander: Sized {
     fn array(&self, _name: &str, _selection: &Select<types::Str>) -> Result<Args, Self::Error>;
     /// Expand a string variable given if it's quoted / unquoted
     fn string(&self, _name: &str) -> Result<types::Str, Self::Error>;
+    /// The characters splitting the output of array processes into fields, or `None` to split it
+    /// on whitespace.
+    fn field_separators(&self) -> Option<types::Str> { None }
     /// Expand a subshell expression.
     fn command(
         &mut self,
         _command: &str,
         _set_cmd_duration: bool,
     ) -> Result<types::Str, Self::Error>;
+    /// Expand a subshell expression, capturing its standard error along with its standard
+    /// output.
+    fn command_with_stderr(&mut self, command: &str) -> Result<types::Str, Self::Error> {
+        self.command(command, true)
+    }
+    /// Run a command and capture its output as it is, even if it is not valid UTF-8.
+    fn command_bytes(&mut self, command: &str) -> Result<Vec<u8>, Self::Error> {
+        Ok(self.command(command, true)?.as_bytes().to_vec())
+    }
+    /// Expand a subshell expression, reusing the output of the previous run of the same
+    /// command if the implementation keeps it.
+    fn cached_command(&mut self, command: &str) -> Result<types::Str, Self::Error> {
+        self.command(command, true)
+    }
+    /// Run a command, discarding its output, and tell whether it succeeded.
+    fn command_succeeds(&mut self, _command: &str) -> Result<bool, Self::Error> {
+        Err(Error::Unsupported("running a command for its status"))
+    }
+    /// Start a command in the background and give the path substituted for it, which either
+    /// feeds its standard input if `input` is set, or reads its standard output.
+    fn substitute_process(
+        &mut self,
+        _command: &str,
+        _input: bool,
+    ) -> Result<types::Str, Self::Error> {
+        Err(Error::Unsupported("process substitution"))
+    }
+    /// Run `f` with some variables set, which are removed once it returns.
+    fn with_vars<T, F>(&mut self, _vars: &[(&str, &str)], _f: F) -> Result<T, Self::Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Self::Error>,
+    {
+        Err(Error::Unsupported("scoped variables"))
+    }
     /// Iterating upon key-value maps.
     fn map_keys(&self, _name: &str) -> Result<Args, Self::Error>;
     /// Iterating upon key-value maps.
     fn map_values(&self, _name: &str) -> Result<Args, Self::Error>;
+    /// Iterating upon key-value maps, giving each key followed by its value.
+    fn map_entries(&self, _name: &str) -> Result<Args, Self::Error> {
+        Err(Error::Unsupported("listing the entries of a map"))
+    }
+    /// The key at `position` in the order of an ordered map, if the map is long enough.
+    fn map_key_at(&self, name: &str, position: usize) -> Result<Option<types::Str>, Self::Error> {
+        Ok(self.map_keys(name)?.into_iter().nth(position))
+    }
+    /// The options used to match glob patterns.
+    fn glob_options(&self) -> GlobOptions { GlobOptions::default() }
+    /// Whether glob patterns are expanded at all.
+    fn globbing(&self) -> bool { true }
     /// Get a string that exists in the shell.
     fn get_string(&mut self, value: &str) -> Result<types::Str, Self::Error> {
         Ok(self.expand_string(value)?.join(" ").into())
@@ -169,7 +286,8 @@ pub trait Expander: Sized {
         let mut token_buffer = Vec::new();
         let mut contains_brace = false;
 
-        for word in WordIterator::new(original, true) {
+        for word in WordIterator::new(original, self.globbing()) {
+            let word = word?;
             if let WordToken::Brace(_) = word {
                 contains_brace = true;
             }
@@ -181,16 +299,21 @@ pub trait Expander: Sized {
     }
 }
 
+/// The selection of every element of an array, each as its own word even when quoted
+const ALL_WORDS: &str = "@";
+
 impl<T: Expander> ExpanderInternal for T {}
 
-trait ExpanderInternal: Expander {
+pub(crate) trait ExpanderInternal: Expander {
     fn expand_process<'a>(
         &mut self,
         current: &mut types::Str,
         command: &str,
+        stderr: bool,
         selection: &Option<&'a str>,
     ) -> Result<(), Self::Error> {
-        let result = self.command(command, true)?;
+        let result =
+            if stderr { self.command_with_stderr(command)? } else { self.command(command, true)? };
         self.slice(current, result.trim_end_matches('\n'), selection)
     }
 
@@ -229,12 +352,8 @@ trait ExpanderInternal: Expander {
         elements: &[&str],
         selection: &Option<&str>,
     ) -> Result<Args, Self::Error> {
-        let selection = if let Some(selection) = selection {
-            let value = self.expand_string(selection)?.join(" ");
-            value.parse::<Select<types::Str>>().map_err(|_| Error::IndexParsingError(value))?
-        } else {
-            Select::All
-        };
+        let index = *selection;
+        let selection = self.array_selection(index)?;
         match selection {
             Select::All => {
                 let mut collected = Args::new();
@@ -246,6 +365,7 @@ trait ExpanderInternal: Expander {
             Select::Index(index) => self.array_nth(elements, index).map(|el| args![el]),
             Select::Range(range) => self.array_range(elements, range),
             Select::Key(key) => Err(Error::KeyOnArray(key.to_string())),
+            Select::KeyRange(..) => Err(Error::KeyOnArray(index.unwrap_or_default().into())),
         }
     }
 
@@ -280,7 +400,7 @@ trait ExpanderInternal: Expander {
             expanded.extend(self.expand_string(element)?);
         }
         if let Some((start, length)) = range.bounds(expanded.len()) {
-            Ok(expanded.into_iter().skip(start).take(length).collect())
+            Ok(range.step(expanded.into_iter().skip(start).take(length)).collect())
         } else {
             Err(Error::InvalidRange { length: elements.len(), range })
         }
@@ -303,6 +423,15 @@ trait ExpanderInternal: Expander {
         }
     }
 
+    /// Expand `$?[selection]`, the statuses of the last commands from the most recent one
+    fn statuses(&mut self, output: &mut types::Str, selection: &str) -> Result<(), Self::Error> {
+        let value = self.expand_string(selection)?.join(" ");
+        let selection =
+            value.parse::<Select<types::Str>>().map_err(|_| Error::IndexParsingError(value))?;
+        output.push_str(&self.array("?", &selection)?.join(" "));
+        Ok(())
+    }
+
     fn slice<'a, S: AsRef<str>>(
         &mut self,
         output: &mut types::Str,
@@ -332,12 +461,12 @@ trait ExpanderInternal: Expander {
                 Select::Range(range) => {
                     let graphemes = UnicodeSegmentation::graphemes(expanded.as_ref(), true);
                     if let Some((start, length)) = range.bounds(graphemes.clone().count()) {
-                        graphemes.skip(start).take(length).for_each(|str| {
+                        range.step(graphemes.skip(start).take(length)).for_each(|str| {
                             output.push_str(str.as_ref());
                         });
                     }
                 }
-                Select::Key(_) => (),
+                Select::Key(_) | Select::KeyRange(..) => (),
             }
         } else {
             output.push_str(expanded.as_ref())
@@ -350,6 +479,7 @@ trait ExpanderInternal: Expander {
         let mut contains_brace = false;
 
         for word in WordIterator::new(original, false) {
+            let word = word?;
             if let WordToken::Brace(_) = word {
                 contains_brace = true;
             }
@@ -361,6 +491,29 @@ trait ExpanderInternal: Expander {
         self.expand_tokens(&token_buffer, contains_brace)
     }
 
+    /// Expand the key of one end of a key range, such as the `$a` of `[$a..$b]`.
+    fn key_bound(&mut self, bound: Bound<&str>) -> Result<Bound<types::Str>, Self::Error> {
+        Ok(match bound {
+            Bound::Included(key) => Bound::Included(self.expand_string(key)?.join(" ").into()),
+            Bound::Excluded(key) => Bound::Excluded(self.expand_string(key)?.join(" ").into()),
+            Bound::Unbounded => Bound::Unbounded,
+        })
+    }
+
+    /// Parse the selection of an array variable, after expanding it.
+    fn array_selection(&mut self, index: Option<&str>) -> Result<Select<types::Str>, Self::Error> {
+        match index {
+            Some(index) if index != ALL_WORDS => {
+                if let Some((start, end)) = parse_key_range(index) {
+                    return Ok(Select::KeyRange(self.key_bound(start)?, self.key_bound(end)?));
+                }
+                let value = self.expand_string(index)?.join(" ");
+                value.parse::<Select<types::Str>>().map_err(|_| Error::IndexParsingError(value))
+            }
+            _ => Ok(Select::All),
+        }
+    }
+
     #[auto_enum]
     fn expand_single_array_token(&mut self, token: &WordToken<'_>) -> Result<Args, Self::Error> {
         match *token {
@@ -396,35 +549,29 @@ trait ExpanderInternal: Expander {
                     Ok(out)
                 }
             }
-            WordToken::ArrayVariable(array, quoted, ref index) => {
-                let index = if let Some(index) = index {
-                    let value = self.expand_string(index)?.join(" ");
-                    value
-                        .parse::<Select<types::Str>>()
-                        .map_err(|_| Error::IndexParsingError(value))?
-                } else {
-                    Select::All
-                };
-                let array = self.array(array, &index)?;
-                if quoted {
+            WordToken::ArrayVariable(array, quoted, index) => {
+                let selection = self.array_selection(index)?;
+                let array = self.array(array, &selection)?;
+                // The `[@]` selection keeps each element as a word, even in double quotes
+                if quoted && index != Some(ALL_WORDS) {
                     Ok(args![types::Str::from(array.join(" "))])
                 } else {
                     Ok(array)
                 }
             }
-            WordToken::ArrayProcess(command, quoted, ref index) => {
+            WordToken::ArrayProcess(command, quoted, stderr, ref index) => {
                 crate::IonPool::string(|output| {
-                    self.expand_process(output, command, &None)?;
+                    self.expand_process(output, command, stderr, &None)?;
+                    let ifs = self.field_separators();
+                    let fields = split_fields(output, ifs.as_ref().map(AsRef::as_ref));
 
                     if quoted {
                         Ok(args!(format!(
                             "{}",
-                            self.slice_array(output.split_whitespace(), index)?
-                                .into_iter()
-                                .format(" ")
+                            self.slice_array(fields, index)?.into_iter().format(" ")
                         )))
                     } else {
-                        self.slice_array(output.split_whitespace(), index)
+                        self.slice_array(fields, index)
                     }
                 })
             }
@@ -450,13 +597,17 @@ trait ExpanderInternal: Expander {
                 self.expand(&mut output, &mut expanded_words, text.as_ref(), do_glob, tilde)?
             }
             WordToken::Whitespace(text) => output.push_str(text),
-            WordToken::Process(command, ref index) => {
-                self.expand_process(&mut output, command, index)?
+            WordToken::Process(command, stderr, ref index) => {
+                self.expand_process(&mut output, command, stderr, index)?
             }
+            WordToken::Variable("?", Some(selection)) => self.statuses(&mut output, selection)?,
             WordToken::Variable(text, ref index) => {
                 self.slice(&mut output, self.string(text)?, index)?;
             }
-            WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s),
+            WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s)?,
+            WordToken::ProcessSubstitution(command, input) => {
+                output.push_str(&self.substitute_process(command, input)?)
+            }
             _ => unreachable!(),
         }
 
@@ -501,7 +652,7 @@ trait ExpanderInternal: Expander {
         let expanded: types::Str = if tilde { self.tilde(&concat)? } else { concat };
 
         if do_glob {
-            match glob_for_os(&expanded) {
+            match glob_for_os(&expanded, self.glob_options()) {
                 Ok(var) => {
                     let prev_size = expanded_words.len();
                     expanded_words
@@ -528,6 +679,25 @@ trait ExpanderInternal: Expander {
             return self.expand_single_array_token(token);
         }
 
+        // Like `"x${array[@]}y"`, the text around the elements joins the first and the last words
+        let all_words = token_buffer.iter().position(|token| {
+            matches!(token, WordToken::ArrayVariable(_, true, Some(index)) if *index == ALL_WORDS)
+        });
+        if let (false, Some(position)) = (contains_brace, all_words) {
+            let prefix = self.expand_tokens(&token_buffer[..position], false)?.join(" ");
+            let mut words = self.expand_single_array_token(&token_buffer[position])?;
+            let mut suffix = self.expand_tokens(&token_buffer[position + 1..], false)?.into_iter();
+            if words.is_empty() {
+                words.push(types::Str::new());
+            }
+            words[0].insert_str(0, &prefix);
+            if let (Some(last), Some(text)) = (words.last_mut(), suffix.next()) {
+                last.push_str(&text);
+            }
+            words.extend(suffix);
+            return Ok(words);
+        }
+
         let mut output = types::Str::new();
         let mut expanded_words = Args::new();
         let tokens: &mut Vec<BraceToken> = &mut Vec::new();
@@ -556,21 +726,17 @@ trait ExpanderInternal: Expander {
                     }
                     output.pop(); // Pop out the last unneeded whitespace token
                 }
-                WordToken::ArrayVariable(array, _, ref index) => {
-                    let index = if let Some(index) = index {
-                        let value = self.expand_string(index)?.join(" ");
-                        value
-                            .parse::<Select<types::Str>>()
-                            .map_err(|_| Error::IndexParsingError(value))?
-                    } else {
-                        Select::All
-                    };
+                WordToken::ArrayVariable(array, _, index) => {
+                    let index = self.array_selection(*index)?;
                     let _ =
                         write!(&mut output, "{}", self.array(array, &index)?.iter().format(" "));
                 }
-                WordToken::ArrayProcess(command, _, ref index)
-                | WordToken::Process(command, ref index) => {
-                    self.expand_process(&mut output, command, index)?;
+                WordToken::ArrayProcess(..) => {
+                    let array = self.expand_single_array_token(word)?;
+                    let _ = write!(&mut output, "{}", array.iter().format(" "));
+                }
+                WordToken::Process(command, stderr, ref index) => {
+                    self.expand_process(&mut output, command, *stderr, index)?;
                 }
                 WordToken::ArrayMethod(ref method, _) => {
                     method.handle(&mut output, self)?;
@@ -593,10 +759,16 @@ trait ExpanderInternal: Expander {
                 WordToken::Whitespace(text) => {
                     output.push_str(text);
                 }
+                WordToken::Variable("?", Some(selection)) => {
+                    self.statuses(&mut output, selection)?
+                }
                 WordToken::Variable(text, ref index) => {
                     self.slice(&mut output, self.string(text)?, index)?;
                 }
-                WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s),
+                WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s)?,
+                WordToken::ProcessSubstitution(command, input) => {
+                    output.push_str(&self.substitute_process(command, *input)?)
+                }
             }
         }
 
@@ -616,8 +788,8 @@ trait ExpanderInternal: Expander {
             }
 
             Ok(expanded_words.into_iter().fold(Args::new(), |mut array, word| {
-                if word.find('*').is_some() {
-                    if let Ok(paths) = glob_for_os(&word) {
+                if word.find('*').is_some() && self.globbing() {
+                    if let Ok(paths) = glob_for_os(&word, self.glob_options()) {
                         array.extend(paths.map(|path| {
                             if let Ok(path_buf) = path {
                                 (*path_buf.to_string_lossy()).into()
@@ -646,14 +818,30 @@ trait ExpanderInternal: Expander {
     /// x * 5 + y => 22
     /// ```
     /// if `x=5` and `y=7`
-    fn expand_arithmetic(&self, output: &mut types::Str, input: &str) {
+    ///
+    /// An expression prefixed with `i`, as in `i 7 / 2`, is truncated to an integer.
+    ///
+    /// Commands substituted with `$(cmd)` are run first, and must output a number.
+    fn expand_arithmetic(
+        &mut self,
+        output: &mut types::Str,
+        input: &str,
+    ) -> Result<(), Self::Error> {
+        let substituted = self.substitute_arithmetic(input)?;
+        let (input, integer) = integer_mode(&substituted);
         crate::IonPool::string(|intermediate| {
             crate::IonPool::string(|varbuf| {
                 let flush = |var: &mut types::Str, out: &mut types::Str| {
                     if !var.is_empty() {
                         // We have reached the end of a potential variable, so we expand it and push
-                        // it onto the result
-                        out.push_str(self.string(var).as_ref().unwrap_or(var));
+                        // it onto the result. Integral values are given as integers, so that they
+                        // may be used with the bitwise operators.
+                        let value = self.string(var);
+                        let value = value.as_ref().unwrap_or(var);
+                        match integral(value) {
+                            Some(value) => out.push_str(&value.to_string()),
+                            None => out.push_str(value),
+                        }
                     }
                 };
 
@@ -673,18 +861,108 @@ trait ExpanderInternal: Expander {
                 flush(varbuf, intermediate);
 
                 output.push_str(&match calc::eval(intermediate) {
-                    Ok(s) => s.to_string(),
+                    Ok(s) => format_arithmetic(&s.to_string(), integer),
                     Err(e) => e.to_string(),
                 });
             });
         });
+        Ok(())
+    }
+
+    /// Replace the commands substituted in an arithmetic expression with their output.
+    fn substitute_arithmetic(&mut self, input: &str) -> Result<types::Str, Self::Error> {
+        let mut output = types::Str::with_capacity(input.len());
+        let mut rest = input;
+        while let Some(start) = rest.find("$(") {
+            output.push_str(&rest[..start]);
+            let mut level = 0;
+            let end = rest[start + 2..].bytes().position(|c| match c {
+                b'(' => {
+                    level += 1;
+                    false
+                }
+                b')' if level == 0 => true,
+                b')' => {
+                    level -= 1;
+                    false
+                }
+                _ => false,
+            });
+            let end = match end {
+                Some(end) => start + 2 + end,
+                None => return Err(WordError::UnterminatedProcess.into()),
+            };
+
+            let command = &rest[start + 2..end];
+            let value = self.command(command, true)?;
+            let value = value.trim();
+            if value.parse::<f64>().is_err() {
+                return Err(Error::NotANumber { command: command.into(), output: value.into() });
+            }
+            output.push_str(value);
+            rest = &rest[end + 1..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+/// Splits the `i` prefix forcing integer arithmetic from an expression.
+///
+/// The prefix must be followed by whitespace and an operand, so that `i * 2` still multiplies
+/// the variable `i`.
+fn integer_mode(input: &str) -> (&str, bool) {
+    let input = input.trim_start();
+    match input.strip_prefix('i') {
+        Some(rest) if rest.starts_with(char::is_whitespace) => {
+            let rest = rest.trim_start();
+            match rest.chars().next() {
+                Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '(' || c == '.' => {
+                    (rest, true)
+                }
+                _ => (input, false),
+            }
+        }
+        _ => (input, false),
+    }
+}
+
+/// The value of a number without a fractional part, if it fits in an integer.
+fn integral(value: &str) -> Option<i64> {
+    let value = value.trim();
+    value.parse::<i64>().ok().or_else(|| {
+        let float = value.parse::<f64>().ok()?;
+        // Past 2^63, the float does not fit, as i64::MAX is rounded up when converted
+        if float.fract() == 0.0 && float >= i64::MIN as f64 && float < i64::MAX as f64 {
+            Some(float as i64)
+        } else {
+            None
+        }
+    })
+}
+
+/// Formats the result of an arithmetic expansion, without a trailing `.0` for integral values.
+///
+/// In integer mode, the result is truncated toward zero.
+fn format_arithmetic(value: &str, integer: bool) -> String {
+    if let Some(value) = integral(value) {
+        return value.to_string();
+    }
+    match value.trim().parse::<f64>() {
+        Ok(float) if integer && float.is_nan() => "not a number".into(),
+        Ok(float) if integer => integral(&float.trunc().to_string())
+            .map_or_else(|| "integer overflow".into(), |value| value.to_string()),
+        _ => value.into(),
     }
 }
 
 /// Resolveds glob pattern like '*'. On redox os it resolves the prefix **file:/** as schemes for
 /// files.
-fn glob_for_os(pattern: &str) -> core::result::Result<glob::Paths, glob::PatternError> {
-    glob(prepare_path_for_os(pattern).as_ref())
+fn glob_for_os(
+    pattern: &str,
+    options: GlobOptions,
+) -> core::result::Result<glob::Paths, glob::PatternError> {
+    glob_with(prepare_path_for_os(pattern).as_ref(), options.into())
 }
 
 fn prepare_path_for_os<'a>(to_trim_away: &'a str) -> Cow<'a, str> {
@@ -748,6 +1026,7 @@ pub(crate) mod test {
         ) -> Result<types::Args, Self::Error> {
             match variable {
                 "ARRAY" => Ok(args!["a", "b", "c"].to_owned()),
+                "SPACED" => Ok(args!["a b", "c  d"]),
                 _ => Err(Error::VarNotFound(variable.into())),
             }
         }
@@ -760,6 +1039,25 @@ pub(crate) mod test {
             Ok(cmd.into())
         }
 
+        fn command_succeeds(&mut self, cmd: &str) -> Result<bool, Self::Error> {
+            Ok(!cmd.starts_with("false"))
+        }
+
+        fn substitute_process(
+            &mut self,
+            _cmd: &str,
+            _input: bool,
+        ) -> Result<types::Str, Self::Error> {
+            Ok("/dev/fd/63".into())
+        }
+
+        fn with_vars<T, F>(&mut self, _vars: &[(&str, &str)], f: F) -> Result<T, Self::Error>
+        where
+            F: FnOnce(&mut Self) -> Result<T, Self::Error>,
+        {
+            f(self)
+        }
+
         fn tilde(&self, input: &str) -> Result<types::Str, Self::Error> { Ok(input.into()) }
 
         fn map_keys<'a>(&'a self, name: &str) -> Result<Args, Self::Error> {
@@ -769,6 +1067,10 @@ pub(crate) mod test {
         fn map_values<'a>(&'a self, name: &str) -> Result<Args, Self::Error> {
             Err(Error::VarNotFound(name.into()))
         }
+
+        fn map_entries(&self, name: &str) -> Result<Args, Self::Error> {
+            Err(Error::VarNotFound(name.into()))
+        }
     }
 
     #[test]
@@ -776,15 +1078,37 @@ pub(crate) mod test {
         let mut output = types::Str::new();
 
         let line = " Mary   had\ta little  \n\t lamb😉😉\t";
-        DummyExpander.expand_process(&mut output, line, &None).unwrap();
+        DummyExpander.expand_process(&mut output, line, false, &None).unwrap();
         assert_eq!(output.as_str(), line);
 
         output.clear();
         let line = "foo not bar😉😉\n\n";
-        DummyExpander.expand_process(&mut output, line, &None).unwrap();
+        DummyExpander.expand_process(&mut output, line, false, &None).unwrap();
         assert_eq!(output.as_str(), "foo not bar😉😉");
     }
 
+    #[test]
+    fn process_selection() {
+        assert_eq!(args!["b", "c"], DummyExpander.expand_string("@(a b c d)[1..3]").unwrap());
+        assert_eq!(args!["b cx"], DummyExpander.expand_string("@(a b c d)[1..3]x").unwrap());
+        assert_eq!(args!["b c"], DummyExpander.expand_string("\"@(a b c d)[1..3]\"").unwrap());
+        assert_eq!(args!["d"], DummyExpander.expand_string("@(a b c d)[-1]").unwrap());
+
+        assert_eq!(args!["abcde"], DummyExpander.expand_string("$(abcdefgh)[0..5]").unwrap());
+        assert_eq!(args!["abcdex"], DummyExpander.expand_string("$(abcdefgh)[0..5]x").unwrap());
+        assert_eq!(args!["h"], DummyExpander.expand_string("\"$(abcdefgh)[-1]\"").unwrap());
+    }
+
+    #[test]
+    fn unterminated_syntax_is_an_error() {
+        match DummyExpander.expand_string("$(abc") {
+            Err(Error::WordError(WordError::UnterminatedProcess)) => (),
+            result => panic!("expected an unterminated process, got {:?}", result),
+        }
+        assert!(DummyExpander.expand_string("{a,b").is_err());
+        assert!(DummyExpander.expand_string("$var[1").is_err());
+    }
+
     #[test]
     fn expand_variable_normal_variable() {
         let input = "$FOO:NOT:$BAR";
@@ -793,6 +1117,15 @@ pub(crate) mod test {
         assert_eq!(args![expected], expanded);
     }
 
+    #[test]
+    fn expand_multiline_quotes() {
+        let expanded = DummyExpander.expand_string("\"first $FOO\n  second\n$BAR\"").unwrap();
+        assert_eq!(args!["first FOOBAR\n  second\nBAR"], expanded);
+
+        let expanded = DummyExpander.expand_string("'first $FOO\n  second\n$BAR'").unwrap();
+        assert_eq!(args!["first $FOO\n  second\n$BAR"], expanded);
+    }
+
     #[test]
     fn expand_braces() {
         let line = "pro{digal,grammer,cessed,totype,cedures,ficiently,ving,spective,jections}";
@@ -877,6 +1210,80 @@ pub(crate) mod test {
         assert_eq!(expected, DummyExpander.expand_string(line).unwrap());
     }
 
+    #[test]
+    fn arith_substitution() {
+        let substituted = DummyExpander.substitute_arithmetic(" $(4) * $( 2.5 ) + (1)").unwrap();
+        assert_eq!(&*substituted, " 4 * 2.5 + (1)");
+        match DummyExpander.expand_string("$(($(echo count) + 1))") {
+            Err(Error::NotANumber { command, output }) => {
+                assert_eq!(command, "echo count");
+                assert_eq!(output, "echo count");
+            }
+            result => panic!("expected a non-number error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn arith_mixed_and_bitwise() {
+        let cases = vec![
+            (args!["3.5"], "$((7 / 2))"),
+            (args!["3"], "$((1.5 + 1.5))"),
+            (args!["3"], "$((i 7 / 2))"),
+            (args!["-3"], "$((i (0 - 7) / 2))"),
+            (args!["2"], "$((6 & 3))"),
+            (args!["7"], "$((6 | 3))"),
+            (args!["5"], "$((6 ^ 3))"),
+            (args!["16"], "$((1 << 4))"),
+            (args!["4"], "$((16 >> 2))"),
+        ];
+        for (expected, input) in cases {
+            assert_eq!(expected, DummyExpander.expand_string(input).unwrap(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn arith_integer_mode() {
+        assert_eq!(integer_mode("i 7 / 2"), ("7 / 2", true));
+        assert_eq!(integer_mode(" i  (a + b)"), ("(a + b)", true));
+        assert_eq!(integer_mode("i * 2"), ("i * 2", false));
+        assert_eq!(integer_mode("i - 1"), ("i - 1", false));
+        assert_eq!(integer_mode("i"), ("i", false));
+        assert_eq!(integer_mode("ix + 1"), ("ix + 1", false));
+    }
+
+    #[test]
+    fn arith_result_format() {
+        assert_eq!(format_arithmetic("3.0", false), "3");
+        assert_eq!(format_arithmetic("-0.0", false), "0");
+        assert_eq!(format_arithmetic("3.5", false), "3.5");
+        assert_eq!(format_arithmetic("3.5", true), "3");
+        assert_eq!(format_arithmetic("-3.5", true), "-3");
+        assert_eq!(format_arithmetic("9223372036854775807", true), "9223372036854775807");
+        assert_eq!(format_arithmetic("1e30", false), "1e30");
+        assert_eq!(format_arithmetic("1e30", true), "integer overflow");
+        assert_eq!(format_arithmetic("-inf", true), "integer overflow");
+        assert_eq!(format_arithmetic("NaN", true), "not a number");
+        assert_eq!(integral("6.0"), Some(6));
+        assert_eq!(integral("6.5"), None);
+        assert_eq!(integral("abc"), None);
+    }
+
+    #[test]
+    fn quoted_array_words() {
+        let cases = vec![
+            (args!["a b c  d"], "\"@SPACED\""),
+            (args!["a b", "c  d"], "\"@SPACED[@]\""),
+            (args!["a b", "c  d"], "@SPACED[@]"),
+            (args!["xa b", "c  d"], "x\"@SPACED[@]\""),
+            (args!["xa b", "c  dy"], "\"x@SPACED[@]y\""),
+            (args!["a b", "c  da b", "c  d"], "\"@SPACED[@]@SPACED[@]\""),
+            (args!["xa b c  d y"], "\"x@SPACED y\""),
+        ];
+        for (expected, input) in cases {
+            assert_eq!(expected, DummyExpander.expand_string(input).unwrap(), "{}", input);
+        }
+    }
+
     #[test]
     fn inline_expression() {
         let cases =
@@ -5,12 +5,15 @@ use super::methods::{ArrayMethod, Pattern, StringMethod};
 use crate::parser::lexers::ArgumentSplitter;
 pub use crate::ranges::{Select, SelectWithSize};
 use std::borrow::Cow;
+use thiserror::Error;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 enum Quotes {
     None,
     Single,
     Double,
+    /// Within `"""`, where newlines and double quotes are literal
+    Triple,
 }
 
 /// Unescapes filenames to be passed into the completer
@@ -90,6 +93,32 @@ fn index_until_character(input: &str, characters: &[u8], ret_on_match: bool) ->
     (i, last_character)
 }
 
+/// A construct of the input was left open
+#[derive(Debug, Error, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum WordError {
+    /// An arithmetic expression was not closed with `))`
+    #[error("unterminated arithmetic expression")]
+    UnterminatedArithmetic,
+    /// An array expression was not closed with `]`
+    #[error("unterminated array expression")]
+    UnterminatedArray,
+    /// A brace expansion was not closed with `}`
+    #[error("unterminated brace expansion")]
+    UnterminatedBrace,
+    /// A process expansion was not closed with `)`
+    #[error("unterminated process expansion")]
+    UnterminatedProcess,
+    /// A braced variable was not closed with `}`
+    #[error("unterminated braced variable")]
+    UnterminatedBracedVariable,
+    /// The arguments of a method were not closed with `)`
+    #[error("unterminated method")]
+    UnterminatedMethod,
+    /// The selection of a variable was not closed with `]`
+    #[error("unterminated index or range")]
+    UnterminatedSelection,
+}
+
 /// Terminal tokens for a Ion script
 #[derive(Debug, PartialEq, Clone)]
 pub enum WordToken<'a> {
@@ -106,16 +135,21 @@ pub enum WordToken<'a> {
     Variable(&'a str, Option<&'a str>),
     /// An array or map-like variable
     ArrayVariable(&'a str, bool, Option<&'a str>),
-    /// A process that should expand to an array
-    ArrayProcess(&'a str, bool, Option<&'a str>),
-    /// A process that expands to a scalar value
-    Process(&'a str, Option<&'a str>),
+    /// A process that should expand to an array, whose standard error is also captured if the
+    /// third element is set
+    ArrayProcess(&'a str, bool, bool, Option<&'a str>),
+    /// A process that expands to a scalar value, whose standard error is also captured if the
+    /// second element is set
+    Process(&'a str, bool, Option<&'a str>),
     /// A method on a scalar value
     StringMethod(StringMethod<'a>),
     /// A method on a array value
     ArrayMethod(ArrayMethod<'a>, bool),
     /// An arithmetic expression
     Arithmetic(&'a str),
+    /// A process substituted by a path, whose standard input reads what is written to the path
+    /// if the second element is set
+    ProcessSubstitution(&'a str, bool),
 }
 
 /// Iterate over the terminal tokens of the parsed text
@@ -129,7 +163,10 @@ pub struct WordIterator<'a> {
 }
 
 impl<'a> WordIterator<'a> {
-    fn arithmetic_expression<I: Iterator<Item = u8>>(&mut self, iter: &mut I) -> WordToken<'a> {
+    fn arithmetic_expression<I: Iterator<Item = u8>>(
+        &mut self,
+        iter: &mut I,
+    ) -> Result<WordToken<'a>, WordError> {
         let _ = iter.next();
 
         let mut paren: i8 = 0;
@@ -143,7 +180,7 @@ impl<'a> WordIterator<'a> {
                         let _ = iter.next();
                         let output = &self.data[start..self.read];
                         self.read += 2;
-                        return WordToken::Arithmetic(output);
+                        return Ok(WordToken::Arithmetic(output));
                     } else {
                         paren -= 1;
                     }
@@ -152,7 +189,7 @@ impl<'a> WordIterator<'a> {
             }
             self.read += 1;
         }
-        panic!("ion: fatal syntax error: unterminated arithmetic expression");
+        Err(WordError::UnterminatedArithmetic)
     }
 
     fn glob_check<I>(&mut self, iterator: &mut I, is_text_adjacent: bool) -> bool
@@ -202,7 +239,7 @@ impl<'a> WordIterator<'a> {
     }
 
     /// Contains the grammar for parsing array expression syntax
-    fn array<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+    fn array<I>(&mut self, iterator: &mut I) -> Result<WordToken<'a>, WordError>
     where
         I: Iterator<Item = u8>,
     {
@@ -223,12 +260,12 @@ impl<'a> WordIterator<'a> {
                             ArgumentSplitter::new(&self.data[start..self.read]).collect::<Vec<_>>();
                         self.read += 1;
 
-                        return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+                        return Ok(if let Some(&b'[') = self.data.as_bytes().get(self.read) {
                             let _ = iterator.next();
-                            WordToken::Array(elements, Some(self.read_selection(iterator)))
+                            WordToken::Array(elements, Some(self.read_selection(iterator)?))
                         } else {
                             WordToken::Array(elements, None)
-                        };
+                        });
                     } else {
                         level -= 1;
                     }
@@ -238,11 +275,11 @@ impl<'a> WordIterator<'a> {
             self.read += 1;
         }
 
-        panic!("ion: fatal error with syntax validation: unterminated array expression")
+        Err(WordError::UnterminatedArray)
     }
 
     /// Contains the grammar for parsing brace expansion syntax
-    fn braces<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+    fn braces<I>(&mut self, iterator: &mut I) -> Result<WordToken<'a>, WordError>
     where
         I: Iterator<Item = u8>,
     {
@@ -266,7 +303,7 @@ impl<'a> WordIterator<'a> {
                     if level == 0 {
                         elements.push(&self.data[start..self.read]);
                         self.read += 1;
-                        return WordToken::Brace(elements);
+                        return Ok(WordToken::Brace(elements));
                     } else {
                         level -= 1;
                     }
@@ -278,68 +315,68 @@ impl<'a> WordIterator<'a> {
             self.read += 1;
         }
 
-        panic!("ion: fatal error with syntax validation: unterminated brace")
+        Err(WordError::UnterminatedBrace)
+    }
+
+    /// The length of the `(` or `&(` that opens a process at `index`, if there is one.
+    fn process_opening(&self, index: usize) -> Option<usize> {
+        let bytes = self.data.as_bytes().get(index..).unwrap_or_default();
+        if bytes.starts_with(b"(") {
+            Some(1)
+        } else if bytes.starts_with(b"&(") {
+            Some(2)
+        } else {
+            None
+        }
     }
 
     /// Contains the logic for parsing array subshell syntax.
-    fn array_process<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+    fn array_process<I>(
+        &mut self,
+        iterator: &mut I,
+        stderr: bool,
+    ) -> Result<WordToken<'a>, WordError>
     where
         I: Iterator<Item = u8>,
     {
         let _ = iterator.next();
-        let start = self.read;
-        let mut level = 0;
-        while let Some(character) = iterator.next() {
-            match character {
-                _ if self.backsl => self.backsl = false,
-                b'\\' => self.backsl = true,
-                b'\'' if self.quotes == Quotes::Single => self.quotes = Quotes::None,
-                b'\'' if self.quotes == Quotes::None => self.quotes = Quotes::Single,
-                b'"' if self.quotes == Quotes::Double => self.quotes = Quotes::None,
-                b'"' if self.quotes == Quotes::None => self.quotes = Quotes::Double,
-                b'@' if self.quotes != Quotes::Single => {
-                    if self.data.as_bytes()[self.read + 1] == b'(' {
-                        level += 1;
-                    }
-                }
-                b')' if self.quotes != Quotes::Single => {
-                    if level == 0 {
-                        let array_process_contents = &self.data[start..self.read];
-                        self.read += 1;
-                        return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
-                            let _ = iterator.next();
-                            WordToken::ArrayProcess(
-                                array_process_contents,
-                                self.quotes == Quotes::Double,
-                                Some(self.read_selection(iterator)),
-                            )
-                        } else {
-                            WordToken::ArrayProcess(
-                                array_process_contents,
-                                self.quotes == Quotes::Double,
-                                None,
-                            )
-                        };
-                    } else {
-                        level -= 1;
-                    }
-                }
-                _ => (),
-            }
-            self.read += 1;
-        }
-
-        // The validator at the frontend should catch unterminated processes.
-        panic!("ion: fatal error with syntax validation: unterminated array process");
+        let array_process_contents = self.process_command(iterator)?;
+        Ok(if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+            let _ = iterator.next();
+            WordToken::ArrayProcess(
+                array_process_contents,
+                self.double_quoted(),
+                stderr,
+                Some(self.read_selection(iterator)?),
+            )
+        } else {
+            WordToken::ArrayProcess(array_process_contents, self.double_quoted(), stderr, None)
+        })
     }
 
     /// Contains the logic for parsing subshell syntax.
-    fn process<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+    fn process<I>(&mut self, iterator: &mut I, stderr: bool) -> Result<WordToken<'a>, WordError>
+    where
+        I: Iterator<Item = u8>,
+    {
+        let output = self.process_command(iterator)?;
+        Ok(if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+            let _ = iterator.next();
+            WordToken::Process(output, stderr, Some(self.read_selection(iterator)?))
+        } else {
+            WordToken::Process(output, stderr, None)
+        })
+    }
+
+    /// Reads the command of a process up to its closing parenthesis, which is skipped.
+    fn process_command<I>(&mut self, iterator: &mut I) -> Result<&'a str, WordError>
     where
         I: Iterator<Item = u8>,
     {
         let start = self.read;
-        let mut level = 0;
+        // The quotes around each of the processes, which are nested like the processes
+        let mut outer_quotes = vec![self.quotes];
+        self.quotes = Quotes::None;
         while let Some(character) = iterator.next() {
             match character {
                 _ if self.backsl => self.backsl = false,
@@ -348,34 +385,29 @@ impl<'a> WordIterator<'a> {
                 b'\'' if self.quotes == Quotes::None => self.quotes = Quotes::Single,
                 b'"' if self.quotes == Quotes::Double => self.quotes = Quotes::None,
                 b'"' if self.quotes == Quotes::None => self.quotes = Quotes::Double,
-                b'$' if self.quotes != Quotes::Single => {
-                    if self.data.as_bytes()[self.read + 1] == b'(' {
-                        // Pop out the '(' char
-                        iterator.next();
-                        self.read += 1;
-                        level += 1;
+                b'$' | b'@' if self.quotes != Quotes::Single => {
+                    if let Some(length) = self.process_opening(self.read + 1) {
+                        // Pop out the '(' or '&(' chars
+                        for _ in 0..length {
+                            iterator.next();
+                        }
+                        self.read += length;
+                        outer_quotes.push(self.quotes);
+                        self.quotes = Quotes::None;
                     }
                 }
-                b'@' if self.quotes != Quotes::Single => {
-                    if self.data.as_bytes()[self.read + 1] == b'(' {
-                        // Pop out the '(' char
-                        iterator.next();
-                        self.read += 1;
-                        level += 1;
-                    }
+                b'<' | b'>'
+                    if self.quotes == Quotes::None
+                        && self.data.as_bytes().get(self.read + 1) == Some(&b'(') =>
+                {
+                    outer_quotes.push(Quotes::None)
                 }
-                b')' if self.quotes != Quotes::Single => {
-                    if level == 0 {
+                b')' if self.quotes == Quotes::None => {
+                    self.quotes = outer_quotes.pop().unwrap_or(Quotes::None);
+                    if outer_quotes.is_empty() {
                         let output = &self.data[start..self.read];
                         self.read += 1;
-                        return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
-                            let _ = iterator.next();
-                            WordToken::Process(output, Some(self.read_selection(iterator)))
-                        } else {
-                            WordToken::Process(output, None)
-                        };
-                    } else {
-                        level -= 1;
+                        return Ok(output);
                     }
                 }
                 _ => (),
@@ -383,11 +415,10 @@ impl<'a> WordIterator<'a> {
             self.read += 1;
         }
 
-        // The validator at the frontend should catch unterminated processes.
-        panic!("ion: fatal error with syntax validation: unterminated process");
+        Err(WordError::UnterminatedProcess)
     }
 
-    fn braced_array_variable<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+    fn braced_array_variable<I>(&mut self, iterator: &mut I) -> Result<WordToken<'a>, WordError>
     where
         I: Iterator<Item = u8>,
     {
@@ -399,40 +430,37 @@ impl<'a> WordIterator<'a> {
                 b'[' => {
                     let result = WordToken::ArrayVariable(
                         &self.data[start..self.read],
-                        self.quotes == Quotes::Double,
-                        Some(self.read_selection(iterator)),
+                        self.double_quoted(),
+                        Some(self.read_selection(iterator)?),
                     );
                     self.read += 1;
                     if let Some(b'}') = iterator.next() {
-                        return result;
+                        return Ok(result);
                     }
-                    panic!(
-                        "ion: fatal with syntax validation error: unterminated braced array \
-                         expression"
-                    );
+                    return Err(WordError::UnterminatedBracedVariable);
                 }
                 b'}' => {
                     let output = &self.data[start..self.read];
                     self.read += 1;
-                    return WordToken::ArrayVariable(output, self.quotes == Quotes::Double, None);
+                    return Ok(WordToken::ArrayVariable(output, self.double_quoted(), None));
                 }
                 // Only alphanumerical and underscores are allowed in variable names
                 0..=47 | 58..=64 | 91..=94 | 96 | 123..=127 => {
-                    return WordToken::ArrayVariable(
+                    return Ok(WordToken::ArrayVariable(
                         &self.data[start..self.read],
-                        self.quotes == Quotes::Double,
+                        self.double_quoted(),
                         None,
-                    );
+                    ));
                 }
                 _ => (),
             }
             self.read += 1;
         }
-        WordToken::ArrayVariable(&self.data[start..], self.quotes == Quotes::Double, None)
+        Ok(WordToken::ArrayVariable(&self.data[start..], self.double_quoted(), None))
     }
 
     /// Contains the logic for parsing array variable syntax
-    fn array_variable<I>(&mut self, mut iterator: I) -> WordToken<'a>
+    fn array_variable<I>(&mut self, mut iterator: I) -> Result<WordToken<'a>, WordError>
     where
         I: Iterator<Item = u8>,
     {
@@ -460,32 +488,42 @@ impl<'a> WordIterator<'a> {
                                 start = self.read;
                                 while let Some(character) = iterator.next() {
                                     if character == b')' {
-                                        let pattern = &self.data[start..self.read].trim();
                                         self.read += 1;
-                                        return if let Some(&b'[') =
-                                            self.data.as_bytes().get(self.read)
-                                        {
-                                            let _ = iterator.next();
-                                            WordToken::ArrayMethod(
-                                                ArrayMethod::new(
-                                                    method,
-                                                    variable.trim(),
-                                                    Pattern::StringPattern(pattern),
-                                                    Some(self.read_selection(&mut iterator)),
-                                                ),
-                                                self.quotes == Quotes::Double,
-                                            )
-                                        } else {
-                                            WordToken::ArrayMethod(
-                                                ArrayMethod::new(
-                                                    method,
-                                                    variable.trim(),
-                                                    Pattern::StringPattern(pattern),
-                                                    None,
-                                                ),
-                                                self.quotes == Quotes::Double,
-                                            )
-                                        };
+                                        if depth != 0 {
+                                            depth -= 1;
+                                            continue;
+                                        }
+                                        let pattern = &self.data[start..self.read - 1].trim();
+                                        return Ok(
+                                            if let Some(&b'[') = self.data.as_bytes().get(self.read)
+                                            {
+                                                let _ = iterator.next();
+                                                WordToken::ArrayMethod(
+                                                    ArrayMethod::new(
+                                                        method,
+                                                        variable.trim(),
+                                                        Pattern::StringPattern(pattern),
+                                                        Some(self.read_selection(&mut iterator)?),
+                                                    ),
+                                                    self.double_quoted(),
+                                                )
+                                            } else {
+                                                WordToken::ArrayMethod(
+                                                    ArrayMethod::new(
+                                                        method,
+                                                        variable.trim(),
+                                                        Pattern::StringPattern(pattern),
+                                                        None,
+                                                    ),
+                                                    self.double_quoted(),
+                                                )
+                                            },
+                                        );
+                                    } else if character == b'(' {
+                                        depth += 1;
+                                    } else if character == b'\\' {
+                                        self.read += 1;
+                                        let _ = iterator.next();
                                     }
                                     self.read += 1;
                                 }
@@ -495,28 +533,30 @@ impl<'a> WordIterator<'a> {
                                 let variable = &self.data[start..self.read];
                                 self.read += 1;
 
-                                return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
-                                    let _ = iterator.next();
-                                    WordToken::ArrayMethod(
-                                        ArrayMethod::new(
-                                            method,
-                                            variable.trim(),
-                                            Pattern::Whitespace,
-                                            Some(self.read_selection(&mut iterator)),
-                                        ),
-                                        self.quotes == Quotes::Double,
-                                    )
-                                } else {
-                                    WordToken::ArrayMethod(
-                                        ArrayMethod::new(
-                                            method,
-                                            variable.trim(),
-                                            Pattern::Whitespace,
-                                            None,
-                                        ),
-                                        self.quotes == Quotes::Double,
-                                    )
-                                };
+                                return Ok(
+                                    if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+                                        let _ = iterator.next();
+                                        WordToken::ArrayMethod(
+                                            ArrayMethod::new(
+                                                method,
+                                                variable.trim(),
+                                                Pattern::Whitespace,
+                                                Some(self.read_selection(&mut iterator)?),
+                                            ),
+                                            self.double_quoted(),
+                                        )
+                                    } else {
+                                        WordToken::ArrayMethod(
+                                            ArrayMethod::new(
+                                                method,
+                                                variable.trim(),
+                                                Pattern::Whitespace,
+                                                None,
+                                            ),
+                                            self.double_quoted(),
+                                        )
+                                    },
+                                );
                             }
                             b')' => depth -= 1,
                             b'(' => depth += 1,
@@ -525,32 +565,32 @@ impl<'a> WordIterator<'a> {
                         self.read += 1;
                     }
 
-                    panic!("ion: fatal error with syntax validation parsing: unterminated method");
+                    return Err(WordError::UnterminatedMethod);
                 }
                 b'[' => {
-                    return WordToken::ArrayVariable(
+                    return Ok(WordToken::ArrayVariable(
                         &self.data[start..self.read],
-                        self.quotes == Quotes::Double,
-                        Some(self.read_selection(&mut iterator)),
-                    );
+                        self.double_quoted(),
+                        Some(self.read_selection(&mut iterator)?),
+                    ));
                 }
                 // Only alphanumerical and underscores are allowed in variable names
                 0..=47 | 58..=64 | 91..=94 | 96 | 123..=127 => {
-                    return WordToken::ArrayVariable(
+                    return Ok(WordToken::ArrayVariable(
                         &self.data[start..self.read],
-                        self.quotes == Quotes::Double,
+                        self.double_quoted(),
                         None,
-                    );
+                    ));
                 }
                 _ => (),
             }
             self.read += 1;
         }
 
-        WordToken::ArrayVariable(&self.data[start..], self.quotes == Quotes::Double, None)
+        Ok(WordToken::ArrayVariable(&self.data[start..], self.double_quoted(), None))
     }
 
-    fn read_selection<I>(&mut self, iterator: &mut I) -> &'a str
+    fn read_selection<I>(&mut self, iterator: &mut I) -> Result<&'a str, WordError>
     where
         I: Iterator<Item = u8>,
     {
@@ -560,12 +600,12 @@ impl<'a> WordIterator<'a> {
             if let b']' = character {
                 let value = &self.data[start..self.read];
                 self.read += 1;
-                return value;
+                return Ok(value);
             }
             self.read += 1;
         }
 
-        panic!()
+        Err(WordError::UnterminatedSelection)
     }
 
     /// Skips all found spaces until a non white space byte is encountered.
@@ -586,7 +626,7 @@ impl<'a> WordIterator<'a> {
     }
 
     /// Contains the logic for parsing variable syntax
-    fn variable<I>(&mut self, mut iterator: I) -> WordToken<'a>
+    fn variable<I>(&mut self, mut iterator: I) -> Result<WordToken<'a>, WordError>
     where
         I: Iterator<Item = u8>,
     {
@@ -622,24 +662,27 @@ impl<'a> WordIterator<'a> {
                                             continue;
                                         }
                                         let pattern = &self.data[start..self.read - 1].trim();
-                                        return if let Some(&b'[') =
-                                            self.data.as_bytes().get(self.read)
-                                        {
-                                            let _ = iterator.next();
-                                            WordToken::StringMethod(StringMethod {
-                                                method,
-                                                variable: variable.trim(),
-                                                pattern,
-                                                selection: Some(self.read_selection(&mut iterator)),
-                                            })
-                                        } else {
-                                            WordToken::StringMethod(StringMethod {
-                                                method,
-                                                variable: variable.trim(),
-                                                pattern,
-                                                selection: None,
-                                            })
-                                        };
+                                        return Ok(
+                                            if let Some(&b'[') = self.data.as_bytes().get(self.read)
+                                            {
+                                                let _ = iterator.next();
+                                                WordToken::StringMethod(StringMethod {
+                                                    method,
+                                                    variable: variable.trim(),
+                                                    pattern,
+                                                    selection: Some(
+                                                        self.read_selection(&mut iterator)?,
+                                                    ),
+                                                })
+                                            } else {
+                                                WordToken::StringMethod(StringMethod {
+                                                    method,
+                                                    variable: variable.trim(),
+                                                    pattern,
+                                                    selection: None,
+                                                })
+                                            },
+                                        );
                                     } else if character == b'(' {
                                         depth += 1;
                                     } else if character == b'\\' {
@@ -654,22 +697,24 @@ impl<'a> WordIterator<'a> {
                                 let variable = &self.data[start..self.read];
                                 self.read += 1;
 
-                                return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
-                                    let _ = iterator.next();
-                                    WordToken::StringMethod(StringMethod {
-                                        method,
-                                        variable: variable.trim(),
-                                        pattern: " ",
-                                        selection: Some(self.read_selection(&mut iterator)),
-                                    })
-                                } else {
-                                    WordToken::StringMethod(StringMethod {
-                                        method,
-                                        variable: variable.trim(),
-                                        pattern: " ",
-                                        selection: None,
-                                    })
-                                };
+                                return Ok(
+                                    if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+                                        let _ = iterator.next();
+                                        WordToken::StringMethod(StringMethod {
+                                            method,
+                                            variable: variable.trim(),
+                                            pattern: " ",
+                                            selection: Some(self.read_selection(&mut iterator)?),
+                                        })
+                                    } else {
+                                        WordToken::StringMethod(StringMethod {
+                                            method,
+                                            variable: variable.trim(),
+                                            pattern: " ",
+                                            selection: None,
+                                        })
+                                    },
+                                );
                             }
                             b')' => depth -= 1,
                             b'(' => depth += 1,
@@ -678,28 +723,28 @@ impl<'a> WordIterator<'a> {
                         self.read += 1;
                     }
 
-                    panic!("ion: fatal error with syntax validation parsing: unterminated method");
+                    return Err(WordError::UnterminatedMethod);
                 }
                 // Only alphanumerical and underscores are allowed in variable names
                 0..=47 | 58..=64 | 91..=94 | 96 | 123..=127 => {
                     let variable = &self.data[start..self.read];
 
-                    return if character == b'[' {
-                        WordToken::Variable(variable, Some(self.read_selection(&mut iterator)))
+                    return Ok(if character == b'[' {
+                        WordToken::Variable(variable, Some(self.read_selection(&mut iterator)?))
                     } else {
                         WordToken::Variable(variable, None)
-                    };
+                    });
                 }
                 _ => (),
             }
             self.read += 1;
         }
 
-        WordToken::Variable(&self.data[start..], None)
+        Ok(WordToken::Variable(&self.data[start..], None))
     }
 
     // Contains the logic for parsing braced variables
-    fn braced_variable<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+    fn braced_variable<I>(&mut self, iterator: &mut I) -> Result<WordToken<'a>, WordError>
     where
         I: Iterator<Item = u8>,
     {
@@ -709,25 +754,42 @@ impl<'a> WordIterator<'a> {
             if character == b'}' {
                 let output = &self.data[start..self.read];
                 self.read += 1;
-                return WordToken::Variable(output, None);
+                return Ok(WordToken::Variable(output, None));
             }
             self.read += 1;
         }
 
-        // The validator at the frontend should catch unterminated braced variables.
-        panic!("ion: fatal error with syntax validation parsing: unterminated braced variable");
+        Err(WordError::UnterminatedBracedVariable)
     }
 
     /// Creates a new iterator with a given expander
     pub const fn new(data: &'a str, do_glob: bool) -> WordIterator<'a> {
         WordIterator { data, backsl: false, read: 0, quotes: Quotes::None, do_glob }
     }
+
+    /// The number of bytes of the input consumed by the tokens returned so far
+    pub const fn position(&self) -> usize { self.read }
+
+    /// Whether the tokens read are within double quotes, or triple quotes
+    const fn double_quoted(&self) -> bool { matches!(self.quotes, Quotes::Double | Quotes::Triple) }
 }
 
 impl<'a> Iterator for WordIterator<'a> {
-    type Item = WordToken<'a>;
+    type Item = Result<WordToken<'a>, WordError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let word = self.word();
+        if let Some(Err(_)) = word {
+            // Nothing can be read past a syntax error
+            self.read = self.data.len();
+        }
+        word
+    }
+}
+
+impl<'a> WordIterator<'a> {
+    /// Reads the next token, failing on unterminated syntax.
+    fn word(&mut self) -> Option<Result<WordToken<'a>, WordError>> {
         if self.read == self.data.len() {
             return None;
         }
@@ -743,6 +805,18 @@ impl<'a> Iterator for WordIterator<'a> {
             match character {
                 b'\'' => {
                     match self.quotes {
+                        // Raw strings are returned verbatim, up to the closing `'''`
+                        Quotes::None if self.data[start..].starts_with("'''") => {
+                            start += 3;
+                            let len =
+                                self.data[start..].find("'''").unwrap_or(self.data.len() - start);
+                            self.read = self.data.len().min(start + len + 3);
+                            return Some(Ok(WordToken::Normal(
+                                self.data[start..start + len].into(),
+                                glob,
+                                tilde,
+                            )));
+                        }
                         Quotes::None => {
                             start += 1;
                             self.read += 1;
@@ -753,21 +827,21 @@ impl<'a> Iterator for WordIterator<'a> {
 
                             // Do we care if there is no matching single quote? This case is already
                             // handled by src/lib/parser/pipelines.rs
-                            let ret = Some(WordToken::Normal(
+                            let ret = Some(Ok(WordToken::Normal(
                                 self.data[start..self.read].into(),
                                 glob,
                                 tilde,
-                            ));
+                            )));
                             self.read += 1;
                             return ret;
                         }
-                        Quotes::Double => {
+                        Quotes::Double | Quotes::Triple => {
                             self.read += 1;
-                            return Some(WordToken::Normal(
+                            return Some(Ok(WordToken::Normal(
                                 self.data[start..self.read].into(),
                                 glob,
                                 tilde,
-                            ));
+                            )));
                         }
                         Quotes::Single => {
                             // Should never happen
@@ -777,6 +851,12 @@ impl<'a> Iterator for WordIterator<'a> {
                 }
                 b'"' => {
                     match self.quotes {
+                        Quotes::None if self.data[start..].starts_with("\"\"\"") => {
+                            let _ = iterator.nth(1);
+                            start += 3;
+                            self.read += 3;
+                            self.quotes = Quotes::Triple;
+                        }
                         Quotes::None => {
                             start += 1;
                             self.read += 1;
@@ -786,7 +866,7 @@ impl<'a> Iterator for WordIterator<'a> {
                             if peeked_character == Some(&b'"') {
                                 self.read += 1;
                                 self.quotes = Quotes::None;
-                                return Some(WordToken::Normal("".into(), glob, tilde));
+                                return Some(Ok(WordToken::Normal("".into(), glob, tilde)));
                             }
                         }
                         Quotes::Double => {
@@ -794,6 +874,21 @@ impl<'a> Iterator for WordIterator<'a> {
                             self.read += 1;
                             self.quotes = Quotes::None;
                         }
+                        // Only three quotes end the string, others are literal
+                        Quotes::Triple if self.data[start..].starts_with("\"\"\"") => {
+                            self.read += 3;
+                            self.quotes = Quotes::None;
+                            // The string may have been empty
+                            return Some(Ok(WordToken::Normal("".into(), glob, tilde)));
+                        }
+                        Quotes::Triple => {
+                            self.read += 1;
+                            return Some(Ok(WordToken::Normal(
+                                self.data[start..self.read].into(),
+                                glob,
+                                tilde,
+                            )));
+                        }
                         Quotes::Single => {
                             // Should never happen
                             panic!();
@@ -802,7 +897,7 @@ impl<'a> Iterator for WordIterator<'a> {
                 }
                 b'$' => {
                     match self.quotes {
-                        Quotes::None | Quotes::Double => {
+                        Quotes::None | Quotes::Double | Quotes::Triple => {
                             self.read += 1;
                             let peeked_character1 = iterator.peek();
                             match peeked_character1 {
@@ -814,27 +909,41 @@ impl<'a> Iterator for WordIterator<'a> {
                                         self.read += 1;
                                         return Some(self.arithmetic_expression(&mut iterator));
                                     } else {
-                                        return Some(self.process(&mut iterator));
+                                        return Some(self.process(&mut iterator, false));
                                     }
                                 }
+                                Some(b'&') if self.process_opening(self.read) == Some(2) => {
+                                    let _ = iterator.next();
+                                    let _ = iterator.next();
+                                    self.read += 2;
+                                    return Some(self.process(&mut iterator, true));
+                                }
                                 Some(b'{') => {
                                     self.read += 1;
                                     return Some(self.braced_variable(&mut iterator));
                                 }
                                 Some(b' ') => {
-                                    return Some(WordToken::Normal(
+                                    return Some(Ok(WordToken::Normal(
                                         self.data[start..self.read].into(),
                                         glob,
                                         tilde,
-                                    ))
+                                    )))
                                 }
                                 Some(b'?') => {
                                     start += 1;
                                     self.read += 1;
-                                    return Some(WordToken::Variable(
-                                        self.data[start..self.read].into(),
-                                        None,
-                                    ));
+                                    let _ = iterator.next();
+                                    let variable = &self.data[start..self.read];
+                                    // `$?[n]` reads the status history
+                                    if self.data.as_bytes().get(self.read) == Some(&b'[') {
+                                        let _ = iterator.next();
+                                        return Some(self.read_selection(&mut iterator).map(
+                                            |selection| {
+                                                WordToken::Variable(variable, Some(selection))
+                                            },
+                                        ));
+                                    }
+                                    return Some(Ok(WordToken::Variable(variable, None)));
                                 }
                                 _ => return Some(self.variable(&mut iterator)),
                             }
@@ -847,25 +956,30 @@ impl<'a> Iterator for WordIterator<'a> {
                 }
                 b'@' => {
                     match self.quotes {
-                        Quotes::None | Quotes::Double => {
+                        Quotes::None | Quotes::Double | Quotes::Triple => {
                             self.read += 1;
                             let peeked_character1 = iterator.peek();
 
                             match peeked_character1 {
                                 Some(b'(') => {
                                     self.read += 1;
-                                    return Some(self.array_process(&mut iterator));
+                                    return Some(self.array_process(&mut iterator, false));
+                                }
+                                Some(b'&') if self.process_opening(self.read) == Some(2) => {
+                                    let _ = iterator.next();
+                                    self.read += 2;
+                                    return Some(self.array_process(&mut iterator, true));
                                 }
                                 Some(b'{') => {
                                     self.read += 1;
                                     return Some(self.braced_array_variable(&mut iterator));
                                 }
                                 Some(b' ') => {
-                                    return Some(WordToken::Normal(
+                                    return Some(Ok(WordToken::Normal(
                                         self.data[start..self.read].into(),
                                         glob,
                                         tilde,
-                                    ))
+                                    )))
                                 }
                                 _ => return Some(self.array_variable(&mut iterator)),
                             }
@@ -876,18 +990,25 @@ impl<'a> Iterator for WordIterator<'a> {
                         }
                     }
                 }
+                b'<' | b'>' if self.quotes == Quotes::None && iterator.peek() == Some(&b'(') => {
+                    let _ = iterator.next();
+                    self.read += 2;
+                    return Some(self.process_command(&mut iterator).map(|command| {
+                        WordToken::ProcessSubstitution(command, character == b'>')
+                    }));
+                }
                 b'{' => match self.quotes {
                     Quotes::None => {
                         self.read += 1;
                         return Some(self.braces(&mut iterator));
                     }
-                    Quotes::Single | Quotes::Double => {
+                    Quotes::Single | Quotes::Double | Quotes::Triple => {
                         self.read += 1;
-                        return Some(WordToken::Normal(
+                        return Some(Ok(WordToken::Normal(
                             self.data[start..self.read].into(),
                             glob,
                             tilde,
-                        ));
+                        )));
                     }
                 },
                 b'[' => match self.quotes {
@@ -900,30 +1021,30 @@ impl<'a> Iterator for WordIterator<'a> {
                             return Some(self.array(&mut iterator));
                         }
                     }
-                    Quotes::Single | Quotes::Double => {
+                    Quotes::Single | Quotes::Double | Quotes::Triple => {
                         self.read += 1;
-                        return Some(WordToken::Normal(
+                        return Some(Ok(WordToken::Normal(
                             self.data[start..self.read].into(),
                             glob,
                             tilde,
-                        ));
+                        )));
                     }
                 },
                 b'~' => {
                     if self.quotes != Quotes::Single {
                         self.read += 1;
                         tilde = true;
-                        return Some(WordToken::Normal(
+                        return Some(Ok(WordToken::Normal(
                             self.data[start..self.read].into(),
                             glob,
                             tilde,
-                        ));
+                        )));
                     }
                 }
                 b' ' => {
                     let (idx, _) = index_until_character(&self.data[start..], &[b' '], false);
                     self.read += idx;
-                    return Some(WordToken::Whitespace(self.data[start..self.read].into()));
+                    return Some(Ok(WordToken::Whitespace(self.data[start..self.read].into())));
                 }
                 _ => {
                     let (idx, prev_character, last_character, glob_character_found) =
@@ -946,7 +1067,7 @@ impl<'a> Iterator for WordIterator<'a> {
                                     )
                                 }
                             }
-                            Quotes::Double => {
+                            Quotes::Double | Quotes::Triple => {
                                 if glob {
                                     index_until_unescaped_character(
                                         &self.data[self.read..],
@@ -1049,7 +1170,7 @@ impl<'a> Iterator for WordIterator<'a> {
                             output,
                             &[' ', '\'', '"', '$', '@', '~', '?', '*', '{', '(', ')', '}', '\\'],
                         );
-                        return Some(WordToken::Normal(output, glob, tilde));
+                        return Some(Ok(WordToken::Normal(output, glob, tilde)));
                     } else {
                         return None;
                     }
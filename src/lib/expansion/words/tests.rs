@@ -4,6 +4,7 @@ use crate::expansion::test::DummyExpander;
 fn compare(input: &str, expected: &[WordToken<'_>]) {
     let mut correct = 0;
     for (actual, expected) in WordIterator::new(input, true).zip(expected.iter()) {
+        let actual = actual.unwrap();
         assert_eq!(actual, *expected, "{:?} != {:?}", actual, expected);
         correct += 1;
     }
@@ -31,6 +32,16 @@ fn string_method() {
     compare(input, expected);
 }
 
+#[test]
+fn array_method_with_parens_in_pattern() {
+    let input = "@map(array 'echo $len($_)')[0]";
+    let expected = &[WordToken::ArrayMethod(
+        ArrayMethod::new("map", "array", Pattern::StringPattern("'echo $len($_)'"), Some("0")),
+        false,
+    )];
+    compare(input, expected);
+}
+
 #[test]
 fn escape_with_backslash() {
     let input = r#"\$FOO\$BAR \$FOO"#;
@@ -72,9 +83,22 @@ fn array_variables() {
 fn array_processes() {
     let input = "@(echo one two three) @(echo one two three)[0]";
     let expected = &[
-        WordToken::ArrayProcess("echo one two three", false, None),
+        WordToken::ArrayProcess("echo one two three", false, false, None),
         WordToken::Whitespace(" "),
-        WordToken::ArrayProcess("echo one two three", false, Some("0")),
+        WordToken::ArrayProcess("echo one two three", false, false, Some("0")),
+    ];
+    compare(input, expected);
+}
+
+#[test]
+fn processes_capturing_stderr() {
+    let input = "$&(echo one)[1..] @&(echo $&(two)) \"@&(three)\"";
+    let expected = &[
+        WordToken::Process("echo one", true, Some("1..")),
+        WordToken::Whitespace(" "),
+        WordToken::ArrayProcess("echo $&(two)", false, true, None),
+        WordToken::Whitespace(" "),
+        WordToken::ArrayProcess("three", true, true, None),
     ];
     compare(input, expected);
 }
@@ -86,7 +110,11 @@ fn array_process_within_string_process() {
         &[
             WordToken::Normal("echo".into(), false, false),
             WordToken::Whitespace(" "),
-            WordToken::Process("let free=[@(free -h)]; echo @free[6]@free[8]/@free[7]", None),
+            WordToken::Process(
+                "let free=[@(free -h)]; echo @free[6]@free[8]/@free[7]",
+                false,
+                None,
+            ),
         ],
     )
 }
@@ -106,6 +134,14 @@ fn indexes() {
         WordToken::ArrayVariable("array", false, Some("3..")),
     ];
     compare(input, expected);
+
+    let input = "$?[1] $?";
+    let expected = &[
+        WordToken::Variable("?", Some("1")),
+        WordToken::Whitespace(" "),
+        WordToken::Variable("?", None),
+    ];
+    compare(input, expected);
 }
 
 #[test]
@@ -127,9 +163,24 @@ fn nested_processes() {
     let expected = &[
         WordToken::Normal("echo".into(), false, false),
         WordToken::Whitespace(" "),
-        WordToken::Process("echo $(echo one)", None),
+        WordToken::Process("echo $(echo one)", false, None),
         WordToken::Whitespace(" "),
-        WordToken::Process("echo one $(echo two) three", None),
+        WordToken::Process("echo one $(echo two) three", false, None),
+    ];
+    compare(input, expected);
+}
+
+#[test]
+fn process_substitutions() {
+    let input = "tee >(cat > $(echo file)) \">(cat)\" <(echo <(echo one))";
+    let expected = &[
+        WordToken::Normal("tee".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::ProcessSubstitution("cat > $(echo file)", true),
+        WordToken::Whitespace(" "),
+        WordToken::Normal(">(cat)".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::ProcessSubstitution("echo <(echo one)", false),
     ];
     compare(input, expected);
 }
@@ -140,7 +191,7 @@ fn words_process_with_quotes() {
     let expected = &[
         WordToken::Normal("echo".into(), false, false),
         WordToken::Whitespace(" "),
-        WordToken::Process("git branch | rg '[*]' | awk '{print $2}'", None),
+        WordToken::Process("git branch | rg '[*]' | awk '{print $2}'", false, None),
     ];
     compare(input, expected);
 
@@ -148,7 +199,62 @@ fn words_process_with_quotes() {
     let expected = &[
         WordToken::Normal("echo".into(), false, false),
         WordToken::Whitespace(" "),
-        WordToken::Process("git branch | rg \"[*]\" | awk '{print $2}'", None),
+        WordToken::Process("git branch | rg \"[*]\" | awk '{print $2}'", false, None),
+    ];
+    compare(input, expected);
+}
+
+#[test]
+fn quoted_parens_in_processes() {
+    let input = r#"echo "$(echo ")" | cat)" @(echo "(" '(' | cat)"#;
+    let expected = &[
+        WordToken::Normal("echo".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Process(r#"echo ")" | cat"#, false, None),
+        WordToken::Whitespace(" "),
+        WordToken::ArrayProcess(r#"echo "(" '(' | cat"#, false, false, None),
+    ];
+    compare(input, expected);
+}
+
+#[test]
+fn raw_quotes() {
+    let input = r#"echo '''it's $raw \n "text"'''x '''''' *"#;
+    let expected = &[
+        WordToken::Normal("echo".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Normal(r#"it's $raw \n "text""#.into(), false, false),
+        WordToken::Normal("x".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Normal("".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Normal("*".into(), true, false),
+    ];
+    compare(input, expected);
+}
+
+#[test]
+fn triple_quotes() {
+    let input = r#"echo """a "b" $x""y"""z """""" *"#;
+    let expected = &[
+        WordToken::Normal("echo".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Normal("a".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Normal("\"".into(), false, false),
+        WordToken::Normal("b".into(), false, false),
+        WordToken::Normal("\"".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Variable("x", None),
+        WordToken::Normal("\"".into(), false, false),
+        WordToken::Normal("\"".into(), false, false),
+        WordToken::Normal("y".into(), false, false),
+        WordToken::Normal("".into(), false, false),
+        WordToken::Normal("z".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Normal("".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::Normal("*".into(), true, false),
     ];
     compare(input, expected);
 }
@@ -168,9 +274,9 @@ fn test_words() {
         WordToken::Whitespace(" "),
         WordToken::Normal("~".into(), false, true),
         WordToken::Whitespace(" "),
-        WordToken::Process("echo foo", None),
+        WordToken::Process("echo foo", false, None),
         WordToken::Whitespace(" "),
-        WordToken::Process("seq 1 100", None),
+        WordToken::Process("seq 1 100", false, None),
     ];
     compare(input, expected);
 }
@@ -248,3 +354,25 @@ fn array_methods() {
     let expected = args!["111"];
     assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), expected);
 }
+
+#[test]
+fn unterminated_syntax() {
+    let cases = [
+        ("echo $((1 + 2", WordError::UnterminatedArithmetic),
+        ("echo [a b", WordError::UnterminatedArray),
+        ("echo {a,b", WordError::UnterminatedBrace),
+        ("echo $(ls", WordError::UnterminatedProcess),
+        ("echo @&(ls", WordError::UnterminatedProcess),
+        ("echo ${var", WordError::UnterminatedBracedVariable),
+        ("echo $join(array", WordError::UnterminatedMethod),
+        ("echo @array[1", WordError::UnterminatedSelection),
+    ];
+    for &(input, error) in &cases {
+        let mut words = WordIterator::new(input, true);
+        assert_eq!(words.next(), Some(Ok(WordToken::Normal("echo".into(), false, false))));
+        assert_eq!(words.next(), Some(Ok(WordToken::Whitespace(" "))));
+        assert_eq!(words.next(), Some(Err(error)), "{}", input);
+        // Nothing is read after the error
+        assert_eq!(words.next(), None);
+    }
+}
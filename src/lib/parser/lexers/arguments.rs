@@ -139,6 +139,17 @@ impl<'a> ArgumentSplitter<'a> {
             self.read += 1;
         }
     }
+
+    /// Skips over a raw or triple quoted string, which ends at the next `delimiter` or at the
+    /// end of the input.
+    fn scan_triplequotes<B: Iterator<Item = u8>>(&mut self, bytes: &mut B, delimiter: &str) {
+        let len = self.data[self.read + 3..]
+            .find(delimiter)
+            .map_or(self.data.len() - self.read, |len| len + 6);
+        // The opening quote was already consumed
+        let _ = bytes.nth(len - 2);
+        self.read += len;
+    }
 }
 
 impl<'a> Iterator for ArgumentSplitter<'a> {
@@ -199,6 +210,15 @@ impl<'a> Iterator for ArgumentSplitter<'a> {
                     let _ = levels.down(Field::Proc);
                 }
 
+                // Raw and triple quoted strings are kept intact, no matter what they contain.
+                b'"' if !self.quotes && data[self.read..].starts_with(b"\"\"\"") => {
+                    self.scan_triplequotes(&mut bytes, "\"\"\"");
+                    continue;
+                }
+                b'\'' if !self.quotes && data[self.read..].starts_with(b"'''") => {
+                    self.scan_triplequotes(&mut bytes, "'''");
+                    continue;
+                }
                 // Toggle double quote rules.
                 b'"' => {
                     self.quotes ^= true;
@@ -277,4 +297,18 @@ mod tests {
         let expected = vec!["'abc'", "'a'", "''"];
         compare(input, expected);
     }
+
+    #[test]
+    fn raw_quotes() {
+        let input = "echo '''it's \\ \"raw\"''' ''''''x '''unterminated '";
+        let expected = vec!["echo", "'''it's \\ \"raw\"'''", "''''''x", "'''unterminated '"];
+        compare(input, expected);
+    }
+
+    #[test]
+    fn triple_quotes() {
+        let input = r#"echo """a "b" c""" """"""x """unterminated ""#;
+        let expected = vec!["echo", r#""""a "b" c""""#, r#"""""""x"#, r#""""unterminated ""#];
+        compare(input, expected);
+    }
 }
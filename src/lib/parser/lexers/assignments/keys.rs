@@ -140,6 +140,9 @@ impl<'a> Iterator for KeyIterator<'a> {
                         kind: Primitive::Str,
                     }));
                 }
+                // The `::` of a namespace, as in `global::name`, is part of the name
+                b':' if self.data[self.read..].starts_with(':')
+                    || self.data[..self.read - 1].ends_with(':') => {}
                 b':' => {
                     let end = self.read - 1;
                     return Some(self.parse_parameter(self.data[start..end].trim()));
@@ -249,4 +252,19 @@ mod tests {
         );
         assert_eq!(parser.next().unwrap(), Err(TypeError::Invalid("a".into())));
     }
+
+    #[test]
+    fn namespaced_keys() {
+        let mut parser = KeyIterator::new("global::a super::super::b:int global::c[]");
+        assert_eq!(parser.next().unwrap(), Ok(Key { name: "global::a", kind: Primitive::Str }));
+        assert_eq!(
+            parser.next().unwrap(),
+            Ok(Key { name: "super::super::b", kind: Primitive::Integer })
+        );
+        assert_eq!(
+            parser.next().unwrap(),
+            Ok(Key { name: "global::c", kind: Primitive::Array(Box::new(Primitive::Str)) })
+        );
+        assert_eq!(parser.next(), None);
+    }
 }
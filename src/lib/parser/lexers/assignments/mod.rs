@@ -119,6 +119,8 @@ mod tests {
             assignment_lexer("abc def ?= 123 456"),
             (Some("abc def"), Some(Operator::OptionalEqual), Some("123 456"))
         );
+
+        assert_eq!(assignment_lexer("a ??= b"), (Some("a"), Some(Operator::EmptyEqual), Some("b")));
     }
 
     #[test]
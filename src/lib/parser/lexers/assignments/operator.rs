@@ -15,6 +15,8 @@ pub enum Operator {
     Equal,
     /// Assign a default value
     OptionalEqual,
+    /// Assign a default value to unset or empty variables
+    EmptyEqual,
     /// Exponent (only works on numeric types)
     Exponent,
     /// Filter the array to remove the matching values (only works on array and map-like types)
@@ -46,6 +48,7 @@ impl Operator {
             b"++" => Some(Self::Concatenate),
             b"::" => Some(Self::ConcatenateHead),
             b"\\\\" => Some(Self::Filter),
+            b"??" => Some(Self::EmptyEqual),
             _ => None,
         }
     }
@@ -64,6 +67,7 @@ impl Display for Operator {
                 Operator::Divide => "/=",
                 Operator::Equal => "=",
                 Operator::OptionalEqual => "?=",
+                Operator::EmptyEqual => "??=",
                 Operator::Exponent => "**=",
                 Operator::IntegerDivide => "//=",
                 Operator::Multiply => "*=",
@@ -10,10 +10,13 @@ pub mod lexers;
 pub mod pipelines;
 mod statement;
 mod terminator;
+mod tokens;
 
+pub(crate) use self::statement::{FunctionParseError, StatementVariant};
 pub use self::{
     statement::{parse_and_validate, Error, StatementSplitter},
     terminator::Terminator,
+    tokens::{tokenize, Token},
 };
 
 #[cfg(fuzzing)]
@@ -2,7 +2,10 @@ use std::iter::Peekable;
 use thiserror::Error;
 
 use crate::{
-    expansion::pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
+    expansion::{
+        pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
+        WordError,
+    },
     parser::lexers::arguments::{Field, Levels, LevelsError},
     shell::Job,
     types::*,
@@ -39,12 +42,22 @@ pub enum PipelineParsingError {
     /// Error with paired tokens (parens, brackets & braces)
     #[error("{0}")]
     Paired(#[source] LevelsError),
+    /// Error with the syntax of a word, such as an unterminated variable index
+    #[error("{0}")]
+    Word(#[source] WordError),
+    /// Something other than a redirection follows a group of commands
+    #[error("expected a redirection after the group of commands, but found '{0}'")]
+    ExpectedRedirection(String),
 }
 
 impl From<LevelsError> for PipelineParsingError {
     fn from(cause: LevelsError) -> Self { Self::Paired(cause) }
 }
 
+impl From<WordError> for PipelineParsingError {
+    fn from(cause: WordError) -> Self { Self::Word(cause) }
+}
+
 trait AddItem<'a> {
     fn add_item(
         &mut self,
@@ -190,6 +203,10 @@ impl<'a> Collector<'a> {
                         std::mem::take(&mut inputs),
                     );
                 }
+                // A process substitution is an argument, not a redirection
+                b'<' | b'>' if self.peek(i + 1) == Some(b'(') => {
+                    self.push_arg(&mut args, &mut bytes)?
+                }
                 b'>' => {
                     bytes.next();
                     self.push_redir_to_output(RedirectFrom::Stdout, &mut outputs, &mut bytes)?;
@@ -315,6 +332,12 @@ impl<'a> Collector<'a> {
                 // Evaluate a quoted string but do not return it
                 // We pass in i, the index of a quote, but start a character later. This ensures
                 // the production rules will produce strings with the quotes intact
+                b'"' | b'\''
+                    if (self.data[i..].starts_with("\"\"\"")
+                        || self.data[i..].starts_with("'''")) =>
+                {
+                    self.triple_quoted(bytes, i)?;
+                }
                 b'"' => {
                     bytes.next();
                     self.double_quoted(bytes, i)?;
@@ -329,6 +352,17 @@ impl<'a> Collector<'a> {
                     bytes.next();
                     bytes.next();
                 }
+                // The `&` of `$&(` and `@&(` belongs to the argument
+                b'&' if i > 0
+                    && matches!(self.data.as_bytes()[i - 1], b'$' | b'@')
+                    && self.peek(i + 1) == Some(b'(') =>
+                {
+                    bytes.next();
+                }
+                // The `<(` or `>(` of a process substitution starts the argument
+                b'<' | b'>' if start == Some(i) && self.peek(i + 1) == Some(b'(') => {
+                    bytes.next();
+                }
                 // If we see a byte from the follow set, we've definitely reached the end of
                 // the arguments
                 b'&' | b'|' | b'<' | b'>' | b' ' | b'\t' if levels.are_rooted() => {
@@ -369,6 +403,20 @@ impl<'a> Collector<'a> {
                     bytes.next();
                     return Ok(&self.data[start..=i]);
                 }
+                // The quotes within a process are its own, as in `"$(echo ")")"`
+                b'$' | b'@' if self.peek(i + 1) == Some(b'(') => {
+                    bytes.next();
+                    bytes.next();
+                    self.process(bytes)?;
+                    continue;
+                }
+                b'$' | b'@' if self.peek(i + 1) == Some(b'&') && self.peek(i + 2) == Some(b'(') => {
+                    bytes.next();
+                    bytes.next();
+                    bytes.next();
+                    self.process(bytes)?;
+                    continue;
+                }
                 _ => (),
             }
             bytes.next();
@@ -376,6 +424,48 @@ impl<'a> Collector<'a> {
         Err(PipelineParsingError::UnterminatedDoubleQuote)
     }
 
+    /// Skips the command of a process up to its closing parenthesis, including its quotes and
+    /// nested parentheses.
+    fn process<I>(&self, bytes: &mut Peekable<I>) -> Result<(), PipelineParsingError>
+    where
+        I: Iterator<Item = (usize, u8)>,
+    {
+        let mut level = 0;
+        while let Some(&(i, b)) = bytes.peek() {
+            match b {
+                b'\\' => {
+                    bytes.next();
+                }
+                b'"' | b'\''
+                    if (self.data[i..].starts_with("\"\"\"")
+                        || self.data[i..].starts_with("'''")) =>
+                {
+                    self.triple_quoted(bytes, i)?;
+                    continue;
+                }
+                b'"' => {
+                    bytes.next();
+                    self.double_quoted(bytes, i)?;
+                    continue;
+                }
+                b'\'' => {
+                    bytes.next();
+                    self.single_quoted(bytes, i)?;
+                    continue;
+                }
+                b'(' => level += 1,
+                b')' if level == 0 => {
+                    bytes.next();
+                    return Ok(());
+                }
+                b')' => level -= 1,
+                _ => (),
+            }
+            bytes.next();
+        }
+        Err(LevelsError::UnmatchedParen.into())
+    }
+
     fn single_quoted<I>(
         &self,
         bytes: &mut Peekable<I>,
@@ -395,6 +485,28 @@ impl<'a> Collector<'a> {
         Err(PipelineParsingError::UnterminatedSingleQuote)
     }
 
+    fn triple_quoted<I>(
+        &self,
+        bytes: &mut Peekable<I>,
+        start: usize,
+    ) -> Result<&'a str, PipelineParsingError>
+    where
+        I: Iterator<Item = (usize, u8)>,
+    {
+        // Everything up to the closing `'''` or `"""` is kept as is, quotes and backslashes
+        // included
+        let delimiter = &self.data[start..start + 3];
+        let end = self.data[start + 3..].find(delimiter).map(|len| start + len + 6).ok_or(
+            if delimiter == "'''" {
+                PipelineParsingError::UnterminatedSingleQuote
+            } else {
+                PipelineParsingError::UnterminatedDoubleQuote
+            },
+        )?;
+        while bytes.next_if(|&(i, _)| i < end).is_some() {}
+        Ok(&self.data[start..end])
+    }
+
     const fn peek(&self, index: usize) -> Option<u8> {
         if index < self.data.len() {
             Some(self.data.as_bytes()[index])
@@ -403,11 +515,75 @@ impl<'a> Collector<'a> {
         }
     }
 
+    /// Check that the quotes and paired tokens of every word in the given data are terminated
+    pub(crate) fn check_words(data: &'a str) -> Result<(), PipelineParsingError> {
+        let collector = Collector::new(data);
+        let mut bytes = data.bytes().enumerate().peekable();
+        while let Some(&(i, _)) = bytes.peek() {
+            collector.arg(&mut bytes)?;
+            // Skip over the separators which end a word without being a part of it
+            if bytes.peek().map(|&(j, _)| j) == Some(i) {
+                bytes.next();
+            }
+        }
+        Ok(())
+    }
+
     /// Collect a pipeline on the given data
     pub fn run<'builtins>(data: &'a str) -> Result<Pipeline<Job>, PipelineParsingError> {
         Collector::new(data).parse()
     }
 
+    /// Collect the redirections applied to a whole group of commands, as the `> file` of
+    /// `{ echo a; echo b } > file`
+    pub fn redirections(
+        data: &'a str,
+    ) -> Result<(Vec<Redirection>, Vec<Input>), PipelineParsingError> {
+        let collector = Collector::new(data);
+        let mut bytes = data.bytes().enumerate().peekable();
+        let mut outputs = Vec::new();
+        let mut inputs = Vec::new();
+
+        while let Some(&(i, b)) = bytes.peek() {
+            match (b, collector.peek(i + 1)) {
+                (b' ' | b'\t', _) => {
+                    bytes.next();
+                }
+                (b'>', _) => {
+                    bytes.next();
+                    collector.push_redir_to_output(
+                        RedirectFrom::Stdout,
+                        &mut outputs,
+                        &mut bytes,
+                    )?;
+                }
+                (b'^' | b'&', Some(b'>')) => {
+                    bytes.next();
+                    bytes.next();
+                    let from = if b == b'^' { RedirectFrom::Stderr } else { RedirectFrom::Both };
+                    collector.push_redir_to_output(from, &mut outputs, &mut bytes)?;
+                }
+                (b'<', Some(b'<')) if collector.peek(i + 2) == Some(b'<') => {
+                    bytes.nth(2);
+                    let string = collector.arg(&mut bytes)?;
+                    inputs.push(Input::HereString(
+                        string.ok_or(PipelineParsingError::NoHereStringArg)?.into(),
+                    ));
+                }
+                (b'<', Some(b'<')) => return Err(PipelineParsingError::HeredocsDeprecated),
+                (b'<', _) => {
+                    bytes.next();
+                    let file = collector.arg(&mut bytes)?;
+                    inputs.push(Input::File(
+                        file.ok_or(PipelineParsingError::NoRedirectionArg)?.into(),
+                    ));
+                }
+                _ => return Err(PipelineParsingError::ExpectedRedirection(data[i..].into())),
+            }
+        }
+        Ok((outputs, inputs))
+    }
+
     const fn new(data: &'a str) -> Self { Self { data } }
 }
 
@@ -480,6 +656,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn process_substitutions() {
+        if let Statement::Pipeline(pipeline) =
+            parse("tee >(cat) <(echo a) > >(wc -l) < <(echo b)").unwrap()
+        {
+            let items = pipeline.items;
+            assert_eq!(args!["tee", ">(cat)", "<(echo a)"], items[0].job.args);
+            let expected = vec![Redirection {
+                from:   RedirectFrom::Stdout,
+                file:   ">(wc -l)".into(),
+                append: false,
+            }];
+            assert_eq!(expected, items[0].outputs);
+            assert_eq!(vec![Input::File("<(echo b)".into())], items[0].inputs);
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn quoted_parens_in_processes() {
+        if let Statement::Pipeline(pipeline) =
+            parse(r#"echo "$(echo ")" | cat)"x "@&(echo '"' | cat)" | cat"#).unwrap()
+        {
+            let items = pipeline.items;
+            assert_eq!(2, items.len());
+            assert_eq!(
+                args!["echo", r#""$(echo ")" | cat)"x"#, r#""@&(echo '"' | cat)""#],
+                items[0].job.args
+            );
+        } else {
+            panic!();
+        }
+    }
+
     #[test]
     fn nested_array_process() {
         if let Statement::Pipeline(pipeline) = parse("echo @(echo one @(echo two) three)").unwrap()
@@ -643,6 +854,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn raw_quotes() {
+        if let Statement::Pipeline(pipeline) =
+            parse("echo '''it's \\ \"raw\" | > '''x ''''''").unwrap()
+        {
+            let items = pipeline.items;
+            assert_eq!(3, items[0].job.args.len());
+            assert_eq!("'''it's \\ \"raw\" | > '''x", &items[0].job.args[1]);
+            assert_eq!("''''''", &items[0].job.args[2]);
+        } else {
+            panic!()
+        }
+    }
+
+    #[test]
+    fn triple_quotes() {
+        if let Statement::Pipeline(pipeline) =
+            parse("echo \"\"\"a \"quoted\" | > \nline\"\"\"x \"\"\"\"\"\"").unwrap()
+        {
+            let items = pipeline.items;
+            assert_eq!(3, items[0].job.args.len());
+            assert_eq!("\"\"\"a \"quoted\" | > \nline\"\"\"x", &items[0].job.args[1]);
+            assert_eq!("\"\"\"\"\"\"", &items[0].job.args[2]);
+        } else {
+            panic!()
+        }
+    }
+
     #[test]
     fn all_whitespace() {
         if let Statement::Default = parse("  \t ").unwrap() {
@@ -741,6 +980,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn heredocs_are_rejected() {
+        for input in &["cat <<EOF", "cat <<-EOF", "cat <<- EOF > out.log"] {
+            assert!(matches!(
+                super::Collector::new(input).parse(),
+                Err(super::PipelineParsingError::HeredocsDeprecated)
+            ));
+        }
+    }
+
+    #[test]
+    fn stderr_capture_is_not_background() {
+        if let Statement::Pipeline(pipeline) = parse("echo $&(cmd) @&(cmd)&").unwrap() {
+            assert_eq!(args!["echo", "$&(cmd)", "@&(cmd)"], pipeline.items[0].job.args);
+            assert_eq!(PipeType::Background, pipeline.pipe);
+        } else {
+            panic!();
+        }
+    }
+
     #[test]
     // FIXME: May need updating after resolution of which part of the pipe
     // the input redirection shoud be associated with.
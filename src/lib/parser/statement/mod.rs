@@ -4,13 +4,11 @@ mod parse;
 mod splitter;
 
 pub use self::{
+    functions::FunctionParseError,
     parse::parse,
     splitter::{StatementSplitter, StatementVariant},
 };
-use super::{
-    pipelines::PipelineParsingError,
-    statement::{case::Error as CaseError, functions::FunctionParseError},
-};
+use super::{pipelines::PipelineParsingError, statement::case::Error as CaseError};
 use crate::shell::flow_control::Statement;
 use std::char;
 use thiserror::Error;
@@ -13,6 +13,41 @@ use crate::{
 };
 use std::char;
 
+/// Splits a group of commands, such as `(cd dir; make)` or `{ echo a; echo b } > file`, into
+/// its body and what follows its closing parenthesis or brace. The parentheses and braces in
+/// quotes or escaped are left out.
+fn group(cmd: &str, open: u8, close: u8) -> Option<(&str, &str)> {
+    let (mut level, mut single_quotes, mut double_quotes, mut escaped) = (0, false, false, false);
+    for (i, character) in cmd.bytes().enumerate() {
+        match character {
+            _ if escaped => escaped = false,
+            b'\\' if !single_quotes => escaped = true,
+            b'\'' if !double_quotes => single_quotes = !single_quotes,
+            b'"' if !single_quotes => double_quotes = !double_quotes,
+            _ if single_quotes || double_quotes => (),
+            _ if character == open => level += 1,
+            _ if character == close && level == 1 => {
+                return Some((cmd[1..i].trim(), cmd[i + 1..].trim()))
+            }
+            _ if character == close => level -= 1,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Parses a group of commands, with the redirections following it
+fn parse_group(cmd: &str, subshell: bool) -> super::Result {
+    let (open, close, unterminated) = if subshell {
+        (b'(', b')', Error::UnterminatedSubshell)
+    } else {
+        (b'{', b'}', Error::UnterminatedBrace)
+    };
+    let (code, rest) = group(cmd, open, close).ok_or(unterminated)?;
+    let (outputs, inputs) = pipelines::Collector::redirections(rest)?;
+    Ok(Statement::Group { code: code.into(), subshell, outputs, inputs })
+}
+
 pub fn parse(code: &str) -> super::Result {
     let cmd = code.trim();
     match cmd {
@@ -42,6 +77,9 @@ pub fn parse(code: &str) -> super::Result {
             }
         }
         "export" => Ok(Statement::Export(ExportAction::List)),
+        _ if cmd.starts_with("export -n ") => {
+            Ok(Statement::Export(ExportAction::Unexport(cmd[10..].trim().into())))
+        }
         _ if cmd.starts_with("export ") => {
             // Split the let expression and ensure that the statement is valid.
             let (keys, op, vals) = assignment_lexer(cmd[7..].trim_start());
@@ -139,6 +177,10 @@ pub fn parse(code: &str) -> super::Result {
         _ if cmd.starts_with("! ") => Ok(Statement::Not(Box::new(parse(cmd[1..].trim_start())?))),
         _ if cmd.eq("not") | cmd.eq("!") => Ok(Statement::Not(Box::new(Statement::Default))),
         _ if cmd.is_empty() || cmd.starts_with('#') => Ok(Statement::Default),
+        _ if cmd.starts_with('(') => parse_group(cmd, true),
+        _ if cmd.starts_with('{') && cmd[1..].starts_with(char::is_whitespace) => {
+            parse_group(cmd, false)
+        }
         _ => Ok(Statement::Pipeline(pipelines::Collector::run(cmd)?)),
     }
 }
@@ -147,14 +189,81 @@ pub fn parse(code: &str) -> super::Result {
 mod tests {
     use super::*;
     use crate::{
-        expansion::pipelines::{PipeItem, PipeType, Pipeline, RedirectFrom},
-        parser::lexers::{
-            assignments::{KeyBuf, Primitive},
-            Operator,
+        expansion::pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
+        parser::{
+            lexers::{
+                assignments::{KeyBuf, Primitive},
+                Operator,
+            },
+            pipelines::PipelineParsingError,
         },
         shell::{flow_control::Statement, Job},
     };
 
+    fn group(
+        code: &str,
+        subshell: bool,
+        outputs: Vec<Redirection>,
+        inputs: Vec<Input>,
+    ) -> Statement {
+        Statement::Group { code: code.into(), subshell, outputs, inputs }
+    }
+
+    #[test]
+    fn parsing_subshell_groups() {
+        assert_eq!(
+            parse("(cd dir; echo \"(\")").unwrap(),
+            group("cd dir; echo \"(\"", true, vec![], vec![])
+        );
+        assert_eq!(parse("( (true) )").unwrap(), group("(true)", true, vec![], vec![]));
+        assert_eq!(
+            parse("(true) false"),
+            Err(Error::Pipeline(PipelineParsingError::ExpectedRedirection("false".into())))
+        );
+        assert_eq!(parse("(true"), Err(Error::UnterminatedSubshell));
+    }
+
+    #[test]
+    fn parsing_brace_groups() {
+        assert_eq!(
+            parse("{ echo {a,b}; echo '}' } >> out ^> err < in").unwrap(),
+            group(
+                "echo {a,b}; echo '}'",
+                false,
+                vec![
+                    Redirection {
+                        from:   RedirectFrom::Stdout,
+                        file:   "out".into(),
+                        append: true,
+                    },
+                    Redirection {
+                        from:   RedirectFrom::Stderr,
+                        file:   "err".into(),
+                        append: false,
+                    },
+                ],
+                vec![Input::File("in".into())],
+            )
+        );
+        assert_eq!(
+            parse("{\n  cat\n} &> out <<< text").unwrap(),
+            group(
+                "cat",
+                false,
+                vec![Redirection {
+                    from:   RedirectFrom::Both,
+                    file:   "out".into(),
+                    append: false,
+                }],
+                vec![Input::HereString("text".into())],
+            )
+        );
+        assert_eq!(parse("{ true; {a,b}"), Err(Error::UnterminatedBrace));
+        assert_eq!(parse("{ true } >"), Err(Error::Pipeline(PipelineParsingError::NoRedirection)));
+        // Brace expansions are not groups
+        assert!(matches!(parse("{echo,true}"), Ok(Statement::Pipeline(_))));
+    }
+
     #[test]
     fn parsing_let_alone() {
         assert_eq!(parse("let").unwrap(), Statement::Let(LocalAction::List),);
@@ -24,6 +24,8 @@ pub struct StatementSplitter<'a> {
     data:                 &'a str,
     read:                 usize,
     paren_level:          i8,
+    group_level:          i8,
+    brace_groups:         i8,
     brace_level:          i8,
     square_bracket_level: i8,
     math_paren_level:     i8,
@@ -32,6 +34,9 @@ pub struct StatementSplitter<'a> {
     variable:             bool,
     single_quotes:        bool,
     double_quotes:        bool,
+    /// Whether each of the parentheses opened was within double quotes, so that quotes nest
+    /// within substitutions as in `"$(echo ")")"`
+    outer_quotes:         Vec<bool>,
 }
 
 impl<'a> StatementSplitter<'a> {
@@ -41,6 +46,8 @@ impl<'a> StatementSplitter<'a> {
             data,
             read: 0,
             paren_level: 0,
+            group_level: 0,
+            brace_groups: 0,
             brace_level: 0,
             square_bracket_level: 0,
             math_paren_level: 0,
@@ -49,11 +56,32 @@ impl<'a> StatementSplitter<'a> {
             variable: false,
             single_quotes: false,
             double_quotes: false,
+            outer_quotes: Vec::new(),
         }
     }
 
     fn inside_quotes(&self) -> bool { return self.single_quotes || self.double_quotes }
 
+    fn open_paren(&mut self) {
+        self.paren_level += 1;
+        self.outer_quotes.push(self.double_quotes);
+        self.double_quotes = false;
+    }
+
+    fn close_paren(&mut self) {
+        self.paren_level -= 1;
+        self.double_quotes = self.outer_quotes.pop().unwrap_or(false);
+    }
+
+    /// Whether a command may start at `i`, as at the start of a statement or of a group
+    fn starts_command(&self, start: usize, i: usize) -> bool {
+        let before = self.data[start..i].trim_end();
+        before.is_empty()
+            || before.ends_with(&[';', '{', '('][..])
+            || before.ends_with("&&")
+            || before.ends_with("||")
+    }
+
     fn get_statement(&self, statement: &'a str) -> StatementVariant<'a> {
         match self.logical {
             LogicalOp::And => StatementVariant::And(statement.trim()),
@@ -98,6 +126,25 @@ impl<'a> Iterator for StatementSplitter<'a> {
                         }
                     }
                 }
+                // Raw and triple quoted strings are taken as is until the closing `'''` or `"""`.
+                b'\'' | b'"'
+                    if !self.inside_quotes()
+                        && (self.data[i..].starts_with("'''")
+                            || self.data[i..].starts_with("\"\"\"")) =>
+                {
+                    let delimiter = &self.data[i..i + 3];
+                    if let Some(len) = self.data[i + 3..].find(delimiter) {
+                        let end = i + len + 6;
+                        while bytes.next_if(|&(j, _)| j < end).is_some() {}
+                    } else if character == b'\'' {
+                        self.single_quotes = true;
+                        bytes.by_ref().for_each(drop);
+                    } else {
+                        self.double_quotes = true;
+                        bytes.by_ref().for_each(drop);
+                    }
+                    self.variable = false;
+                }
                 // Toggle quotes and stop matching variables.
                 b'\'' if !self.double_quotes => {
                     self.single_quotes = !self.single_quotes;
@@ -117,12 +164,26 @@ impl<'a> Iterator for StatementSplitter<'a> {
                 // Array expansion
                 b'@' | b'$' => self.variable = true,
                 b'{' if [Some(b'$'), Some(b'@')].contains(&last) => self.vbrace = true,
+                // Parentheses are literal within single quotes, as in `$method(var '(x)')`
+                b'(' | b')' if self.single_quotes => (),
                 b'(' if self.math_paren_level > 0 => self.math_paren_level += 1,
                 b'(' if self.variable && last == Some(b'(') => {
                     self.math_paren_level = 1;
-                    self.paren_level -= 1;
+                    self.close_paren();
+                }
+                b'(' if self.variable => self.open_paren(),
+                // A process substitution, as in `<(cmd)` and `>(cmd)`
+                b'(' if !self.inside_quotes() && matches!(last, Some(b'<' | b'>')) => {
+                    self.open_paren()
+                }
+                // A subshell group, at the start of a command or nested in another group
+                b'(' if !self.inside_quotes()
+                    && self.paren_level == self.group_level
+                    && (self.group_level > 0 || self.starts_command(start, i)) =>
+                {
+                    self.open_paren();
+                    self.group_level += 1;
                 }
-                b'(' if self.variable => self.paren_level += 1,
                 b'(' if error.is_none() && !self.inside_quotes() => {
                     error = Some(Error::InvalidCharacter(character as char, i + 1))
                 }
@@ -140,16 +201,35 @@ impl<'a> Iterator for StatementSplitter<'a> {
                         }
                     }
                 },
+                b')' if self.math_paren_level > 1 => self.math_paren_level -= 1,
                 b')' if self.paren_level == 0 => {
                     if !self.variable && error.is_none() && !self.inside_quotes() {
                         error = Some(Error::InvalidCharacter(character as char, i + 1))
                     }
                     self.variable = false;
                 }
-                b')' => self.paren_level -= 1,
+                b')' if self.double_quotes => (),
+                b')' if self.paren_level == self.group_level => {
+                    self.close_paren();
+                    self.group_level -= 1;
+                }
+                b')' => self.close_paren(),
                 // [^A-Za-z0-9_]
                 0..=37 | 39..=47 | 58 | 60..=64 | 91..=94 | 96 | 126..=127 => self.variable = false,
                 _ if self.inside_quotes() => {}
+                // A group of commands, which is followed by whitespace unlike brace expansions
+                b'{' if self.paren_level == self.group_level
+                    && self.starts_command(start, i)
+                    && matches!(bytes.peek(), Some((_, b' ' | b'\t' | b'\n')) | None) =>
+                {
+                    self.brace_groups += 1
+                }
+                b'}' if self.brace_level == 0
+                    && self.brace_groups > 0
+                    && matches!(last, Some(b' ' | b'\t' | b'\n' | b';')) =>
+                {
+                    self.brace_groups -= 1
+                }
                 b'{' => self.brace_level += 1,
                 b'}' => {
                     if self.brace_level == 0 {
@@ -160,7 +240,7 @@ impl<'a> Iterator for StatementSplitter<'a> {
                         self.brace_level -= 1;
                     }
                 }
-                b';' if self.paren_level == 0 => {
+                b';' if self.paren_level == 0 && self.brace_groups == 0 => {
                     self.read = i + 1;
                     if start == i {
                         return Some(Err(Error::ExpectedCommandButFound(";")));
@@ -173,7 +253,11 @@ impl<'a> Iterator for StatementSplitter<'a> {
                     };
                 }
                 // Detecting if there is a 2nd `&` character
-                b'&' | b'|' if self.paren_level == 0 && last == Some(character) => {
+                b'&' | b'|'
+                    if self.paren_level == 0
+                        && self.brace_groups == 0
+                        && last == Some(character) =>
+                {
                     self.read = i + 1;
                     if start == i - 1 {
                         return {
@@ -204,7 +288,7 @@ impl<'a> Iterator for StatementSplitter<'a> {
                 Some(Err(Error::UnterminatedSubshell))
             } else if self.vbrace {
                 Some(Err(Error::UnterminatedBracedVar))
-            } else if self.brace_level != 0 {
+            } else if self.brace_level != 0 || self.brace_groups != 0 {
                 Some(Err(Error::UnterminatedBrace))
             } else if self.math_paren_level != 0 {
                 Some(Err(Error::UnterminatedArithmetic))
@@ -361,12 +445,52 @@ fn syntax_errors() {
     assert_eq!(results.len(), 1);
 }
 
+#[test]
+fn subshell_groups() {
+    let command = "(cd dir; make) && (echo ')'; (echo nested)); echo (one)";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("(cd dir; make)")));
+    assert_eq!(results[1], Ok(StatementVariant::And("(echo ')'; (echo nested))")));
+    assert_eq!(results[2], Err(Error::InvalidCharacter('(', 51)));
+    assert_eq!(results.len(), 3);
+
+    let command = "(echo one; echo two";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Err(Error::UnterminatedSubshell));
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn brace_groups() {
+    let command = "{ echo a; echo {b,c} } > out || { true && false;}; {a,b}";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("{ echo a; echo {b,c} } > out")));
+    assert_eq!(results[1], Ok(StatementVariant::Or("{ true && false;}")));
+    assert_eq!(results[2], Ok(StatementVariant::Default("{a,b}")));
+    assert_eq!(results.len(), 3);
+
+    let command = "{ echo a; (cd b; { ls; }) }";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default(command)));
+    assert_eq!(results.len(), 1);
+
+    let command = "{ echo a; echo b}";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Err(Error::InvalidCharacter('}', 17)));
+    assert_eq!(results.len(), 1);
+}
+
 #[test]
 fn arithmetic() {
     let command = "$((3 + 3))";
     let results = StatementSplitter::new(command).collect::<Vec<_>>();
     assert_eq!(results[0], Ok(StatementVariant::Default("$((3 + 3))")));
     assert_eq!(results.len(), 1);
+
+    let command = "echo $(((1 + 2) * $(echo 3))); echo";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("echo $(((1 + 2) * $(echo 3)))")));
+    assert_eq!(results.len(), 2);
 }
 
 #[test]
@@ -424,6 +548,45 @@ fn quotes() {
     assert_eq!(results.len(), 2);
 }
 
+#[test]
+fn raw_strings() {
+    let command = "echo '''it's; a \\ raw string''' && echo '''a'b''' ''''''; echo";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("echo '''it's; a \\ raw string'''")));
+    assert_eq!(results[1], Ok(StatementVariant::And("echo '''a'b''' ''''''")));
+    assert_eq!(results[2], Ok(StatementVariant::Default("echo")));
+    assert_eq!(results.len(), 3);
+
+    let command = "echo '''unterminated'' ; echo";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Err(Error::UnterminatedSingleQuotes));
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn triple_quoted_strings() {
+    let command = r#"echo """a; "b" && c""" && echo """""" """"x"""; echo"#;
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default(r#"echo """a; "b" && c""""#)));
+    assert_eq!(results[1], Ok(StatementVariant::And(r#"echo """""" """"x""""#)));
+    assert_eq!(results[2], Ok(StatementVariant::Default("echo")));
+    assert_eq!(results.len(), 3);
+
+    let command = r#"echo """unterminated"" ; echo"#;
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Err(Error::UnterminatedDoubleQuotes));
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn quoted_parens_in_method() {
+    let command = "echo $regex_replace(x '(a)' ')'); echo";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("echo $regex_replace(x '(a)' ')')")));
+    assert_eq!(results[1], Ok(StatementVariant::Default("echo")));
+    assert_eq!(results.len(), 2);
+}
+
 #[test]
 fn nested_process() {
     let command = "echo $(echo one $(echo two) three)";
@@ -437,6 +600,18 @@ fn nested_process() {
     assert_eq!(results.len(), 1);
 }
 
+#[test]
+fn quoted_parens_in_process() {
+    let command = r#"echo "$(echo ")" | cat)" $(echo "(" | tr "(" x); echo"#;
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(
+        results[0],
+        Ok(StatementVariant::Default(r#"echo "$(echo ")" | cat)" $(echo "(" | tr "(" x)"#))
+    );
+    assert_eq!(results[1], Ok(StatementVariant::Default("echo")));
+    assert_eq!(results.len(), 2);
+}
+
 #[test]
 fn nested_array_process() {
     let command = "echo @(echo one @(echo two) three)";
@@ -4,6 +4,8 @@ use std::{iter::Peekable, str};
 enum Quotes {
     Single,
     Double,
+    Raw,
+    Triple,
     None,
 }
 
@@ -14,21 +16,52 @@ enum Quotes {
 /// - It reaches a newline without a "\\" char, not more "(" than ")" and not more "[" than "]"
 ///   before it
 ///
+/// The newlines of a group of commands, as in `(cd dir\nmake)` or `{ echo a\necho b }`, are kept
+/// so that they still separate its statements.
+///
 /// Assumes that the given byte sequence is valid UTF-8
 ///
+/// Comments start with `#` by default, which can be changed or disabled with `with_comment`.
+///
+/// The body of a `<<-DELIM` heredoc is read from the lines following the one of the operator, up
+/// to a line with only the delimiter, and the leading tabs of its lines are stripped. The heredoc
+/// is then given to the statement as a triple quoted herestring, or as a raw one if the delimiter
+/// is quoted.
+///
 /// This example comes from the shell's REPL, which ensures that the user's input
 /// will only be submitted for execution once a terminated command is supplied.
 #[derive(Debug)]
 pub struct Terminator<I: Iterator<Item = u8>> {
-    inner:      RearPeekable<I>,
-    array:      usize,
-    skip_next:  bool,
-    quotes:     Quotes,
-    terminated: bool,
-    and_or:     bool,
-    whitespace: bool,
-    empty:      bool,
-    subshell:   usize,
+    inner:            RearPeekable<I>,
+    array:            usize,
+    skip_next:        bool,
+    quotes:           Quotes,
+    terminated:       bool,
+    and_or:           bool,
+    whitespace:       bool,
+    empty:            bool,
+    subshell:         usize,
+    group:            usize,
+    separated:        bool,
+    comment:          Option<u8>,
+    raw_quotes:       u8,
+    angles:           u8,
+    emitted:          usize,
+    heredocs:         Vec<Heredoc>,
+    /// The bytes given since the start of the first heredoc whose body is not read yet
+    pending:          Vec<u8>,
+    /// Whether the input ended before the delimiter of a heredoc
+    unclosed_heredoc: bool,
+}
+
+/// A `<<-` heredoc of the statement being read
+#[derive(Debug)]
+struct Heredoc {
+    /// Where the operator starts in the statement
+    start:  usize,
+    /// The end of the delimiter in the statement, and the herestring replacing the heredoc,
+    /// once its body is read
+    string: Option<(usize, String)>,
 }
 
 impl<'a> From<&'a str> for Terminator<std::str::Bytes<'a>> {
@@ -37,9 +70,10 @@ impl<'a> From<&'a str> for Terminator<std::str::Bytes<'a>> {
 
 #[derive(Clone, Debug)]
 struct RearPeekable<I: Iterator> {
-    iter: Peekable<I>,
-    now:  Option<I::Item>,
-    last: Option<I::Item>,
+    iter:   Peekable<I>,
+    now:    Option<I::Item>,
+    last:   Option<I::Item>,
+    before: Option<I::Item>,
 }
 
 impl<I> Iterator for RearPeekable<I>
@@ -51,6 +85,7 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<I::Item> {
+        self.before = self.last;
         self.last = self.now;
         self.now = self.iter.next();
         self.now
@@ -66,6 +101,9 @@ impl<I: Iterator> RearPeekable<I> {
 
     #[inline]
     pub fn prev(&self) -> Option<&I::Item> { self.last.as_ref() }
+
+    #[inline]
+    pub const fn before_prev(&self) -> Option<&I::Item> { self.before.as_ref() }
 }
 
 impl<I: Iterator<Item = u8>> Iterator for Terminator<I> {
@@ -76,6 +114,19 @@ impl<I: Iterator<Item = u8>> Iterator for Terminator<I> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_byte();
+        if let Some(byte) = next {
+            self.emitted += 1;
+            if self.heredoc_pending() {
+                self.pending.push(byte);
+            }
+        }
+        next
+    }
+}
+
+impl<I: Iterator<Item = u8>> Terminator<I> {
+    fn next_byte(&mut self) -> Option<u8> {
         if self.terminated {
             return None;
         }
@@ -91,10 +142,32 @@ impl<I: Iterator<Item = u8>> Iterator for Terminator<I> {
             self.inner.next()
         };
 
-        if self.skip_next {
+        if self.skip_next && next.is_some() {
             self.skip_next = false;
+        } else if matches!(self.quotes, Quotes::Raw | Quotes::Triple) {
+            // Raw and triple quoted strings only end on three consecutive quotes of their kind
+            let quote = if self.quotes == Quotes::Raw { b'\'' } else { b'"' };
+            self.raw_quotes = if next == Some(quote) { self.raw_quotes + 1 } else { 0 };
+            if self.raw_quotes == 3 {
+                self.raw_quotes = 0;
+                self.quotes = Quotes::None;
+            }
         } else if self.quotes != Quotes::None && next != Some(b'\\') {
             match (next, &self.quotes) {
+                // An empty single quoted string followed by a quote opens a raw string
+                (Some(b'\''), Quotes::Single)
+                    if self.inner.prev() == Some(&b'\'') && self.inner.peek() == Some(&b'\'') =>
+                {
+                    self.quotes = Quotes::Raw;
+                    self.skip_next = true;
+                }
+                // Likewise for a triple quoted string
+                (Some(b'"'), Quotes::Double)
+                    if self.inner.prev() == Some(&b'"') && self.inner.peek() == Some(&b'"') =>
+                {
+                    self.quotes = Quotes::Triple;
+                    self.skip_next = true;
+                }
                 (Some(b'\''), Quotes::Single) | (Some(b'"'), Quotes::Double) => {
                     self.quotes = Quotes::None;
                 }
@@ -103,15 +176,18 @@ impl<I: Iterator<Item = u8>> Iterator for Terminator<I> {
         } else if let Some(character) = next {
             next = self.handle_char(character, prev_whitespace);
             self.empty &= character.is_ascii_whitespace();
-        } else if self.subshell == 0 && self.array == 0 && !self.and_or && !self.empty {
+        } else if self.subshell == 0
+            && self.group == 0
+            && self.array == 0
+            && !self.and_or
+            && !self.empty
+        {
             self.terminated = true;
         }
 
         next
     }
-}
 
-impl<I: Iterator<Item = u8>> Terminator<I> {
     /// Consumes lines until a statement is formed or the iterator runs dry, and returns the
     /// underlying `String`.
     ///
@@ -127,7 +203,13 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
         let stmt = self.collect::<Vec<_>>();
         // TODO: Parsing is only concerned about UTF-8 encoding.
         // For port to windows this can cause problems !
-        let stmt = String::from_utf8(stmt).expect("Ion shell is only dealing with utf8 content");
+        let mut stmt =
+            String::from_utf8(stmt).expect("Ion shell is only dealing with utf8 content");
+        for heredoc in self.heredocs.drain(..).rev() {
+            if let Some((end, string)) = heredoc.string {
+                stmt.replace_range(heredoc.start..end, &string);
+            }
+        }
 
         if self.empty {
             None
@@ -136,7 +218,114 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
         }
     }
 
+    /// Whether the body of a heredoc of the statement remains to be read
+    fn heredoc_pending(&self) -> bool {
+        matches!(self.heredocs.last(), Some(Heredoc { string: None, .. }))
+    }
+
+    /// Read the bodies of the heredocs of the line that just ended, from the following lines.
+    fn read_heredocs(&mut self) {
+        let offset = self.emitted - self.pending.len();
+        let pending = std::mem::take(&mut self.pending);
+        for heredoc in self.heredocs.iter_mut().filter(|heredoc| heredoc.string.is_none()) {
+            // The operator is followed by its delimiter, after an optional space
+            let mut position = heredoc.start - offset + 3;
+            if pending.get(position) == Some(&b' ') {
+                position += 1;
+            }
+            let (mut delimiter, mut quote, mut quoted) = (Vec::new(), None, false);
+            while let Some(&byte) = pending.get(position) {
+                match (quote, byte) {
+                    (None, b'\'' | b'"') => {
+                        quote = Some(byte);
+                        quoted = true;
+                    }
+                    (Some(open), _) if open == byte => quote = None,
+                    (None, _) if byte.is_ascii_whitespace() || b";|&<>()".contains(&byte) => break,
+                    _ => delimiter.push(byte),
+                }
+                position += 1;
+            }
+            if delimiter.is_empty() {
+                // Without a delimiter, the operator is reported as invalid by the parser
+                heredoc.string = Some((heredoc.start, String::new()));
+                continue;
+            }
+
+            let mut body = Vec::new();
+            loop {
+                let mut line = Vec::new();
+                let ended = loop {
+                    match self.inner.next() {
+                        Some(b'\n') => break false,
+                        Some(byte) => line.push(byte),
+                        None => break true,
+                    }
+                };
+                // Only tabs are stripped, not spaces, including before the delimiter
+                let indent = line.iter().take_while(|&&byte| byte == b'\t').count();
+                if line[indent..] == delimiter[..] {
+                    break;
+                }
+                if ended {
+                    self.unclosed_heredoc = true;
+                    if line.is_empty() {
+                        break;
+                    }
+                }
+                body.extend_from_slice(&line[indent..]);
+                body.push(b'\n');
+                if ended {
+                    break;
+                }
+            }
+            let body =
+                String::from_utf8(body).expect("Ion shell is only dealing with utf8 content");
+            let quotes = if quoted { "'''" } else { "\"\"\"" };
+            let string = format!("<<< {}{}{}", quotes, body, quotes);
+            heredoc.string = Some((position + offset, string));
+        }
+    }
+
+    /// Whether the input read so far leaves nothing open: no quote, parenthese, bracket or
+    /// group, and no `&&`, `||` or backslash waiting for what follows.
+    pub const fn is_complete(&self) -> bool {
+        matches!(self.quotes, Quotes::None)
+            && self.array == 0
+            && self.subshell == 0
+            && self.group == 0
+            && !self.and_or
+            && !self.skip_next
+            && !self.unclosed_heredoc
+    }
+
+    /// Whether the previous characters start a subshell, as in `$(`, `@(`, `$&(`, `@&(` and the
+    /// `<(` and `>(` of process substitutions
+    fn opens_subshell(&self) -> bool {
+        let sigil = match self.inner.prev() {
+            Some(b'&') => self.inner.before_prev(),
+            prev => prev,
+        };
+        matches!(sigil, Some(b'$' | b'@')) || matches!(self.inner.prev(), Some(b'<' | b'>'))
+    }
+
     fn handle_char(&mut self, character: u8, prev_whitespace: bool) -> Option<u8> {
+        let starts_statement = self.empty || self.and_or || self.separated;
+        if !character.is_ascii_whitespace() {
+            self.separated = matches!(character, b';' | b'(' | b'{');
+        }
+        if character == b'-' && self.angles == 2 {
+            // The bytes of the operator are only recorded already for a previous heredoc
+            if !self.heredoc_pending() {
+                self.pending.extend_from_slice(b"<<");
+            }
+            self.heredocs.push(Heredoc { start: self.emitted - 2, string: None });
+        }
+        self.angles = if character == b'<' { self.angles + 1 } else { 0 };
+        if character == b'\n' && self.heredoc_pending() {
+            self.read_heredocs();
+        }
+
         match character {
             b'\'' => {
                 self.quotes = Quotes::Single;
@@ -146,7 +335,7 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                 self.quotes = Quotes::Double;
                 Some(b'"')
             }
-            b'(' if self.inner.prev() == Some(&b'$') || self.inner.prev() == Some(&b'@') => {
+            b'(' if self.opens_subshell() => {
                 self.subshell += 1;
                 Some(b'(')
             }
@@ -154,6 +343,24 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                 self.subshell -= 1;
                 Some(b')')
             }
+            b'(' if self.group > 0 || starts_statement => {
+                self.group += 1;
+                Some(b'(')
+            }
+            b')' if self.group > 0 => {
+                self.group -= 1;
+                Some(b')')
+            }
+            b'{' if starts_statement
+                && matches!(self.inner.peek(), Some(b' ' | b'\t' | b'\n') | None) =>
+            {
+                self.group += 1;
+                Some(b'{')
+            }
+            b'}' if self.group > 0 && (prev_whitespace || self.inner.prev() == Some(&b';')) => {
+                self.group -= 1;
+                Some(b'}')
+            }
             b'[' => {
                 self.array += 1;
                 Some(b'[')
@@ -162,11 +369,22 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                 self.array -= 1;
                 Some(b']')
             }
-            b'#' if prev_whitespace || self.inner.prev().is_none() => {
+            _ if self.comment == Some(character)
+                && (prev_whitespace || self.inner.prev().is_none()) =>
+            {
                 self.inner.find(|&c| c == b'\n');
+                if self.heredoc_pending() {
+                    self.read_heredocs();
+                }
                 if self.array == 0 && self.subshell == 0 && !self.and_or && !self.empty {
-                    self.terminated = true;
-                    None
+                    if self.group > 0 {
+                        self.whitespace = true;
+                        self.separated = true;
+                        Some(b'\n')
+                    } else {
+                        self.terminated = true;
+                        None
+                    }
                 } else {
                     self.whitespace = true;
                     Some(b' ')
@@ -176,7 +394,7 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                 if self.inner.peek() == Some(&b'\n') {
                     self.whitespace = true;
                     self.inner.next();
-                    self.next()
+                    self.next_byte()
                 } else {
                     self.skip_next = true;
                     Some(character)
@@ -187,8 +405,14 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                 Some(character)
             }
             b'\n' if self.array == 0 && self.subshell == 0 && !self.and_or && !self.empty => {
-                self.terminated = true;
-                None
+                if self.group > 0 {
+                    self.whitespace = true;
+                    self.separated = true;
+                    Some(b'\n')
+                } else {
+                    self.terminated = true;
+                    None
+                }
             }
             _ if character.is_ascii_whitespace() => {
                 self.whitespace = true;
@@ -204,17 +428,38 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
     /// Create a new reader on the provided input
     pub fn new(inner: I) -> Self {
         Self {
-            inner:      RearPeekable { iter: inner.peekable(), now: None, last: None },
-            array:      0,
-            skip_next:  false,
-            quotes:     Quotes::None,
-            terminated: false,
-            and_or:     false,
-            whitespace: false,
-            empty:      true,
-            subshell:   0,
+            inner:            RearPeekable {
+                iter:   inner.peekable(),
+                now:    None,
+                last:   None,
+                before: None,
+            },
+            array:            0,
+            skip_next:        false,
+            quotes:           Quotes::None,
+            terminated:       false,
+            and_or:           false,
+            whitespace:       false,
+            empty:            true,
+            subshell:         0,
+            group:            0,
+            separated:        false,
+            comment:          Some(b'#'),
+            raw_quotes:       0,
+            angles:           0,
+            emitted:          0,
+            heredocs:         Vec::new(),
+            pending:          Vec::new(),
+            unclosed_heredoc: false,
         }
     }
+
+    /// Set the character starting a comment, or disable comments with `None`
+    #[must_use]
+    pub const fn with_comment(mut self, comment: Option<u8>) -> Self {
+        self.comment = comment;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -256,6 +501,24 @@ mod testing {
         }
     }
 
+    #[test]
+    fn completeness() {
+        let complete = |input: &str| {
+            let mut terminator = Terminator::new(input.bytes());
+            terminator.terminate();
+            terminator.is_complete()
+        };
+        assert!(complete("echo 'a' \"b\" [c] $(d) { e; }"));
+        assert!(!complete("echo 'a"));
+        assert!(!complete("echo \"a"));
+        assert!(!complete("echo '''a\nb''"));
+        assert!(!complete("let a = [1 2"));
+        assert!(!complete("echo $(echo"));
+        assert!(!complete("(cd dir"));
+        assert!(!complete("true &&"));
+        assert!(!complete("echo \\"));
+    }
+
     #[test]
     fn terminate_array_over_serveral_lines() {
         let input = "let array = [2 4
@@ -274,6 +537,17 @@ mod testing {
         );
     }
 
+    #[test]
+    fn terminate_stderr_capture_over_serveral_lines() {
+        let input = "let output = @&(echo
+            hello)
+            echo second line";
+        assert_serveral_terminations(
+            input,
+            vec!["let output = @&(echo hello)", " echo second line"],
+        );
+    }
+
     #[test]
     fn should_terminate_all_items() {
         let left_input = "fn greet\n  echo hi there\nend\n greet  \n\n#  Some comments\n # \
@@ -298,6 +572,95 @@ mod testing {
         );
     }
 
+    #[test]
+    fn custom_comment_character() {
+        let input = "echo hello % a comment\n% another comment\necho #world";
+        let stmts = input
+            .bytes()
+            .batching(|lines| Terminator::new(lines).with_comment(Some(b'%')).terminate())
+            .collect::<Vec<_>>();
+        assert_eq!(stmts, vec!["echo hello ", " echo #world"]);
+    }
+
+    #[test]
+    fn disabled_comments() {
+        let input = "echo #hello # world\necho end";
+        let stmts = input
+            .bytes()
+            .batching(|lines| Terminator::new(lines).with_comment(None).terminate())
+            .collect::<Vec<_>>();
+        assert_eq!(stmts, vec!["echo #hello # world", "echo end"]);
+    }
+
+    #[test]
+    fn raw_strings() {
+        let input = "echo '''it's a \"raw\" \\ string''' # comment\necho '''''' done";
+        assert_serveral_terminations(
+            input,
+            vec!["echo '''it's a \"raw\" \\ string''' ", "echo '''''' done"],
+        );
+    }
+
+    #[test]
+    fn triple_quoted_strings() {
+        let input = "echo \"\"\"a \"b\"\n  # c\n\"\"\" # comment\necho \"\"\"\"\"\" done";
+        assert_serveral_terminations(
+            input,
+            vec!["echo \"\"\"a \"b\"\n  # c\n\"\"\" ", "echo \"\"\"\"\"\" done"],
+        );
+    }
+
+    #[test]
+    fn multiline_quotes() {
+        let input = "echo \"one\n  # two\n\" 'three\n\tfour;'\necho five";
+        assert_serveral_terminations(
+            input,
+            vec!["echo \"one\n  # two\n\" 'three\n\tfour;'", "echo five"],
+        );
+    }
+
+    #[test]
+    fn subshell_groups_over_several_lines() {
+        let input = "(\n  cd dir # enter it\n  make\n) && (echo $(echo\n  one))\necho two; (\n  \
+                     echo three\n)\necho (four\necho five";
+        assert_serveral_terminations(
+            input,
+            vec![
+                "(\ncd dir \nmake\n) && (echo $(echo one))",
+                "echo two; (\necho three\n)",
+                "echo (four",
+                "echo five",
+            ],
+        );
+    }
+
+    #[test]
+    fn brace_groups_over_several_lines() {
+        let input = "{\n  echo {a,b}\n  (cd dir\n  ls)\n} > out\necho { x\necho end";
+        assert_serveral_terminations(
+            input,
+            vec!["{\necho {a,b}\n(cd dir\nls)\n} > out", "echo { x", "echo end"],
+        );
+    }
+
+    #[test]
+    fn heredocs() {
+        let input =
+            "cat <<-EOF | sort; cat <<- 'END'\n\t\tb $x\n\t  a\n\tEOF\n\tEOF $x\n\tEND\necho done";
+        assert_serveral_terminations(
+            input,
+            vec!["cat <<< \"\"\"b $x\n  a\n\"\"\" | sort; cat <<< '''EOF $x\n'''", "echo done"],
+        );
+
+        let input = "{\n  cat <<-EOF\n\t}\n  EOF\n\tEOF\n}";
+        assert_serveral_terminations(input, vec!["{\ncat <<< \"\"\"}\n  EOF\n\"\"\"\n}"]);
+
+        // The heredoc is incomplete until its delimiter
+        let mut terminator = Terminator::new("cat <<-EOF\n\tbody".bytes());
+        assert_eq!(terminator.terminate().unwrap(), "cat <<< \"\"\"body\n\"\"\"");
+        assert!(!terminator.is_complete());
+    }
+
     fn assert_serveral_terminations(input: &str, expected: Vec<&str>) {
         let stmts =
             input.bytes().batching(|lines| Terminator::new(lines).terminate()).collect::<Vec<_>>();
@@ -0,0 +1,80 @@
+use super::pipelines::{Collector, PipelineParsingError};
+use crate::expansion::{WordIterator, WordToken};
+use std::ops::Range;
+
+/// A token of the input, along with the bytes it spans
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token<'a> {
+    /// The range of bytes of the input covered by the token, quotes included
+    pub span: Range<usize>,
+    /// The token, as it would be given to the expander
+    pub word: WordToken<'a>,
+}
+
+/// Split `input` into the tokens that the shell would expand, without expanding them.
+///
+/// This is meant for tools that need the tokens of the real grammar, such as syntax
+/// highlighters. Unterminated quotes and paired tokens are reported as errors.
+pub fn tokenize(input: &str) -> Result<Vec<Token<'_>>, PipelineParsingError> {
+    Collector::check_words(input)?;
+
+    let mut words = WordIterator::new(input, true);
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while let Some(word) = words.next() {
+        let word = word?;
+        let end = words.position();
+        tokens.push(Token { span: start..end, word });
+        start = end;
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lexers::LevelsError;
+
+    fn spans(input: &str) -> Vec<&str> {
+        tokenize(input).unwrap().into_iter().map(|token| &input[token.span]).collect()
+    }
+
+    #[test]
+    fn tokens_map_to_the_input() {
+        let input = "echo $foo @(ls -a) 'raw $x'";
+        let tokens = tokenize(input).unwrap();
+        assert_eq!(tokens.len(), 7);
+        assert_eq!(
+            tokens[0],
+            Token { span: 0..4, word: WordToken::Normal("echo".into(), false, false) }
+        );
+        assert_eq!(tokens[2], Token { span: 5..9, word: WordToken::Variable("foo", None) });
+        assert_eq!(
+            tokens[4],
+            Token { span: 10..18, word: WordToken::ArrayProcess("ls -a", false, false, None) }
+        );
+        assert_eq!(tokens[6].word, WordToken::Normal("raw $x".into(), false, false));
+        assert_eq!(spans(input), ["echo", " ", "$foo", " ", "@(ls -a)", " ", "'raw $x'"]);
+    }
+
+    #[test]
+    fn spans_cover_methods_and_braces() {
+        assert_eq!(spans("$len(@a){x,y}${b}"), ["$len(@a)", "{x,y}", "${b}"]);
+        assert_eq!(spans("a*.rs ~/b"), ["a*.rs", " ", "~", "/b"]);
+    }
+
+    #[test]
+    fn unterminated_tokens() {
+        assert_eq!(tokenize("echo \"foo"), Err(PipelineParsingError::UnterminatedDoubleQuote));
+        assert_eq!(tokenize("echo 'foo"), Err(PipelineParsingError::UnterminatedSingleQuote));
+        assert_eq!(
+            tokenize("echo $(ls"),
+            Err(PipelineParsingError::Paired(LevelsError::UnmatchedParen))
+        );
+        assert_eq!(
+            tokenize("echo ${foo"),
+            Err(PipelineParsingError::Paired(LevelsError::UnmatchedBrace))
+        );
+        assert_eq!(tokenize(""), Ok(Vec::new()));
+    }
+}
@@ -108,6 +108,18 @@ impl<'b> Shell<'b> {
                     Status::error(format!("ion: cannot export {} because it does not exist.", key))
                 }
             },
+            ExportAction::Unexport(ref keys) => {
+                for key in keys.split_whitespace() {
+                    if let Ok(value) = env::var(key) {
+                        env::remove_var(key);
+                        // The shell reads the variables it doesn't have from the environment
+                        if self.variables.get(key).is_none() {
+                            self.variables.set(key, value);
+                        }
+                    }
+                }
+                Status::SUCCESS
+            }
             ExportAction::List => {
                 let stdout = io::stdout();
                 let mut stdout = stdout.lock();
@@ -128,12 +140,13 @@ impl<'b> Shell<'b> {
         for action in actions {
             let Action(key, operator, expression) = action.map_err(|e| e.to_string())?;
 
-            // sanitize variable names
-            if ["HOME", "HOST", "PWD", "MWD", "SWD", "?"].contains(&key.name) {
+            // sanitize variable names, which may be prefixed by a namespace
+            let (_, name) = Variables::namespace(key.name);
+            if ["HOME", "HOST", "PWD", "MWD", "SWD", "?"].contains(&name) {
                 return Err(format!("not allowed to set `{}`", key.name));
             }
 
-            if !Variables::is_valid_name(key.name) {
+            if !Variables::is_valid_name(name) {
                 return Err("invalid variable name: only alphanumerical characters and \
                             underscores are supported"
                     .to_string());
@@ -159,6 +172,10 @@ impl<'b> Shell<'b> {
                 _ if [Operator::Equal, Operator::OptionalEqual].contains(&operator) => {
                     backup.push((key, rhs))
                 }
+                // Unlike `?=`, `??=` also replaces empty strings, arrays and maps
+                _ if operator == Operator::EmptyEqual && self.variables.get(key.name).is_none() => {
+                    backup.push((key, rhs))
+                }
                 _ => {
                     let lhs = self.variables.get(key.name).ok_or_else(|| {
                         format!("cannot update non existing variable `{}`", key.name)
@@ -225,6 +242,11 @@ fn apply(
             lhs.prepend(rhs);
             Ok(lhs)
         }
+        Operator::EmptyEqual => {
+            let mut lhs = lhs.clone();
+            lhs.default_to(rhs);
+            Ok(lhs)
+        }
         Operator::Filter => match (&lhs, &rhs) {
             (Value::Array(ref array), Value::Str(_)) => {
                 // TODO: this should be avoided, but for now values are expanded too late, so we
@@ -1,6 +1,57 @@
 use crate::expansion;
 use itertools::Itertools;
-use std::fmt;
+use std::{fmt, str::FromStr};
+
+/// When the color namespace, as in `${c::red}`, expands to ANSI escape codes. Otherwise the
+/// colors expand to nothing, so that the output written to files and pipes stays clean.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorMode {
+    /// Only if the standard output is a terminal
+    Auto,
+    /// Always
+    Always,
+    /// Never
+    Never,
+}
+
+impl ColorMode {
+    /// The name of the mode, as given to `set --color`
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Always => "always",
+            Self::Never => "never",
+        }
+    }
+
+    /// Whether colors expand to escape codes at the moment
+    #[must_use]
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Self::Auto => atty::is(atty::Stream::Stdout),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+impl fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.name()) }
+}
+
+impl FromStr for ColorMode {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, ()> {
+        match name {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(()),
+        }
+    }
+}
 
 #[derive(Debug)]
 struct StaticMap {
@@ -8,14 +8,20 @@ use crate::{
     builtins::Status,
     expansion::{
         pipelines::{PipeItem, Pipeline},
-        Expander, ForValueExpression,
+        Expander, ExpanderInternal, ForValueExpression,
     },
-    parser::{parse_and_validate, StatementSplitter, Terminator},
-    shell::{IonError, Job, Value},
+    parser::{
+        lexers::{KeyBuf, Primitive},
+        parse_and_validate, Error as ParseError, FunctionParseError, StatementSplitter,
+        StatementVariant, Terminator,
+    },
+    shell::{variables::Variables, IonError, Job, Value},
     types,
 };
+use glob::Pattern;
 use itertools::Itertools;
 use nix::unistd::Pid;
+use scopes::Scoped;
 use std::{rc::Rc, time::SystemTime};
 use thiserror::Error;
 
@@ -246,6 +252,23 @@ impl<'a> Shell<'a> {
         }
     }
 
+    /// Runs `execute` as a condition, in which failing commands do not run the `ERR` trap.
+    fn in_condition<T>(&mut self, execute: impl FnOnce(&mut Self) -> T) -> T {
+        self.conditions += 1;
+        let result = execute(self);
+        self.conditions -= 1;
+        result
+    }
+
+    /// Executes `statement`, as a condition if it is followed by `&&` or `||`.
+    fn execute_statement_before(&mut self, statement: &Statement, logic: bool) -> Result {
+        if logic {
+            self.in_condition(|shell| shell.execute_statement(statement))
+        } else {
+            self.execute_statement(statement)
+        }
+    }
+
     /// Conditionally executes branches of statements according to evaluated
     /// expressions
     fn execute_if(
@@ -256,14 +279,14 @@ impl<'a> Shell<'a> {
         failure: &[Statement],
     ) -> Result {
         // Try execute success branch
-        self.execute_statements(expression)?;
+        self.in_condition(|shell| shell.execute_statements(expression))?;
         if self.previous_status.is_success() {
             return self.execute_statements(success);
         }
 
         // Try to execute else_if branches
         for ElseIf { expression, success } in else_if {
-            self.execute_statements(expression)?;
+            self.in_condition(|shell| shell.execute_statements(expression))?;
 
             if self.previous_status.is_success() {
                 return self.execute_statements(success);
@@ -283,6 +306,7 @@ impl<'a> Shell<'a> {
     ) -> Result {
         macro_rules! set_vars_then_exec {
             ($chunk:expr, $def:expr) => {
+                self.check_deadline()?;
                 for (key, value) in variables.iter().zip($chunk.chain(::std::iter::repeat($def))) {
                     if key != "_" {
                         self.variables_mut().set(key, value.clone());
@@ -326,7 +350,9 @@ impl<'a> Shell<'a> {
     /// condition is met.
     fn execute_while(&mut self, expression: &[Statement], statements: &[Statement]) -> Result {
         loop {
-            self.execute_statements(expression)?;
+            // Loops of builtins alone would otherwise never reach the deadline of a substitution
+            self.check_deadline()?;
+            self.in_condition(|shell| shell.execute_statements(expression))?;
             if self.previous_status.is_failure() {
                 return Ok(Condition::NoOp);
             }
@@ -345,11 +371,11 @@ impl<'a> Shell<'a> {
         match statement {
             Statement::Let(action) => {
                 self.previous_status = self.local(action);
-                self.variables.set("?", self.previous_status);
+                self.variables.set_silently("?", self.previous_status);
             }
             Statement::Export(action) => {
                 self.previous_status = self.export(action);
-                self.variables.set("?", self.previous_status);
+                self.variables.set_silently("?", self.previous_status);
             }
             Statement::While { expression, statements } => {
                 let condition = self.execute_while(expression, statements)?;
@@ -385,11 +411,7 @@ impl<'a> Shell<'a> {
                 let (pipeline, statements) = expand_pipeline(self, pipeline)?;
                 if !pipeline.items.is_empty() {
                     let status = self.run_pipeline(&pipeline)?;
-
-                    // Retrieve the exit_status and set the $? variable and
-                    // history.previous_status
-                    self.variables_mut().set("?", status);
-                    self.previous_status = status;
+                    self.push_status(status);
                 }
                 if !statements.is_empty() {
                     self.execute_statements(&statements)?;
@@ -438,9 +460,25 @@ impl<'a> Shell<'a> {
             }
             Statement::Not(box_statement) => {
                 // NOTE: Should the condition be used?
-                let _condition = self.execute_statement(box_statement)?;
+                let _condition =
+                    self.in_condition(|shell| shell.execute_statement(box_statement))?;
                 self.previous_status.toggle();
-                self.variables.set("?", self.previous_status);
+                self.variables.set_silently("?", self.previous_status);
+            }
+            Statement::Group { code, subshell: true, outputs, inputs } => {
+                let status =
+                    self.with_redirections(outputs, inputs, |shell| shell.execute_subshell(code))?;
+                self.push_status(status);
+                self.handle_failure(status, true)?;
+            }
+            Statement::Group { code, subshell: false, outputs, inputs } => {
+                let condition = self.with_redirections(outputs, inputs, |shell| {
+                    let statements = shell.parse_block(code)?;
+                    shell.execute_statements(&statements)
+                })?;
+                if condition != Condition::NoOp {
+                    return Ok(condition);
+                }
             }
             Statement::Break => return Ok(Condition::Break),
             Statement::Continue => return Ok(Condition::Continue),
@@ -472,20 +510,23 @@ impl<'a> Shell<'a> {
 
     /// Simply executes all supplied statements.
     pub fn execute_statements(&mut self, statements: &[Statement]) -> Result {
-        self.variables.new_scope(false);
-        let condition = statements
+        let mut shell = self.enter_scope(false);
+        statements
             .iter()
-            .map(|statement| self.execute_statement(statement))
+            .enumerate()
+            .map(|(i, statement)| {
+                let logic =
+                    matches!(statements.get(i + 1), Some(Statement::And(_) | Statement::Or(_)));
+                shell.execute_statement_before(statement, logic)
+            })
             .find(|condition| !matches!(condition, Ok(Condition::NoOp)))
-            .unwrap_or(Ok(Condition::NoOp));
-        self.variables.pop_scope();
-        condition
+            .unwrap_or(Ok(Condition::NoOp))
     }
 
     /// Expand an expression and run a branch based on the value of the
     /// expanded expression
     fn execute_match<T: AsRef<str>>(&mut self, expression: T, cases: &[Case]) -> Result {
-        use regex::RegexSet;
+        use regex::Regex;
         // Logic for determining if the LHS of a match-case construct (the value we are
         // matching against) matches the RHS of a match-case construct (a value
         // in a case statement). For example, checking to see if the value
@@ -497,10 +538,24 @@ impl<'a> Shell<'a> {
         let value = self.expand_string(expression.as_ref())?;
         for case in cases.iter() {
             let is_match = if let Some(v) = &case.value {
-                let v = self.expand_string(v)?;
-                // Anchor to start and end
-                let v = v.into_iter().map(|v| format!("^{}$", v));
-                RegexSet::new(v).ok().map_or(false, |regex| value.iter().all(|v| regex.is_match(v)))
+                // The patterns are matched as they are, not against the files of the directory
+                let v = self.expand_string_no_glob(v)?;
+                let mut patterns = Vec::with_capacity(v.len());
+                for pattern in v {
+                    // Anchor to start and end
+                    match (Regex::new(&format!("^{}$", pattern)), Pattern::new(&pattern)) {
+                        (Err(why), Err(_)) => {
+                            return Err(IonError::InvalidCasePattern(pattern, why))
+                        }
+                        (regex, glob) => patterns.push((regex.ok(), glob.ok())),
+                    }
+                }
+                value.iter().all(|v| {
+                    patterns.iter().any(|(regex, glob)| {
+                        matches!(regex, Some(regex) if regex.is_match(v))
+                            || matches!(glob, Some(glob) if glob.matches(v))
+                    })
+                })
             } else {
                 true
             };
@@ -562,26 +617,129 @@ impl<'a> Shell<'a> {
         set_cmd_duration: bool,
     ) -> std::result::Result<(), IonError> {
         let command_start_time = if set_cmd_duration { Some(SystemTime::now()) } else { None };
+        if self.recursion_depth == 0 {
+            self.cached.clear();
+        }
 
-        for stmt in command_to_execute.batching(|cmd| Terminator::new(cmd).terminate()) {
+        let comment = self.opts.comment;
+        for stmt in command_to_execute
+            .batching(|cmd| Terminator::new(cmd).with_comment(comment).terminate())
+        {
             // Go through all of the statements and build up the block stack
             // When block is done return statement for execution.
-            for statement in StatementSplitter::new(&stmt) {
+            let mut statements = StatementSplitter::new(&stmt).peekable();
+            while let Some(statement) = statements.next() {
                 let statement = parse_and_validate(statement?)?;
                 if let Some(stm) = Self::insert_statement(&mut self.flow_control, statement)? {
-                    self.execute_statement(&stm)?;
+                    let logic = matches!(
+                        statements.peek(),
+                        Some(Ok(StatementVariant::And(_) | StatementVariant::Or(_)))
+                    );
+                    self.execute_statement_before(&stm, logic)?;
                 }
             }
         }
 
         if let Some(start_time) = command_start_time {
             if let Ok(elapsed_time) = start_time.elapsed() {
-                self.variables_mut().set("CMD_DURATION", elapsed_time.as_secs().to_string());
+                self.variables.set_silently("CMD_DURATION", elapsed_time.as_secs().to_string());
             }
         }
 
         Ok(())
     }
+
+    /// Evaluates `code` as the condition of an `if` statement, and tells whether it succeeded.
+    ///
+    /// The code must be complete: a block left open is an error, and blocks opened by the shell
+    /// are left untouched.
+    pub fn eval_condition(&mut self, code: &str) -> std::result::Result<bool, IonError> {
+        let statements = self.parse_block(code)?;
+        self.in_condition(|shell| shell.execute_statements(&statements))?;
+        Ok(self.previous_status.is_success())
+    }
+
+    /// Define a function from Rust, as if it was declared with `fn` in a script. Each parameter
+    /// is given with its type, and `body` is the Ion code the function runs.
+    ///
+    /// The body must be complete: a block left open is an error. It is parsed at once, so
+    /// syntax errors are reported here rather than when the function is called.
+    pub fn define_function(
+        &mut self,
+        name: &str,
+        params: &[(&str, Primitive)],
+        body: &str,
+    ) -> std::result::Result<(), IonError> {
+        if !Variables::is_valid_name(name) {
+            return Err(ParseError::InvalidFunctionName(name.into()).into());
+        }
+        let mut args: Vec<KeyBuf> = Vec::with_capacity(params.len());
+        for &(param, ref kind) in params {
+            if args.iter().any(|arg| arg.name == param) {
+                let cause = FunctionParseError::RepeatedArgument(param.into());
+                return Err(ParseError::InvalidFunctionArgument(cause).into());
+            }
+            args.push(KeyBuf { name: param.into(), kind: kind.clone() });
+        }
+
+        let statements = self.parse_block(body)?;
+        self.variables.set(
+            name,
+            Value::Function(Rc::new(Function::new(None, name.into(), args, statements))),
+        );
+        Ok(())
+    }
+
+    /// Tells whether `buffer` is complete, so that a REPL can run it, or needs more lines. It is
+    /// incomplete when it leaves a quote, a parenthese, a bracket or a block such as `if` open,
+    /// or ends with `&&`, `||` or a backslash. Blocks opened by the shell itself are ignored.
+    ///
+    /// A buffer with a syntax error is complete, so that the error is reported when it runs.
+    #[must_use]
+    pub fn line_is_complete(&self, buffer: &str) -> bool {
+        let mut bytes = buffer.bytes();
+        let mut block = Block::new();
+        loop {
+            let mut terminator = Terminator::new(&mut bytes).with_comment(self.opts.comment);
+            let stmt = match terminator.terminate() {
+                Some(stmt) => stmt,
+                None => return block.is_empty(),
+            };
+            if !terminator.is_complete() {
+                return false;
+            }
+            for statement in StatementSplitter::new(&stmt) {
+                let statement = match statement.and_then(parse_and_validate) {
+                    Ok(statement) => statement,
+                    Err(_) => return true,
+                };
+                if Self::insert_statement(&mut block, statement).is_err() {
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Parses `code` into the statements of a block. A block left open is an error, and blocks
+    /// opened by the shell are left untouched.
+    fn parse_block(&self, code: &str) -> std::result::Result<Block, IonError> {
+        let comment = self.opts.comment;
+        let mut block = Block::new();
+        let mut statements = Vec::new();
+        for stmt in
+            code.bytes().batching(|cmd| Terminator::new(cmd).with_comment(comment).terminate())
+        {
+            for statement in StatementSplitter::new(&stmt) {
+                let statement = parse_and_validate(statement?)?;
+                statements.extend(Self::insert_statement(&mut block, statement)?);
+            }
+        }
+
+        if let Some(statement) = block.last() {
+            return Err(BlockError::UnclosedBlock(statement.to_string()).into());
+        }
+        Ok(statements)
+    }
 }
 
 /// Expand a pipeline containing aliases. As aliases can split the pipeline by having logical
@@ -756,4 +914,117 @@ mod tests {
             assert!(Shell::insert_statement(&mut flow_control, err).is_err());
         }
     }
+
+    #[test]
+    fn eval_condition() {
+        let mut shell = Shell::default();
+        assert!(shell.eval_condition("test 1 = 1").unwrap());
+        assert!(!shell.eval_condition("test 1 = 2").unwrap());
+        assert!(!shell.eval_condition("true && false").unwrap());
+        assert!(shell.eval_condition("if false\nfalse\nelse\ntrue\nend").unwrap());
+
+        // A failing condition does not make the shell exit
+        shell.opts_mut().err_exit = true;
+        assert!(!shell.eval_condition("false").unwrap());
+        shell.opts_mut().err_exit = false;
+
+        assert!(shell.eval_condition("if true").is_err());
+        assert!(shell.eval_condition("end").is_err());
+        assert!(shell.flow_control.is_empty());
+    }
+
+    #[test]
+    fn invalid_case_pattern() {
+        let mut shell = Shell::default();
+        let result = shell.execute_command(&b"match a\n  case \"[\"; echo bracket\nend"[..]);
+        assert!(
+            matches!(result, Err(IonError::InvalidCasePattern(ref pattern, _)) if pattern == "[")
+        );
+        // A pattern only has to be valid in one of the two syntaxes
+        let (output, _) = shell.run_in_subshell("match a.rs\n  case *.rs; echo rust\nend").unwrap();
+        assert_eq!(output.as_str(), "rust\n");
+    }
+
+    #[test]
+    fn line_is_complete() {
+        let shell = Shell::default();
+        let complete = [
+            "echo 'a b' \"c\"",
+            "let a = [1 2]; echo $(echo a)",
+            "if true\n  echo \"a\n b\"\nend",
+            "(cd /\n ls)",
+            "for i in 1..3\n  match $i\n    case 1; echo one\n  end\nend",
+            "cat <<-EOF\n\tbody\n\tEOF",
+            // Errors are reported when the line runs
+            "end",
+        ];
+        for line in &complete {
+            assert!(shell.line_is_complete(line), "{:?} is complete", line);
+        }
+
+        let incomplete = [
+            "echo 'a b",
+            "echo \"a\nb",
+            "echo $(echo a",
+            "(cd /",
+            "let a = [1 2",
+            "true &&",
+            "if true",
+            "if true\n  echo a\nelse",
+            "fn f\n  while true\n  end",
+            "cat <<-EOF\n\tbody",
+        ];
+        for line in &incomplete {
+            assert!(!shell.line_is_complete(line), "{:?} is incomplete", line);
+        }
+    }
+
+    #[test]
+    fn define_function() {
+        let mut shell = Shell::default();
+        shell
+            .define_function(
+                "repeat",
+                &[("word", Primitive::Str), ("times", Primitive::Integer)],
+                "for _ in 1..=$times\n  echo -n $word\nend",
+            )
+            .unwrap();
+        let (output, status) = shell.run_in_subshell("repeat ab 3").unwrap();
+        assert_eq!(output.as_str(), "ababab");
+        assert_eq!(status, Status::SUCCESS);
+        // The parameters are type checked as for declared functions
+        let (_, status) = shell.run_in_subshell("repeat ab three").unwrap();
+        assert!(status.is_failure());
+        assert_eq!(shell.get_func("repeat").unwrap().to_string(), "fn repeat word times:int");
+
+        assert!(shell.define_function("no-dash", &[], "true").is_err());
+        assert!(shell
+            .define_function("twice", &[("a", Primitive::Str), ("a", Primitive::Str)], "true")
+            .is_err());
+        assert!(shell.define_function("open", &[], "if true").is_err());
+        assert!(shell.get_func("open").is_none());
+    }
+
+    #[test]
+    fn brace_group_redirections() {
+        let path = std::env::temp_dir().join(format!("ion-brace-group-{}", std::process::id()));
+        let file = path.to_str().unwrap();
+        let mut shell = Shell::default();
+        shell.variables_mut().set("file", file);
+
+        shell.execute_command(&b"let x = 1; { echo a; echo b; let x = 2 } > $file"[..]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb\n");
+        // The group runs in the shell itself
+        assert_eq!(shell.variables().get_str("x").unwrap().as_str(), "2");
+
+        shell.execute_command(&b"(echo c) >> $file"[..]).unwrap();
+        let (output, _) =
+            shell.run_in_subshell("{ read one; read two; echo $two$one } < $file").unwrap();
+        assert_eq!(output.as_str(), "ba\n");
+        let (output, status) = shell.run_in_subshell("{ cat; false } < $file").unwrap();
+        assert_eq!(output.as_str(), "a\nb\nc\n");
+        assert_eq!(status, Status::FALSE);
+
+        std::fs::remove_file(path).unwrap();
+    }
 }
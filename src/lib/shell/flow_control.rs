@@ -1,10 +1,11 @@
 use crate::{
     assignments::*,
-    expansion::pipelines::Pipeline,
+    expansion::pipelines::{Input, Pipeline, Redirection},
     parser::lexers::assignments::{KeyBuf, Operator, Primitive},
     shell::{IonError, Job, Shell},
     types,
 };
+use scopes::Scoped;
 use smallvec::SmallVec;
 use std::fmt;
 use thiserror::Error;
@@ -75,6 +76,8 @@ pub enum ExportAction {
     LocalExport(String),
     /// Export and update
     Assign(String, Operator, String),
+    /// Remove the variables from the environment, keeping them in the shell
+    Unexport(String),
 }
 
 /// The mode for the next if block
@@ -169,6 +172,17 @@ pub enum Statement {
     Or(Box<Statement>),
     /// Succeed on failure of the inner statement
     Not(Box<Statement>),
+    /// Execute a group of commands, as in `{ ... }` or `( ... )`
+    Group {
+        /// The commands of the group
+        code:     types::Str,
+        /// Run the commands in a subshell, which does not change the state of the shell
+        subshell: bool,
+        /// Where the output of every command of the group goes
+        outputs:  Vec<Redirection>,
+        /// Where every command of the group reads its input from
+        inputs:   Vec<Input>,
+    },
     /// An empty statement
     Default,
 }
@@ -197,6 +211,7 @@ impl fmt::Display for Statement {
                 Statement::And(_) => "And { .. }",
                 Statement::Or(_) => "Or { .. }",
                 Statement::Not(_) => "Not { .. }",
+                Statement::Group { .. } => "Group { .. }",
                 Statement::Return(_) => "Return",
                 Statement::Default => "Default",
             }
@@ -280,15 +295,23 @@ impl Function {
         // Pop off all scopes since function temporarily
         let temporary: Vec<_> = shell.variables.pop_scopes(index).collect();
 
-        shell.variables.new_scope(true);
+        let res = {
+            let mut shell = shell.enter_scope(true);
 
-        for (type_, value) in values {
-            shell.variables.set(&type_.name, value);
-        }
+            for (type_, value) in values {
+                shell.variables.set(&type_.name, value);
+            }
 
-        let res = shell.execute_statements(&self.statements);
+            // The statuses of the caller are not visible from the function, and its changes to
+            // the positional parameters are undone once it returns
+            let status_history = std::mem::take(&mut shell.status_history);
+            let args = shell.args.clone();
+            let res = shell.recurse(|shell| shell.execute_statements(&self.statements));
+            shell.status_history = status_history;
+            shell.args = args;
+            res
+        };
 
-        shell.variables.pop_scope();
         shell.variables.append_scopes(temporary);
         res.map(|_| ())
     }
@@ -308,3 +331,20 @@ impl Function {
         Self { description, name, args, statements }
     }
 }
+
+impl fmt::Display for Function {
+    /// Writes the signature of the function, as it was declared
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "fn {}", self.name)?;
+        for arg in &self.args {
+            match arg.kind {
+                Primitive::Str => write!(f, " {}", arg.name)?,
+                ref kind => write!(f, " {}:{}", arg.name, kind)?,
+            }
+        }
+        if let Some(ref description) = self.description {
+            write!(f, " -- {}", description)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use super::Shell;
+use glob::{Pattern, PatternError};
+
+impl<'a> Shell<'a> {
+    /// Keep the command lines matching any of the glob `patterns` out of the history, replacing
+    /// the patterns set before.
+    ///
+    /// The patterns are left unchanged if one of them is invalid.
+    pub fn set_history_ignore<I, S>(&mut self, patterns: I) -> Result<(), PatternError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.history_ignore = patterns
+            .into_iter()
+            .map(|pattern| Pattern::new(pattern.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Tell whether `command` should be kept out of the history, according to the patterns set
+    /// with [`Shell::set_history_ignore`].
+    #[must_use]
+    pub fn is_history_ignored(&self, command: &str) -> bool {
+        self.history_ignore.iter().any(|pattern| pattern.matches(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: [&str; 5] =
+        ["ls -l", "export TOKEN=secret", "echo hi", "lsblk", " echo spaced"];
+
+    fn recorded(shell: &Shell<'_>) -> Vec<&'static str> {
+        COMMANDS.iter().copied().filter(|command| !shell.is_history_ignored(command)).collect()
+    }
+
+    #[test]
+    fn ignored_commands() {
+        let mut shell = Shell::new();
+        assert_eq!(recorded(&shell), COMMANDS);
+
+        shell.set_history_ignore(["ls *", "*TOKEN=*", " *"]).unwrap();
+        assert_eq!(recorded(&shell), ["echo hi", "lsblk"]);
+
+        // An invalid pattern keeps the previous ones
+        assert!(shell.set_history_ignore(["[", "echo *"]).is_err());
+        assert_eq!(recorded(&shell), ["echo hi", "lsblk"]);
+
+        shell.set_history_ignore(Vec::<String>::new()).unwrap();
+        assert_eq!(recorded(&shell), COMMANDS);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::types;
+use std::collections::HashMap;
+
+/// Caches the home directories that tilde expansion looks up in the user database, so that
+/// expanding `~user` repeatedly does not query the database each time.
+#[derive(Debug, Default)]
+pub struct HomeDirs {
+    dirs: HashMap<types::Str, types::Str>,
+}
+
+impl HomeDirs {
+    /// Get the home directory of `user` (the current user if empty), calling `lookup` only if
+    /// it was not resolved before.
+    pub fn get_or_lookup<F>(&mut self, user: &str, lookup: F) -> Option<types::Str>
+    where
+        F: FnOnce(&str) -> Option<types::Str>,
+    {
+        if let Some(dir) = self.dirs.get(user) {
+            return Some(dir.clone());
+        }
+
+        let dir = lookup(user)?;
+        self.dirs.insert(user.into(), dir.clone());
+        Some(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn caches_lookups() {
+        let calls = Cell::new(0);
+        let lookup = |user: &str| {
+            calls.set(calls.get() + 1);
+            Some(types::Str::from(format!("/home/{}", user)))
+        };
+
+        let mut dirs = HomeDirs::default();
+        for _ in 0..3 {
+            assert_eq!(dirs.get_or_lookup("", lookup), Some("/home/".into()));
+            assert_eq!(dirs.get_or_lookup("foo", lookup), Some("/home/foo".into()));
+        }
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn failed_lookups_are_not_cached() {
+        let calls = Cell::new(0);
+        let lookup = |_: &str| {
+            calls.set(calls.get() + 1);
+            None
+        };
+
+        let mut dirs = HomeDirs::default();
+        assert_eq!(dirs.get_or_lookup("nobody", lookup), None);
+        assert_eq!(dirs.get_or_lookup("nobody", lookup), None);
+        assert_eq!(calls.get(), 2);
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
     expansion::{self, pipelines::RedirectFrom, Expander},
     types, Value,
 };
-use std::{fmt, fs::File, str};
+use std::{env, fmt, fs::File, path::Path, str};
 
 #[derive(Clone)]
 /// A shell job
@@ -22,15 +22,22 @@ impl Job {
     /// Takes the current job's arguments and expands them, one argument at a
     /// time, returning a new `Job` with the expanded arguments.
     pub fn expand<'a>(&self, shell: &mut Shell<'a>) -> expansion::Result<RefinedJob<'a>, IonError> {
-        let mut args = types::Args::new();
-        for arg in &self.args {
-            args.extend(expand_arg(arg, shell)?);
+        if shell.opts.parallel_substitutions {
+            shell
+                .prefetch_substitutions(&self.args)
+                .map_err(|err| expansion::Error::Subprocess(Box::new(err)))?;
         }
+        let expanded =
+            self.args.iter().map(|arg| expand_arg(arg, shell)).collect::<Result<Vec<_>, _>>();
+        shell.prefetched.clear();
+        let args: types::Args = expanded?.into_iter().flatten().collect();
 
         Ok(if let Some(Value::Function(_)) = shell.variables.get(&self.args[0]) {
             RefinedJob::function(self.args.clone(), self.redirection)
         } else if let Some(bt) = shell.builtins.get(&args[0]) {
             RefinedJob::builtin(bt, args, self.redirection)
+        } else if shell.command_not_found.is_some() && command_is_missing(&args[0]) {
+            RefinedJob::not_found(args, self.redirection)
         } else {
             RefinedJob::external(args, self.redirection)
         })
@@ -56,6 +63,15 @@ impl fmt::Debug for Job {
     }
 }
 
+/// Whether an external command would fail to start because it cannot be found
+fn command_is_missing(command: &str) -> bool {
+    if command.contains('/') {
+        return !Path::new(command).exists();
+    }
+    let paths = env::var_os("PATH").unwrap_or_else(|| "/bin".into());
+    !env::split_paths(&paths).any(|dir| dir.join(command).is_file())
+}
+
 /// Expands a given argument and returns it as an `Args`.
 fn expand_arg(arg: &str, shell: &mut Shell<'_>) -> expansion::Result<types::Args, IonError> {
     let res = shell.expand_string(arg)?;
@@ -90,6 +106,9 @@ pub enum Variant<'a> {
     Builtin { main: BuiltinFunction<'a> },
     /// Functions can act as commands too!
     Function,
+    /// A command which could not be found, handled by the callback set with
+    /// [`Shell::set_command_not_found`]
+    NotFound,
     /// Represents redirection into stdin from more than one source
     Cat { sources: Vec<File> },
     Tee {
@@ -180,7 +199,7 @@ impl<'a> RefinedJob<'a> {
 
     /// We must fork on a function and builtin
     pub const fn needs_forking(&self) -> bool {
-        !matches!(self.var, Variant::Function | Variant::Builtin { .. })
+        !matches!(self.var, Variant::Function | Variant::Builtin { .. } | Variant::NotFound)
     }
 
     /// Redirect stdout to file
@@ -238,6 +257,11 @@ impl<'a> RefinedJob<'a> {
         }
     }
 
+    /// Apply the callback for commands which could not be found
+    pub const fn not_found(args: types::Args, redirection: RedirectFrom) -> Self {
+        Self { stdin: None, stdout: None, stderr: None, args, var: Variant::NotFound, redirection }
+    }
+
     /// Apply external program executed by this shell
     pub const fn external(args: types::Args, redirection: RedirectFrom) -> Self {
         Self { stdin: None, stdout: None, stderr: None, args, var: Variant::External, redirection }
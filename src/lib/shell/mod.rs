@@ -4,54 +4,72 @@ mod directory_stack;
 mod flow;
 /// The various blocks
 pub mod flow_control;
+mod history;
+mod home_dirs;
 mod job;
 mod pipe_exec;
 mod shell_expand;
 mod signals;
+mod snapshot;
+mod subshell;
 pub mod sys;
+mod traps;
 /// Variables for the shell
 pub mod variables;
 
-use self::{
-    directory_stack::DirectoryStack,
-    flow_control::{Block, Function, FunctionError, Statement},
-    pipe_exec::foreground,
-    sys::NULL_PATH,
-    variables::Variables,
-};
 pub use self::{
+    colors::ColorMode,
     flow::BlockError,
     job::{Job, RefinedJob},
     pipe_exec::{
         job_control::{BackgroundEvent, BackgroundProcess},
         PipelineError,
     },
-    variables::Value,
+    snapshot::Snapshot,
+    traps::Trap,
+    variables::{Value, VarSetCallback},
+};
+use self::{
+    directory_stack::DirectoryStack,
+    flow_control::{Block, Function, FunctionError, Statement},
+    home_dirs::HomeDirs,
+    pipe_exec::foreground,
+    subshell::Subshell,
+    sys::NULL_PATH,
+    variables::Variables,
 };
 use crate::{
     assignments::value_check,
     builtins::{BuiltinMap, Status},
     expansion::{
         pipelines::{PipeType, Pipeline},
-        Error as ExpansionError,
+        Error as ExpansionError, GlobOptions,
     },
     parser::{
         lexers::{Key, Primitive},
         Error as ParseError,
     },
+    types,
 };
 use nix::{
     sys::signal::{self, SigHandler},
     unistd::Pid,
 };
+use scopes::Scoped;
 use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
     convert::TryFrom,
+    env,
     fs::File,
+    io::{self, Write},
     mem,
     ops::{Deref, DerefMut},
+    os::unix::io::{AsRawFd, RawFd},
+    path::Path,
     rc::Rc,
     sync::{atomic::Ordering, Arc, Mutex},
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 use thiserror::Error;
 
@@ -76,6 +94,12 @@ pub enum IonError {
     /// Could not properly expand to a pipeline
     #[error("expansion error: {0}")]
     ExpansionError(#[source] ExpansionError<IonError>),
+    /// Functions, evals or substitutions were nested too deeply
+    #[error("maximum recursion depth of {0} exceeded")]
+    RecursionLimit(usize),
+    /// The pattern of a case is neither a regular expression nor a glob
+    #[error("case pattern '{0}' is neither a regular expression nor a glob: {1}")]
+    InvalidCasePattern(types::Str, #[source] regex::Error),
 }
 
 impl From<ParseError> for IonError {
@@ -103,17 +127,112 @@ impl From<ExpansionError<Self>> for IonError {
     fn from(cause: ExpansionError<Self>) -> Self { Self::ExpansionError(cause) }
 }
 
+impl IonError {
+    /// The exit code that a shell failing with this error reports: 127 if a command was not
+    /// found, 126 if it could not be executed, 128 plus the signal number if a signal ended
+    /// the job, the status of the failed pipeline on an early exit, and 1 for any other error.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 { Status::from(self).as_os_code() }
+}
+
+impl From<&IonError> for Status {
+    fn from(err: &IonError) -> Self {
+        match err {
+            IonError::PipelineExecutionError(err) => match err {
+                PipelineError::CommandNotFound(_) => Self::NO_SUCH_COMMAND,
+                PipelineError::CommandExecError(err, _)
+                    if err.kind() == io::ErrorKind::NotFound =>
+                {
+                    Self::NO_SUCH_COMMAND
+                }
+                PipelineError::CommandExecError(..) => Self::COULD_NOT_EXEC,
+                PipelineError::Interrupted(_, signal)
+                | PipelineError::CoreDumpBySignal(_, signal) => Self::signaled(*signal as i32),
+                PipelineError::EarlyExit(status) => *status,
+                _ => Self::FALSE,
+            },
+            IonError::ExpansionError(ExpansionError::Subprocess(err)) => Self::from(&**err),
+            _ => Self::FALSE,
+        }
+    }
+}
+
+impl From<IonError> for Status {
+    fn from(err: IonError) -> Self { Self::from(&err) }
+}
+
+/// The result of an operation on the shell
+pub type IonResult<T> = Result<T, IonError>;
+
+/// How deeply functions, evals and command substitutions may be nested by default
+pub const DEFAULT_MAX_RECURSION_DEPTH: usize = 256;
+
+/// How many statuses are kept in [`Shell::status_history`]
+pub const STATUS_HISTORY_SIZE: usize = 16;
+
+/// How many empty strings an assignment past the end of an array may add before its index.
+/// Assignments further away fail, as they do with [`Options::strict_indices`].
+pub const MAX_ARRAY_GROWTH: usize = 65_536;
+
 /// Options for the shell
-#[derive(Debug, Clone, Hash, Default)]
+#[derive(Debug, Clone, Hash)]
 pub struct Options {
     /// Exit from the shell on the first error.
-    pub err_exit:  bool,
+    pub err_exit:               bool,
     /// Activates the -p option, aka pipefail in bash
-    pub pipe_fail: bool,
+    pub pipe_fail:              bool,
     /// Do not execute any commands given to the shell.
-    pub no_exec:   bool,
+    pub no_exec:                bool,
     /// If set, denotes that this shell is running as a background job.
-    pub grab_tty:  bool,
+    pub grab_tty:               bool,
+    /// The character starting a comment, `#` by default. Comments are disabled when unset.
+    pub comment:                Option<u8>,
+    /// How glob patterns are matched against paths during expansion.
+    pub glob_options:           GlobOptions,
+    /// Leave glob patterns as they are instead of expanding them, as `set -f` does in bash.
+    pub no_glob:                bool,
+    /// How deeply functions, evals and command substitutions may be nested. Exceeding it fails
+    /// with [`IonError::RecursionLimit`]. There is no limit when unset.
+    pub max_recursion:          Option<usize>,
+    /// How long a command substitution may run. Exceeding it kills the commands still running
+    /// and fails the expansion with a [`PipelineError::TimedOut`]. There is no limit when unset.
+    pub command_timeout:        Option<Duration>,
+    /// Run the command substitutions of a command in parallel subshells. They then cannot
+    /// change the variables or the working directory of the shell.
+    pub parallel_substitutions: bool,
+    /// Fail the assignments to indices past the end of an array, instead of extending the array
+    /// with empty strings up to the index.
+    pub strict_indices:         bool,
+    /// Interpret backslash escapes in the arguments of `echo`, unless `-E` is given, as if `-e`
+    /// was always given.
+    pub echo_escapes:           bool,
+    /// When the color namespace, as in `${c::red}`, expands to ANSI escape codes.
+    pub color:                  ColorMode,
+    /// Substitute nothing for a command substitution whose command is not found, instead of
+    /// failing the expansion. The status is set to 127 either way, and the expansion still
+    /// fails when `err_exit` is set.
+    pub lenient_substitutions:  bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            err_exit:               false,
+            pipe_fail:              false,
+            no_exec:                false,
+            grab_tty:               false,
+            comment:                Some(b'#'),
+            glob_options:           GlobOptions::default(),
+            no_glob:                false,
+            max_recursion:          Some(DEFAULT_MAX_RECURSION_DEPTH),
+            command_timeout:        None,
+            parallel_substitutions: false,
+            strict_indices:         false,
+            echo_escapes:           false,
+            color:                  ColorMode::Always,
+            lenient_substitutions:  false,
+        }
+    }
 }
 
 /// The shell structure is a megastructure that manages all of the state of the shell throughout
@@ -130,9 +249,29 @@ pub struct Shell<'a> {
     flow_control:       Block,
     /// Contains the directory stack parameters.
     directory_stack:    DirectoryStack,
+    /// Caches the home directories resolved during tilde expansion.
+    home_dirs:          RefCell<HomeDirs>,
+    /// Command lines matching these patterns are kept out of the history.
+    history_ignore:     Vec<glob::Pattern>,
+    /// How deeply functions, evals and command substitutions are currently nested.
+    recursion_depth:    usize,
+    /// When the command substitutions being expanded must be done by, if they are limited.
+    deadline:           Option<Instant>,
+    /// The output of the command substitutions which already ran in parallel subshells.
+    prefetched:         Vec<(types::Str, Vec<u8>)>,
+    /// The output of the command substitutions passed to `$cached`, by command. They are
+    /// forgotten once the command line being executed is done.
+    cached:             HashMap<types::Str, types::Str>,
+    /// The processes of the process substitutions in the pipelines being run, with the end of
+    /// their pipe that the shell keeps open for the path substituted.
+    substitutions:      Vec<(Subshell, File)>,
     /// When a command is executed, the final result of that command is stored
     /// here.
     previous_status:    Status,
+    /// The statuses of the last pipelines, from the most recent one.
+    status_history:     Vec<Status>,
+    /// The positional parameters, read as `@args` unless a variable of that name is set.
+    args:               Vec<types::Str>,
     /// The job ID of the previous command sent to the background.
     previous_job:       usize,
     /// Contains all the options relative to the shell
@@ -143,19 +282,34 @@ pub struct Shell<'a> {
     /// When the `fg` command is run, this will be used to communicate with the specified
     /// background process.
     foreground_signals: Arc<foreground::Signals>,
+    /// The code to run for each trap, set with the `trap` builtin
+    traps:              BTreeMap<Trap, types::Str>,
+    /// How many conditions are being executed, in which failures do not run the `ERR` trap
+    conditions:         usize,
+    /// Whether the `DEBUG` trap is running, so that its own commands do not run it
+    debugging:          bool,
 
     // Callbacks
     /// Custom callback for each command call
-    on_command:       Option<OnCommandCallback<'a>>,
+    on_command:        Option<OnCommandCallback<'a>>,
     /// Custom callback before each command call
-    pre_command:      Option<PreCommandCallback<'a>>,
+    pre_command:       Option<PreCommandCallback<'a>>,
     /// Custom callback when a background event occurs
-    background_event: Option<BackgroundEventCallback>,
+    background_event:  Option<BackgroundEventCallback>,
+    /// Custom callback receiving the output of background jobs
+    background_output: Option<BackgroundOutputCallback>,
+    /// Custom callback when `cd` changes the working directory
+    on_cwd_change:     Option<CwdChangeCallback<'a>>,
+    /// Custom callback when a command could not be found
+    command_not_found: Option<CommandNotFoundCallback<'a>>,
 
     // Default std pipes
-    stdin:  Option<File>,
-    stdout: Option<File>,
-    stderr: Option<File>,
+    stdin:   Option<File>,
+    stdout:  Option<File>,
+    stderr:  Option<File>,
+    /// The pipe capturing the standard output in [`Shell::execute_command_captured`], along with
+    /// the standard output it replaced
+    capture: Option<(RawFd, Option<File>)>,
 }
 
 /// A callback that is executed after each pipeline is run
@@ -164,12 +318,27 @@ pub type OnCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, std::time::Duration) + '
 pub type PreCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, &Pipeline<RefinedJob<'_>>) + 'a>;
 /// A callback that is executed when a background event occurs
 pub type BackgroundEventCallback = Arc<dyn Fn(usize, Pid, BackgroundEvent) + Send + Sync>;
+/// A callback that receives each line written to stdout or stderr by a background job
+pub type BackgroundOutputCallback = Arc<dyn Fn(usize, Pid, &str) + Send + Sync>;
+/// A callback that receives the new working directory after `cd` changed it
+pub type CwdChangeCallback<'a> = Box<dyn FnMut(&Path) + 'a>;
+/// A callback that handles a command which could not be found, given with its arguments
+///
+/// It returns the status of the command, or `None` to fail with a "command not found" error.
+pub type CommandNotFoundCallback<'a> =
+    Box<dyn FnMut(&mut Shell<'_>, &[types::Str]) -> Option<Status> + 'a>;
 
 impl<'a> Default for Shell<'a> {
     #[must_use]
     fn default() -> Self { Self::new() }
 }
 
+impl<'a> Scoped for Shell<'a> {
+    fn new_scope(&mut self, namespace: bool) { self.variables.new_scope(namespace) }
+
+    fn pop_scope(&mut self) { self.variables.pop_scope() }
+}
+
 impl<'a> Shell<'a> {
     /// Install signal handlers necessary for the shell to work
     fn install_signal_handler() {
@@ -196,6 +365,31 @@ impl<'a> Shell<'a> {
     #[must_use]
     pub fn new() -> Self { Self::with_builtins(BuiltinMap::default()) }
 
+    /// Create a shell set up for interactive use, the same way as the `ion` binary: the unsafe
+    /// builtins, such as `eval` and `set`, are added to the default ones, and the changes of the
+    /// background jobs are reported on the standard error.
+    ///
+    /// The builtins that act on the whole process, like `exit` and `exec`, are left to the host.
+    #[must_use]
+    pub fn with_interactive_defaults() -> Self {
+        let mut builtins = BuiltinMap::default();
+        builtins.with_unsafe();
+
+        let mut shell = Self::with_builtins(builtins);
+        shell.set_background_event(Some(Arc::new(|njob, pid, kind| match kind {
+            BackgroundEvent::Added => eprintln!("ion: bg [{}] {}", njob, pid),
+            BackgroundEvent::Stopped => eprintln!("ion: ([{}] {}) Stopped", njob, pid),
+            BackgroundEvent::Resumed => eprintln!("ion: ([{}] {}) Running", njob, pid),
+            BackgroundEvent::Exited(status) => {
+                eprintln!("ion: ([{}] {}) exited with {}", njob, pid, status)
+            }
+            BackgroundEvent::Errored(error) => {
+                eprintln!("ion: ([{}] {}) errored: {}", njob, pid, error)
+            }
+        })));
+        shell
+    }
+
     /// Create a shell with custom builtins
     #[must_use]
     pub fn with_builtins(builtins: BuiltinMap<'a>) -> Self {
@@ -210,18 +404,34 @@ impl<'a> Shell<'a> {
             variables: Variables::default(),
             flow_control: Block::with_capacity(5),
             directory_stack: DirectoryStack::new(),
+            home_dirs: RefCell::default(),
+            history_ignore: Vec::new(),
+            recursion_depth: 0,
+            deadline: None,
+            prefetched: Vec::new(),
+            cached: HashMap::new(),
+            substitutions: Vec::new(),
             previous_job: !0,
             previous_status: Status::SUCCESS,
+            status_history: Vec::with_capacity(STATUS_HISTORY_SIZE),
+            args: Vec::new(),
             opts: Options::default(),
             background: Arc::new(Mutex::new(Vec::new())),
             foreground_signals: Arc::new(foreground::Signals::new()),
+            traps: BTreeMap::new(),
+            conditions: 0,
+            debugging: false,
             on_command: None,
             pre_command: None,
             background_event: None,
+            on_cwd_change: None,
+            command_not_found: None,
+            background_output: None,
 
             stdin: None,
             stdout: None,
             stderr: None,
+            capture: None,
         }
     }
 
@@ -240,6 +450,32 @@ impl<'a> Shell<'a> {
         mem::replace(&mut self.stderr, stderr.into())
     }
 
+    /// The standard output of the jobs sent to the background. They write to the standard
+    /// output replaced by the capture of [`Shell::execute_command_captured`] instead of the
+    /// capture itself, which would otherwise wait for them to exit.
+    fn background_stdout(&self) -> Option<&File> {
+        match (&self.capture, &self.stdout) {
+            (Some((pipe, original)), Some(stdout)) if stdout.as_raw_fd() == *pipe => {
+                original.as_ref()
+            }
+            _ => self.stdout.as_ref(),
+        }
+    }
+
+    /// Write out the output left buffered by the builtins, along with the files replacing the
+    /// standard output and error.
+    ///
+    /// This is done before the prompt and when the shell exits, but embedders writing to the
+    /// same outputs should flush the shell before writing their own output.
+    pub fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()?;
+        io::stderr().flush()?;
+        for file in self.stdout.iter_mut().chain(self.stderr.iter_mut()) {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
     /// Access the directory stack
     #[must_use]
     pub const fn dir_stack(&self) -> &DirectoryStack { &self.directory_stack }
@@ -260,6 +496,23 @@ impl<'a> Shell<'a> {
     #[must_use]
     pub fn block_len(&self) -> usize { self.flow_control.len() }
 
+    /// Runs `f` one level of recursion deeper, failing instead if the maximum depth has been
+    /// reached.
+    pub(crate) fn recurse<T, F>(&mut self, f: F) -> Result<T, IonError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, IonError>,
+    {
+        match self.opts.max_recursion {
+            Some(max) if self.recursion_depth >= max => return Err(IonError::RecursionLimit(max)),
+            _ => (),
+        }
+
+        self.recursion_depth += 1;
+        let result = f(self);
+        self.recursion_depth -= 1;
+        result
+    }
+
     /// A method for executing a function, using `args` as the input.
     pub fn execute_function<S: AsRef<str>>(
         &mut self,
@@ -288,18 +541,56 @@ impl<'a> Shell<'a> {
         }
     }
 
+    /// Executes the command(s) in a string, the same way as [`Shell::execute_command`].
+    pub fn execute_command_str(&mut self, command: &str) -> IonResult<Status> {
+        self.execute_command(command.as_bytes())
+    }
+
+    /// Executes a script the same way as [`Shell::execute_command`], with `args` as its `@args`
+    /// array. By convention, the first argument is the name of the script.
+    ///
+    /// The previous value of `@args` is restored once the script returns, even if it failed.
+    pub fn execute_script<T: std::io::Read, S: AsRef<str>>(
+        &mut self,
+        script: T,
+        args: &[S],
+    ) -> Result<Status, IonError> {
+        let args = args.iter().map(|arg| arg.as_ref().into()).collect();
+        let previous = std::mem::replace(&mut self.args, args);
+        let result = self.execute_command(script);
+        self.args = previous;
+        result
+    }
+
     /// Executes a pipeline and returns the final exit status of the pipeline.
     pub fn run_pipeline(&mut self, pipeline: &Pipeline<Job>) -> Result<Status, IonError> {
+        // The substitutions of the outer pipelines still have to reach their own commands
+        let substitutions = self.substitutions.len();
+        let result = self.expand_and_run_pipeline(pipeline);
+        self.finish_substitutions(substitutions);
+        if let Err(ref err @ IonError::PipelineExecutionError(PipelineError::Interrupted(..))) =
+            result
+        {
+            // The job did not exit by itself, which its status has to tell
+            self.previous_status = Status::from(err);
+        }
+        result
+    }
+
+    fn expand_and_run_pipeline(&mut self, pipeline: &Pipeline<Job>) -> Result<Status, IonError> {
         let command_start_time = SystemTime::now();
 
         let mut pipeline = pipeline.expand(self)?;
 
         let null_file =
             if pipeline.pipe == PipeType::Disown { File::open(NULL_PATH).ok() } else { None };
-        let (stderr, stdout) = (
-            null_file.as_ref().or_else(|| self.stderr.as_ref()),
-            null_file.as_ref().or_else(|| self.stdout.as_ref()),
-        );
+        let stdout = if pipeline.pipe == PipeType::Background {
+            self.background_stdout()
+        } else {
+            self.stdout.as_ref()
+        };
+        let (stderr, stdout) =
+            (null_file.as_ref().or_else(|| self.stderr.as_ref()), null_file.as_ref().or(stdout));
 
         for item in &mut pipeline.items {
             item.job.stdin = self
@@ -317,6 +608,12 @@ impl<'a> Shell<'a> {
         if let Some(ref callback) = self.pre_command {
             callback(self, &pipeline);
         }
+        self.run_debug_trap(&pipeline);
+
+        // The failures inside a function already ran the `ERR` trap, not the call itself
+        let function_call = matches!(pipeline.items.last(), Some(item)
+            if self.builtins.get(item.command()).is_none()
+                && matches!(self.variables.get(&item.job.args[0]), Some(Value::Function(_))));
 
         // Don't execute commands when the `-n` flag is passed.
         let exit_status = if self.opts.no_exec {
@@ -343,13 +640,25 @@ impl<'a> Shell<'a> {
             }
         }
 
-        if self.opts.err_exit && !exit_status.is_success() {
-            return Err(PipelineError::EarlyExit(exit_status).into());
-        }
-
+        self.handle_failure(exit_status, !function_call)?;
         Ok(exit_status)
     }
 
+    /// Runs the `ERR` trap if `status` is a failure and `run_trap` is set, and stops the
+    /// execution with `set -e`.
+    fn handle_failure(&mut self, status: Status, run_trap: bool) -> Result<(), IonError> {
+        // Failures in conditions are expected, so neither the `ERR` trap nor `set -e` handle them
+        if !status.is_success() && self.conditions == 0 {
+            if run_trap {
+                self.run_err_trap(status);
+            }
+            if self.opts.err_exit {
+                return Err(PipelineError::EarlyExit(status).into());
+            }
+        }
+        Ok(())
+    }
+
     /// Get the pid of the last executed job
     #[must_use]
     pub const fn previous_job(&self) -> Option<usize> {
@@ -371,6 +680,20 @@ impl<'a> Shell<'a> {
         &mut self.background_event
     }
 
+    /// Set the callback receiving the output of background jobs line by line
+    ///
+    /// When set, the stdout and stderr of jobs sent to the background are piped to this callback
+    /// instead of the terminal.
+    pub fn set_background_output(&mut self, callback: Option<BackgroundOutputCallback>) {
+        self.background_output = callback;
+    }
+
+    /// Set the callback receiving the output of background jobs line by line
+    #[must_use]
+    pub fn background_output_mut(&mut self) -> &mut Option<BackgroundOutputCallback> {
+        &mut self.background_output
+    }
+
     /// Set the callback to call before each command
     pub fn set_pre_command(&mut self, callback: Option<PreCommandCallback<'a>>) {
         self.pre_command = callback;
@@ -390,6 +713,74 @@ impl<'a> Shell<'a> {
     /// Set the callback to call on each command
     pub fn on_command_mut(&mut self) -> &mut Option<OnCommandCallback<'a>> { &mut self.on_command }
 
+    /// Set the callback to call when `cd` changes the working directory
+    ///
+    /// The callback receives the new working directory, as an absolute path.
+    pub fn set_on_cwd_change(&mut self, callback: Option<CwdChangeCallback<'a>>) {
+        self.on_cwd_change = callback;
+    }
+
+    /// Set the callback to call when `cd` changes the working directory
+    #[must_use]
+    pub fn on_cwd_change_mut(&mut self) -> &mut Option<CwdChangeCallback<'a>> {
+        &mut self.on_cwd_change
+    }
+
+    /// Set the callback to call when a variable is set, with its name and new value
+    ///
+    /// It is called for the assignments of scripts, such as `let theme = dark`, as well as for
+    /// the variables that the shell sets itself once created, such as the parameters of functions
+    /// and the variables of `for` loops. Variables set by the embedder through
+    /// [`Shell::variables_mut`] are reported too. An assignment to an element of an array or a
+    /// map reports the whole array or map. The `$?` and `$CMD_DURATION` that the shell updates
+    /// after each command are not reported.
+    pub fn set_on_var_set(&mut self, callback: Option<VarSetCallback>) {
+        self.variables.set_on_set(callback);
+    }
+
+    /// Notify the callback set with [`Shell::set_on_cwd_change`] of a new working directory
+    pub(crate) fn cwd_changed(&mut self) {
+        if let Some(ref mut callback) = self.on_cwd_change {
+            if let Ok(cwd) = env::current_dir() {
+                callback(&cwd);
+            }
+        }
+    }
+
+    /// Set the callback to call when a command could not be found, before failing
+    ///
+    /// If the callback returns a status, it is used as the status of the command. Otherwise,
+    /// the "command not found" error is returned. Like builtins, the callback runs in a fork of
+    /// the shell when the command is piped, with the standard streams of the command.
+    pub fn set_command_not_found(&mut self, callback: Option<CommandNotFoundCallback<'a>>) {
+        self.command_not_found = callback;
+    }
+
+    /// Set the callback to call when a command could not be found, before failing
+    #[must_use]
+    pub fn command_not_found_mut(&mut self) -> &mut Option<CommandNotFoundCallback<'a>> {
+        &mut self.command_not_found
+    }
+
+    fn handle_command_not_found(&mut self, args: &[types::Str]) -> Option<Status> {
+        // The callback is taken out while it runs, as it receives the shell
+        let mut callback = self.command_not_found.take()?;
+        let status = callback(self, args);
+        if self.command_not_found.is_none() {
+            self.command_not_found = Some(callback);
+        }
+        status
+    }
+
+    /// Set how deeply functions, evals and command substitutions may be nested before
+    /// failing with [`IonError::RecursionLimit`], or remove the limit with `None`
+    pub fn set_max_recursion_depth(&mut self, depth: Option<usize>) {
+        self.opts.max_recursion = depth;
+    }
+
+    /// Set whether `echo` interprets backslash escapes when neither `-e` nor `-E` is given
+    pub fn set_default_echo_escapes(&mut self, escapes: bool) { self.opts.echo_escapes = escapes; }
+
     /// Get access to the builtins
     #[must_use]
     pub const fn builtins(&self) -> &BuiltinMap<'a> { &self.builtins }
@@ -418,6 +809,14 @@ impl<'a> Shell<'a> {
     #[must_use]
     pub fn variables_mut(&mut self) -> &mut Variables { &mut self.variables }
 
+    /// The positional parameters, available to scripts as `@args`. The first one is the name of
+    /// the script or of the shell.
+    #[must_use]
+    pub fn args(&self) -> &[types::Str] { &self.args }
+
+    /// Replace the positional parameters, including the name of the script.
+    pub fn set_args(&mut self, args: Vec<types::Str>) { self.args = args; }
+
     /// Access to the variables
     #[must_use]
     pub fn background_jobs(&self) -> impl Deref<Target = Vec<BackgroundProcess>> + '_ {
@@ -438,6 +837,36 @@ impl<'a> Shell<'a> {
         }
     }
 
+    /// Define an alias in the global scope, replacing any previous definition, as the `alias`
+    /// builtin does.
+    pub fn define_alias(&mut self, name: &str, expansion: &str) {
+        self.variables.set_global(name, types::Alias(expansion.into()));
+    }
+
+    /// Remove an alias, telling whether it was defined.
+    pub fn remove_alias(&mut self, name: &str) -> bool {
+        if let Some(Value::Alias(_)) = self.variables.get(name) {
+            self.variables.remove(name).is_some()
+        } else {
+            false
+        }
+    }
+
+    /// The aliases which are defined, with their expansion, sorted by name.
+    #[must_use]
+    pub fn aliases(&self) -> Vec<(types::Str, types::Str)> {
+        let mut aliases: Vec<_> = self
+            .variables
+            .aliases()
+            .filter(|&(name, _)| matches!(self.variables.get(name), Some(Value::Alias(_))))
+            .map(|(name, alias)| (name.clone(), alias.clone()))
+            .collect();
+        // The innermost definitions come first, and are the ones kept
+        aliases.sort_by(|a, b| a.0.cmp(&b.0));
+        aliases.dedup_by(|a, b| a.0 == b.0);
+        aliases
+    }
+
     /// Get the last command's return code and/or the code for the error
     pub fn set_previous_status(&mut self, status: Status) { self.previous_status = status; }
 
@@ -445,6 +874,21 @@ impl<'a> Shell<'a> {
     #[must_use]
     pub const fn previous_status(&self) -> Status { self.previous_status }
 
+    /// Get the statuses of the last pipelines, from the most recent one, as read with `$?[n]`.
+    ///
+    /// Up to [`STATUS_HISTORY_SIZE`] statuses are kept. A function call starts with an empty
+    /// history, and the history of its caller is restored once it returns.
+    #[must_use]
+    pub fn status_history(&self) -> &[Status] { &self.status_history }
+
+    /// Set `status` as the status of the last pipeline, and add it to the status history
+    fn push_status(&mut self, status: Status) {
+        self.variables.set_silently("?", status);
+        self.previous_status = status;
+        self.status_history.truncate(STATUS_HISTORY_SIZE - 1);
+        self.status_history.insert(0, status);
+    }
+
     fn assign(&mut self, key: &Key<'_>, value: Value<Rc<Function>>) -> Result<(), String> {
         match (&key.kind, &value) {
             (Primitive::Indexed(ref index_name, ref index_kind), Value::Str(_)) => {
@@ -461,25 +905,43 @@ impl<'a> Shell<'a> {
                         match lhs {
                             Value::HashMap(hmap) => {
                                 let _ = hmap.insert(index, value);
-                                Ok(())
                             }
                             Value::BTreeMap(bmap) => {
                                 let _ = bmap.insert(index, value);
-                                Ok(())
                             }
                             Value::Array(array) => {
-                                let index_num = index.parse::<usize>().map_err(|_| {
+                                let index_num = index.parse::<isize>().map_err(|_| {
                                     format!("index variable is not a numeric value: `{}`", index)
                                 })?;
+                                // Negative indices count from the end, as when reading
+                                let index_num = if index_num < 0 {
+                                    array.len().checked_sub(index_num.unsigned_abs()).ok_or_else(
+                                        || format!("index out of bounds: `{}`", index_num),
+                                    )?
+                                } else {
+                                    index_num as usize
+                                };
 
-                                if let Some(var) = array.get_mut(index_num) {
-                                    *var = value;
+                                if index_num >= array.len() {
+                                    if self.opts.strict_indices
+                                        || index_num - array.len() > MAX_ARRAY_GROWTH
+                                    {
+                                        return Err(format!(
+                                            "index out of bounds: `{}`",
+                                            index_num
+                                        ));
+                                    }
+                                    array.resize(index_num + 1, Value::Str(types::Str::new()));
                                 }
-                                Ok(())
+                                array[index_num] = value;
+                            }
+                            Value::Str(_) => {
+                                return Err("cannot assign to an index of a string".into())
                             }
-                            Value::Str(_) => Err("cannot assign to an index of a string".into()),
-                            _ => Ok(()),
+                            _ => return Ok(()),
                         }
+                        self.variables.notify_modified(key.name);
+                        Ok(())
                     }
                     Value::Array(_) => Err("index variable cannot be an array".into()),
                     Value::HashMap(_) => Err("index variable cannot be a hmap".into()),
@@ -491,10 +953,525 @@ impl<'a> Shell<'a> {
             | (_, Value::Array(_))
             | (Primitive::HashMap(_), Value::HashMap(_))
             | (Primitive::BTreeMap(_), Value::BTreeMap(_)) => {
-                self.variables.set(key.name, value);
-                Ok(())
+                if self.variables.set_namespaced(key.name, value) {
+                    Ok(())
+                } else {
+                    Err(format!("no namespace to set `{}` in", key.name))
+                }
             }
             _ => Ok(()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{builtins::BuiltinFunction, expansion::Expander};
+    use nix::unistd;
+    use serial_test_derive::serial;
+    use std::{
+        cell::Cell,
+        fs,
+        io::{self, Read, Write},
+        os::unix::io::FromRawFd,
+        thread,
+    };
+
+    /// Debug builds need a lot of stack to reach the recursion limit
+    fn with_large_stack<F: FnOnce() + Send + 'static>(f: F) {
+        thread::Builder::new().stack_size(256 * 1024 * 1024).spawn(f).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn recursive_function_hits_limit() {
+        with_large_stack(|| {
+            let mut shell = Shell::new();
+            let result = shell.execute_command(&b"fn rec\n  rec\nend\nrec"[..]);
+            assert!(matches!(result, Err(IonError::RecursionLimit(DEFAULT_MAX_RECURSION_DEPTH))));
+
+            // The depth is restored once the error unwinds
+            assert_eq!(shell.recursion_depth, 0);
+            assert_eq!(shell.execute_command(&b"test 1 = 1"[..]).unwrap(), Status::SUCCESS);
+        });
+    }
+
+    #[test]
+    fn recursive_substitution_hits_limit() {
+        with_large_stack(|| {
+            let mut shell = Shell::new();
+            let result = shell.execute_command(&b"fn sub\n  echo $(sub)\nend\nsub"[..]);
+            match result {
+                Err(IonError::ExpansionError(ExpansionError::Subprocess(err))) => {
+                    assert!(matches!(*err, IonError::RecursionLimit(DEFAULT_MAX_RECURSION_DEPTH)))
+                }
+                _ => panic!("expected a recursion error, got {:?}", result),
+            }
+            assert_eq!(shell.recursion_depth, 0);
+        });
+    }
+
+    #[test]
+    fn script_args_are_restored() {
+        let mut shell = Shell::new();
+        let script = &b"echo @args > /dev/null; test @args[1] = first && test $len(@args) = 3"[..];
+        assert_eq!(
+            shell.execute_script(script, &["script", "first", "second"]).unwrap(),
+            Status::SUCCESS
+        );
+        assert!(shell.args().is_empty());
+
+        shell.set_args(vec!["outer".into()]);
+        let script = &b"set -- changed; if true"[..];
+        assert!(shell.execute_script(script, &["script"]).is_err());
+        shell.reset_flow();
+        assert_eq!(shell.args(), ["outer"]);
+    }
+
+    #[test]
+    fn positional_args_are_scoped() {
+        let mut builtins = BuiltinMap::default();
+        builtins.with_unsafe();
+        let mut shell = Shell::with_builtins(builtins);
+        let sourced = mktemp::Temp::new_file().unwrap();
+        fs::write(&sourced, "set -- sourced").unwrap();
+        shell.set_args(vec!["script".into(), "a".into(), "b".into()]);
+        let script = format!(
+            "fn inner\n  set -- x y z\n  let global::from_inner = [@args]\nend\nfn outer\n  let \
+             global::before = [@args]\n  inner\n  let global::after = [@args]\nend\nouter\nsource \
+             {0}\nsource {0} other",
+            sourced.as_path().display()
+        );
+        shell.execute_command(script.as_bytes()).unwrap();
+
+        assert_eq!(shell.expand_string("@before").unwrap(), args!["script", "a", "b"]);
+        assert_eq!(shell.expand_string("@from_inner").unwrap(), args!["script", "x", "y", "z"]);
+        assert_eq!(shell.expand_string("@after").unwrap(), args!["script", "a", "b"]);
+        assert_eq!(shell.args(), ["script", "a", "b"]);
+    }
+
+    #[test]
+    #[serial]
+    fn var_set_callback() {
+        let sets = Rc::new(RefCell::new(Vec::new()));
+        let mut shell = Shell::new();
+        let recorded = sets.clone();
+        shell.set_on_var_set(Some(Box::new(move |name, value| {
+            recorded.borrow_mut().push((name.to_string(), value.to_string()));
+        })));
+        let script = &b"let theme = dark\nfn configure size\n  let global::font = $size\nend\n\
+                        configure 12\nlet sizes = [10 12]; let sizes[1] = 14"[..];
+        shell.execute_command(script).unwrap();
+
+        // Restoring a snapshot keeps the callback
+        let snapshot = shell.snapshot();
+        shell.restore(snapshot).unwrap();
+        shell.variables_mut().set("embedder", "value");
+        assert_eq!(
+            *sets.borrow(),
+            [
+                ("theme".to_string(), "dark".to_string()),
+                ("configure".to_string(), String::new()),
+                ("size".to_string(), "12".to_string()),
+                ("font".to_string(), "12".to_string()),
+                ("sizes".to_string(), "10 12".to_string()),
+                ("sizes".to_string(), "10 14".to_string()),
+                ("embedder".to_string(), "value".to_string()),
+            ]
+        );
+
+        shell.set_on_var_set(None);
+        shell.execute_command(&b"let theme = light"[..]).unwrap();
+        assert_eq!(sets.borrow().len(), 7);
+    }
+
+    #[test]
+    fn command_not_found_callback() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut shell = Shell::new();
+        let recorded = calls.clone();
+        shell.set_command_not_found(Some(Box::new(move |shell, args| {
+            recorded.borrow_mut().push(args.to_vec());
+            shell.variables_mut().set("handled", args[0].clone());
+            if args[0] == "ion-missing-handled" {
+                Some(Status::from_exit_code(42))
+            } else {
+                None
+            }
+        })));
+
+        let status = shell.execute_command_str("ion-missing-handled a 'b c'").unwrap();
+        assert_eq!(status, Status::from_exit_code(42));
+        assert_eq!(shell.variables().get_str("handled").unwrap(), "ion-missing-handled");
+
+        let result = shell.execute_command_str("ion-missing-other");
+        assert!(matches!(
+            result,
+            Err(IonError::PipelineExecutionError(PipelineError::CommandNotFound(ref command)))
+                if command == "ion-missing-other"
+        ));
+        assert_eq!(shell.execute_command_str("true").unwrap(), Status::SUCCESS);
+        assert_eq!(
+            *calls.borrow(),
+            [
+                args!["ion-missing-handled", "a", "b c"].to_vec(),
+                args!["ion-missing-other"].to_vec()
+            ]
+        );
+
+        // Piped and redirected commands go through the callback too, with their streams
+        shell.set_command_not_found(Some(Box::new(|_shell, args| {
+            let mut stdout = io::stdout();
+            writeln!(stdout, "handled {}", args[1..].join(" ")).unwrap();
+            stdout.flush().unwrap();
+            Some(Status::from_exit_code(42))
+        })));
+        let output = mktemp::Temp::new_file().unwrap();
+        let command = format!("ion-missing a > {}", output.as_path().display());
+        assert_eq!(shell.execute_command_str(&command).unwrap(), Status::from_exit_code(42));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "handled a\n");
+        let command = format!("ion-missing b c | cat > {}", output.as_path().display());
+        assert_eq!(shell.execute_command_str(&command).unwrap(), Status::SUCCESS);
+        assert_eq!(fs::read_to_string(&output).unwrap(), "handled b c\n");
+        let command = format!("ion-missing d | ion-missing e > {}", output.as_path().display());
+        assert_eq!(shell.execute_command_str(&command).unwrap(), Status::from_exit_code(42));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "handled e\n");
+    }
+
+    #[test]
+    fn alias_from_rust() {
+        let mut shell = Shell::new();
+        shell.define_alias("greet", "echo hello");
+        let (output, _) = shell.run_in_subshell("greet world").unwrap();
+        assert_eq!(output.as_str(), "hello world\n");
+
+        shell.define_alias("greet", "echo bye");
+        let (output, _) = shell.run_in_subshell("greet | cat").unwrap();
+        assert_eq!(output.as_str(), "bye\n");
+        assert!(matches!(shell.variables().get("greet"), Some(Value::Alias(_))));
+    }
+
+    #[test]
+    fn alias_introspection() {
+        let mut shell = Shell::new();
+        shell.define_alias("ll", "ls -l");
+        shell.define_alias("g", "git");
+        shell.variables_mut().set("x", "not an alias");
+        assert_eq!(shell.aliases(), [("g".into(), "git".into()), ("ll".into(), "ls -l".into())]);
+
+        assert!(shell.remove_alias("ll"));
+        assert!(!shell.remove_alias("ll"));
+        assert!(!shell.remove_alias("x"));
+        assert!(shell.variables().get("x").is_some());
+        assert_eq!(shell.aliases(), [("g".into(), "git".into())]);
+    }
+
+    #[test]
+    fn builtins_listing() {
+        let mut shell = Shell::new();
+        shell.builtins_mut().add("zzz", &|_, _| Status::SUCCESS, "a custom builtin");
+        let builtins: Vec<_> = shell.builtins().iter().collect();
+        assert!(builtins.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(builtins.len(), shell.builtins().keys().count());
+        assert_eq!(builtins.last(), Some(&("zzz", "a custom builtin")));
+        assert!(builtins.contains(&("echo", "Display a line of text")));
+    }
+
+    #[test]
+    fn status_history() {
+        let mut shell = Shell::new();
+        shell.execute_command(&b"false; true; test 1 -eq 2 || true"[..]).unwrap();
+        let statuses = [Status::SUCCESS, Status::FALSE, Status::SUCCESS, Status::FALSE];
+        assert_eq!(shell.status_history(), statuses);
+
+        let (output, _) = shell
+            .run_in_subshell(
+                "true; false; echo $?[1] $?[0]\nfn f\n  echo $?[0]x\n  false\nend\nf; echo \
+                 $?[0..2]",
+            )
+            .unwrap();
+        assert_eq!(output.as_str(), "0 1\nx\n1 0\n");
+
+        for _ in 0..=STATUS_HISTORY_SIZE {
+            shell.execute_command(&b"true"[..]).unwrap();
+        }
+        assert_eq!(shell.status_history(), [Status::SUCCESS; STATUS_HISTORY_SIZE]);
+    }
+
+    #[test]
+    fn interactive_defaults() {
+        let shell = Shell::new();
+        assert!(shell.builtins().contains("echo"));
+        assert!(!shell.builtins().contains("eval"));
+        assert!(shell.background_event.is_none());
+
+        let shell = Shell::with_interactive_defaults();
+        for builtin in &["echo", "eval", "set"] {
+            assert!(shell.builtins().contains(builtin), "{} is missing", builtin);
+        }
+        // The process-wide builtins are left to the host
+        assert!(!shell.builtins().contains("exit"));
+        assert!(shell.background_event.is_some());
+    }
+
+    #[test]
+    fn builtins_by_category() {
+        let mut shell = Shell::new();
+        shell
+            .builtins_mut()
+            .add_in_category("git", "gst", &|_, _| Status::SUCCESS, "git status")
+            .add_in_category("git", "gco", &|_, _| Status::SUCCESS, "git checkout")
+            .add_in_category("docker", "dps", &|_, _| Status::SUCCESS, "docker ps");
+        let builtins = shell.builtins();
+        assert_eq!(builtins.get_category("gst"), Some("git"));
+        assert_eq!(builtins.get_category("echo"), Some(BuiltinMap::UNCATEGORIZED));
+        assert_eq!(builtins.get_category("unknown"), None);
+
+        let categories: Vec<_> = builtins.by_category().collect();
+        assert_eq!(categories.len(), 3);
+        assert_eq!(categories[0], ("docker", vec![("dps", "docker ps")]));
+        assert_eq!(categories[1], ("git", vec![("gco", "git checkout"), ("gst", "git status")]));
+        assert_eq!(categories[2].0, BuiltinMap::UNCATEGORIZED);
+        assert!(categories[2].1.contains(&("echo", "Display a line of text")));
+
+        // Adding a builtin again without a category makes it uncategorized
+        shell.builtins_mut().add("gst", &|_, _| Status::SUCCESS, "git status");
+        assert_eq!(shell.builtins().get_category("gst"), Some(BuiltinMap::UNCATEGORIZED));
+    }
+
+    #[test]
+    fn wrap_and_restore_builtin() {
+        let calls = Cell::new(0);
+        let wrapped: Cell<Option<BuiltinFunction<'_>>> = Cell::new(None);
+        let wrapper = |args: &[types::Str], shell: &mut Shell<'_>| {
+            calls.set(calls.get() + 1);
+            wrapped.get().unwrap()(args, shell)
+        };
+
+        let mut shell = Shell::new();
+        let category = shell.builtins().get_category("true").unwrap().to_owned();
+        let original = shell.builtins_mut().replace("true", &wrapper, "Counting true");
+        assert!(original.is_some());
+        wrapped.set(original);
+        assert_eq!(shell.builtins().get_category("true"), Some(category.as_str()));
+
+        shell.execute_command(&b"true; true"[..]).unwrap();
+        assert_eq!(shell.previous_status(), Status::TRUE);
+        assert_eq!(calls.get(), 2);
+
+        let previous =
+            shell.builtins_mut().replace("true", original.unwrap(), "Do nothing, successfully");
+        assert!(previous.is_some());
+        shell.execute_command(&b"true"[..]).unwrap();
+        assert_eq!(shell.previous_status(), Status::TRUE);
+        assert_eq!(calls.get(), 2);
+        assert!(shell.builtins_mut().replace("new", &|_, _| Status::SUCCESS, "new").is_none());
+    }
+
+    #[test]
+    fn signaled_status() {
+        let mut shell = Shell::new();
+        assert!(shell.execute_command(&b"sh -c 'kill -9 $$'"[..]).is_err());
+        let killed = shell.previous_status();
+        assert!(killed.is_signaled());
+        assert_eq!(killed.signal(), Some(9));
+        assert_eq!(killed.exit_code(), None);
+        assert_eq!(killed.as_os_code(), 137);
+
+        let exited = shell.execute_command(&b"sh -c 'exit 137'"[..]).unwrap();
+        assert!(!exited.is_signaled());
+        assert_eq!(exited.signal(), None);
+        assert_eq!(exited.exit_code(), Some(137));
+        assert_ne!(exited, killed);
+    }
+
+    #[test]
+    fn error_exit_codes() {
+        let not_found = IonError::from(PipelineError::CommandNotFound("nope".into()));
+        assert_eq!(not_found.exit_code(), 127);
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let denied = IonError::from(PipelineError::CommandExecError(denied, args!["./file"]));
+        assert_eq!(Status::from(denied), Status::COULD_NOT_EXEC);
+        let interrupted = PipelineError::Interrupted(Pid::this(), signal::Signal::SIGINT);
+        assert_eq!(IonError::from(interrupted).exit_code(), 130);
+        let core_dump = PipelineError::CoreDumpBySignal(Pid::this(), signal::Signal::SIGSEGV);
+        assert_eq!(Status::from(IonError::from(core_dump)), Status::signaled(11));
+        let early_exit = PipelineError::EarlyExit(Status::from_exit_code(3));
+        assert_eq!(IonError::from(early_exit).exit_code(), 3);
+        assert_eq!(IonError::RecursionLimit(1).exit_code(), 1);
+
+        // Errors of command substitutions are unwrapped
+        let mut shell = Shell::new();
+        let err = shell.execute_command(&b"echo $(nonexistent-command-for-ion)"[..]).unwrap_err();
+        assert_eq!(err.exit_code(), 127);
+        let err = shell.execute_command(&b"if true"[..]).unwrap_err();
+        assert_eq!(Status::from(err), Status::FALSE);
+    }
+
+    #[test]
+    #[serial]
+    fn flush_buffered_output() {
+        let mut shell = Shell::new();
+        shell.builtins_mut().add(
+            "partial",
+            &|_, _| io::stdout().write_all(b"partial line").into(),
+            "write without a newline",
+        );
+
+        let (reader, writer) = unistd::pipe().unwrap();
+        let saved = unistd::dup(1).unwrap();
+        io::stdout().flush().unwrap();
+        unistd::dup2(writer, 1).unwrap();
+        shell.execute_command_str("partial").unwrap();
+        shell.flush().unwrap();
+        unistd::dup2(saved, 1).unwrap();
+        unistd::close(saved).unwrap();
+        unistd::close(writer).unwrap();
+
+        let mut output = String::new();
+        unsafe { File::from_raw_fd(reader) }.read_to_string(&mut output).unwrap();
+        assert!(output.contains("partial line"));
+    }
+
+    #[test]
+    fn execute_str() {
+        let mut shell = Shell::new();
+        let status = shell.execute_command_str("let x = 1; let y = 2\ntest $x$y = 12").unwrap();
+        assert_eq!(status, Status::SUCCESS);
+        assert_eq!(shell.execute_command_str("true; false").unwrap(), Status::FALSE);
+        assert!(shell.execute_command_str("if true").is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn cwd_change_callback() {
+        let cwd = env::current_dir().unwrap();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let mut shell = Shell::new();
+        let recorded = changes.clone();
+        shell.set_on_cwd_change(Some(Box::new(move |dir| {
+            recorded.borrow_mut().push(dir.to_path_buf())
+        })));
+
+        let temp = env::temp_dir().canonicalize().unwrap();
+        shell.execute_command(format!("cd {}", temp.display()).as_bytes()).unwrap();
+        // A failed change is not reported
+        shell.execute_command(&b"cd /does/not/exist"[..]).unwrap();
+        shell.execute_command(&b"cd -"[..]).unwrap();
+        assert_eq!(*changes.borrow(), [temp, cwd.clone()]);
+        env::set_current_dir(cwd).unwrap();
+    }
+
+    #[test]
+    fn positional_args() {
+        let mut builtins = BuiltinMap::default();
+        builtins.with_unsafe();
+        let mut shell = Shell::with_builtins(builtins);
+        assert!(shell.args().is_empty());
+
+        shell.set_args(vec!["script".into(), "one two".into(), "three".into()]);
+        assert_eq!(shell.args(), ["script", "one two", "three"]);
+        assert_eq!(shell.expand_string("$len(@args)").unwrap(), args!["3"]);
+        assert_eq!(shell.expand_string("@args[1]").unwrap(), args!["one two"]);
+
+        // `set --` goes through the same parameters
+        shell.execute_command(&b"set -- four"[..]).unwrap();
+        assert_eq!(shell.args(), ["script", "four"]);
+    }
+
+    #[test]
+    fn custom_recursion_limit() {
+        let mut shell = Shell::new();
+        shell.set_max_recursion_depth(Some(10));
+        shell.execute_command(&b"fn rec\n  rec\nend"[..]).unwrap();
+        let result = shell.execute_command(&b"rec"[..]);
+        assert!(matches!(result, Err(IonError::RecursionLimit(10))));
+
+        assert_eq!(shell.recursion_depth, 0);
+
+        shell.opts_mut().max_recursion = Some(20);
+        let result = shell.execute_command(&b"rec"[..]);
+        assert!(matches!(result, Err(IonError::RecursionLimit(20))));
+        assert_eq!(shell.recursion_depth, 0);
+
+        // The depth is restored after each call, so sequential calls are not limited
+        shell.opts_mut().max_recursion = Some(3);
+        shell
+            .execute_command(
+                &b"fn leaf
+  true
+end
+fn twice
+  leaf
+  leaf
+end"[..],
+            )
+            .unwrap();
+        for _ in 0..5 {
+            shell.execute_command(&b"twice"[..]).unwrap();
+        }
+        assert_eq!(shell.recursion_depth, 0);
+    }
+
+    #[test]
+    fn assign_array_indices() {
+        let mut shell = Shell::new();
+        let mut array = |code: &str| {
+            shell.execute_command(code.as_bytes()).unwrap();
+            (shell.expand_string("@array").unwrap(), shell.previous_status())
+        };
+        assert_eq!(
+            array("let array = [a b c]; let array[-1] = d"),
+            (args!["a", "b", "d"], Status::SUCCESS)
+        );
+        assert_eq!(array("let array[-3] = e"), (args!["e", "b", "d"], Status::SUCCESS));
+        assert_eq!(array("let array[4] = f"), (args!["e", "b", "d", "", "f"], Status::SUCCESS));
+        let (values, status) = array("let array[-6] = g");
+        assert_eq!(values, args!["e", "b", "d", "", "f"]);
+        assert!(!status.is_success());
+        let (values, status) = array("let array[4000000000] = g");
+        assert_eq!(values, args!["e", "b", "d", "", "f"]);
+        assert!(!status.is_success());
+
+        shell.opts_mut().strict_indices = true;
+        let mut array = |code: &str| {
+            shell.execute_command(code.as_bytes()).unwrap();
+            (shell.expand_string("@array").unwrap(), shell.previous_status())
+        };
+        assert_eq!(array("let array[-5] = h"), (args!["h", "b", "d", "", "f"], Status::SUCCESS));
+        let (values, status) = array("let array[5] = i");
+        assert_eq!(values, args!["h", "b", "d", "", "f"]);
+        assert!(!status.is_success());
+    }
+
+    #[test]
+    fn color_modes() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.expand("${c::red}red").unwrap(), "\x1b[31mred");
+
+        shell.opts_mut().color = ColorMode::Never;
+        assert_eq!(shell.expand("${c::red}red${c::reset}").unwrap(), "red");
+        assert!(shell.expand("${c::nocolor}").is_err());
+
+        assert_eq!("auto".parse(), Ok(ColorMode::Auto));
+        assert_eq!(ColorMode::Never.to_string(), "never");
+        assert!("sometimes".parse::<ColorMode>().is_err());
+    }
+
+    #[test]
+    fn not_found_substitutions() {
+        let mut shell = Shell::new();
+        assert!(shell.expand_string("a$(nonexistent-command)b").is_err());
+        assert_eq!(shell.previous_status(), Status::NO_SUCH_COMMAND);
+
+        shell.opts_mut().lenient_substitutions = true;
+        assert_eq!(shell.expand_string("$(echo a)").unwrap(), args!["a"]);
+        assert_eq!(shell.previous_status(), Status::SUCCESS);
+        assert_eq!(shell.expand_string("a$(nonexistent-command)b").unwrap(), args!["ab"]);
+        assert_eq!(shell.previous_status(), Status::NO_SUCH_COMMAND);
+
+        shell.opts_mut().err_exit = true;
+        assert!(shell.expand_string("a$(nonexistent-command)b").is_err());
+        assert_eq!(shell.previous_status(), Status::NO_SUCH_COMMAND);
+    }
+}
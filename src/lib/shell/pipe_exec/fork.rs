@@ -1,4 +1,8 @@
-use super::job_control::{BackgroundProcess, ProcessState};
+use super::{
+    create_pipe,
+    job_control::{BackgroundProcess, ProcessState},
+    PipelineError,
+};
 use crate::{
     builtins::Status,
     expansion::pipelines::Pipeline,
@@ -8,19 +12,47 @@ use nix::{
     sys::signal::{self, SigHandler, Signal},
     unistd::{self, ForkResult, Pid},
 };
-use std::{io, os::unix::io::AsRawFd};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    os::unix::io::AsRawFd,
+    thread::spawn,
+};
 
 impl<'a> Shell<'a> {
     /// Ensures that the forked child is given a unique process ID.
     fn create_process_group() { unistd::setpgid(Pid::this(), Pid::this()).unwrap(); }
 
+    /// Sends the stdout and stderr of every job in the pipeline to the given pipe.
+    fn redirect_output(
+        pipeline: &mut Pipeline<RefinedJob<'a>>,
+        writer: &File,
+    ) -> Result<(), PipelineError> {
+        for item in &mut pipeline.items {
+            item.job.stdout = Some(writer.try_clone().map_err(PipelineError::ClonePipeFailed)?);
+            item.job.stderr = Some(writer.try_clone().map_err(PipelineError::ClonePipeFailed)?);
+        }
+        Ok(())
+    }
+
     /// Forks the shell, adding the child to the parent's background list, and executing
     /// the given commands in the child fork.
     pub(super) fn fork_pipe(
         &mut self,
-        pipeline: Pipeline<RefinedJob<'a>>,
+        mut pipeline: Pipeline<RefinedJob<'a>>,
         state: ProcessState,
     ) -> Status {
+        // If requested, capture the output of the job to stream it to the callback
+        let output = match self.background_output {
+            Some(_) if state == ProcessState::Running => match create_pipe() {
+                Ok(pipe) => Some(pipe),
+                Err(why) => {
+                    return Status::error(format!("ion: could not capture job output: {}", why))
+                }
+            },
+            _ => None,
+        };
+
         match unsafe { unistd::fork() } {
             Ok(ForkResult::Child) => {
                 self.opts_mut().grab_tty = false;
@@ -34,12 +66,27 @@ impl<'a> Shell<'a> {
                 // This ensures that the child fork has a unique PGID.
                 Self::create_process_group();
 
+                // Release the pipe of a capture, which would otherwise wait for the job
+                if let Some((pipe, original)) = self.capture.take() {
+                    if matches!(&self.stdout, Some(stdout) if stdout.as_raw_fd() == pipe) {
+                        self.stdout(original);
+                    }
+                }
+
+                if let Some((reader, writer)) = output {
+                    drop(reader);
+                    if let Err(why) = Self::redirect_output(&mut pipeline, &writer) {
+                        eprintln!("ion: could not capture job output: {}", why);
+                        unsafe { nix::libc::_exit(Status::COULD_NOT_EXEC.as_os_code()) };
+                    }
+                }
+
                 // After execution of it's commands, exit with the last command's status.
                 let code = self
                     .pipe(pipeline)
                     .unwrap_or_else(|err| {
                         eprintln!("{}", err);
-                        Status::COULD_NOT_EXEC
+                        Status::from(err)
                     })
                     .as_os_code();
                 unsafe { nix::libc::_exit(code) };
@@ -53,9 +100,49 @@ impl<'a> Shell<'a> {
                         pipeline.to_string(),
                     ));
                 }
+
+                // Stream the output of the job line by line until every writer is closed
+                if let (Some((reader, writer)), Some(callback)) =
+                    (output, self.background_output.clone())
+                {
+                    drop(writer);
+                    let njob = self.previous_job;
+                    let _ = spawn(move || {
+                        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+                            callback(njob, child, &line);
+                        }
+                    });
+                }
                 Status::SUCCESS
             }
             Err(why) => Status::error(format!("ion: background fork failed: {}", why)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        time::Duration,
+    };
+
+    #[test]
+    fn streams_background_output() {
+        let (sender, receiver) = mpsc::channel();
+        let sender = Mutex::new(sender);
+        let mut shell = Shell::new();
+        shell.set_background_output(Some(Arc::new(move |_, _, line: &str| {
+            let _ = sender.lock().unwrap().send(line.to_string());
+        })));
+
+        shell.execute_command(&b"echo -e 'one\\ntwo\\nthree' &"[..]).unwrap();
+        shell.wait_for_background().unwrap();
+
+        let lines = (0..3)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+    }
+}
@@ -17,7 +17,7 @@ use std::{
     fmt,
     sync::Mutex,
     thread::{sleep, spawn},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[derive(Clone, Copy, Hash, Debug, PartialEq)]
@@ -280,11 +280,29 @@ impl<'a> Shell<'a> {
             PipeErrorPropagation::LastCommand(Status::SUCCESS)
         };
 
+        // Past the deadline of a command substitution, the group is killed
+        let mut timed_out = false;
+
         loop {
-            match wait::waitpid(Pid::from_raw(-group.as_raw()), Some(WaitPidFlag::WUNTRACED)) {
+            let flags = if self.deadline.is_some() && !timed_out {
+                WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG
+            } else {
+                WaitPidFlag::WUNTRACED
+            };
+            match wait::waitpid(Pid::from_raw(-group.as_raw()), Some(flags)) {
+                Ok(WaitStatus::StillAlive) => {
+                    if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+                        let _ = signal::killpg(group, Signal::SIGKILL);
+                        timed_out = true;
+                    } else {
+                        sleep(Duration::from_millis(10));
+                    }
+                }
                 Err(err) => match err {
                     nix::errno::Errno::ECHILD => {
-                        if let Some(signal) = signaled {
+                        if timed_out {
+                            break Err(PipelineError::TimedOut);
+                        } else if let Some(signal) = signaled {
                             break Err(signal);
                         } else {
                             break Ok(exit_status.into());
@@ -308,10 +326,11 @@ impl<'a> Shell<'a> {
                         PipeErrorPropagation::FirstErrorFound(_) => {}
                     }
                 }
+                Ok(WaitStatus::Signaled(..)) if timed_out => (),
                 Ok(WaitStatus::Signaled(pid, signal, core_dumped)) => {
                     if signal == signal::Signal::SIGPIPE {
                     } else if core_dumped {
-                        signaled = Some(PipelineError::CoreDump(pid));
+                        signaled = Some(PipelineError::CoreDumpBySignal(pid, signal));
                     } else {
                         if signal == Signal::SIGINT {
                             let _ = signal::kill(pid, signal);
@@ -20,7 +20,10 @@ use super::{
 };
 use crate::{
     builtins::Status,
-    expansion::pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
+    expansion::{
+        pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
+        Expander,
+    },
     types,
 };
 use nix::{
@@ -31,8 +34,12 @@ use smallvec::SmallVec;
 use std::{
     fs::{File, OpenOptions},
     io::{self, Write},
-    os::unix::process::CommandExt,
+    os::unix::{
+        io::{AsRawFd, RawFd},
+        process::CommandExt,
+    },
     process::{exit, Command, Stdio},
+    time::Instant,
 };
 use thiserror::Error;
 
@@ -93,9 +100,13 @@ pub enum PipelineError {
     /// A signal interrupted a child process
     #[error("process ({0}) ended by signal {1}")]
     Interrupted(Pid, Signal),
-    /// A subprocess had a core dump
+    /// A subprocess had a core dump. The shell reports
+    /// [`PipelineError::CoreDumpBySignal`] instead, which keeps the signal
     #[error("process ({0}) had a core dump")]
     CoreDump(Pid),
+    /// A subprocess had a core dump after the signal which ended it
+    #[error("process ({0}) had a core dump after signal {1}")]
+    CoreDumpBySignal(Pid, Signal),
     /// WaitPID errored
     #[error("waitpid error: {0}")]
     WaitPid(nix::Error),
@@ -116,6 +127,14 @@ pub enum PipelineError {
     /// pipeline after it's creation
     #[error("could not kill the processes: {0}")]
     KillFailed(#[source] nix::Error),
+
+    /// A command substitution ran for longer than the timeout set in the options
+    #[error("command substitution timed out")]
+    TimedOut,
+
+    /// Process substitutions need `/dev/fd` paths, which the platform does not provide
+    #[error("process substitutions are not supported on this platform")]
+    UnsupportedSubstitution,
 }
 
 impl From<RedirectError> for PipelineError {
@@ -181,6 +200,25 @@ fn need_tee(outs: &[Redirection], redirection: RedirectFrom) -> (bool, bool) {
     (stdout_count > 1, stderr_count > 1)
 }
 
+/// Opens the file of an output redirection, returning a second handle on it for the standard
+/// error when both outputs are redirected.
+fn open_output(output: &Redirection) -> Result<(File, Option<File>), RedirectError> {
+    let error =
+        |why| RedirectError::Output { redirect: output.from, file: output.file.to_string(), why };
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(output.append)
+        .truncate(!output.append)
+        .open(output.file.as_str())
+        .map_err(error)?;
+    let copy = match output.from {
+        RedirectFrom::Both => Some(file.try_clone().map_err(error)?),
+        _ => None,
+    };
+    Ok((file, copy))
+}
+
 fn do_tee<'a>(
     outputs: &[Redirection],
     job: &mut RefinedJob<'a>,
@@ -189,37 +227,13 @@ fn do_tee<'a>(
 ) -> Result<(), RedirectError> {
     // XXX: Possibly add an assertion here for correctness
     for output in outputs {
-        match OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(output.append)
-            .truncate(!output.append)
-            .open(output.file.as_str())
-        {
-            Ok(file) => match output.from {
-                RedirectFrom::None => (),
-                RedirectFrom::Stdout => stdout(job, file),
-                RedirectFrom::Stderr => stderr(job, file),
-                RedirectFrom::Both => match file.try_clone() {
-                    Ok(f_copy) => {
-                        stdout(job, file);
-                        stderr(job, f_copy);
-                    }
-                    Err(why) => {
-                        return Err(RedirectError::Output {
-                            redirect: output.from,
-                            file: output.file.to_string(),
-                            why,
-                        });
-                    }
-                },
-            },
-            Err(why) => {
-                return Err(RedirectError::Output {
-                    redirect: output.from,
-                    file: output.file.to_string(),
-                    why,
-                });
+        match (output.from, open_output(output)?) {
+            (RedirectFrom::None, _) => (),
+            (RedirectFrom::Stdout, (file, _)) => stdout(job, file),
+            (RedirectFrom::Stderr, (file, _)) => stderr(job, file),
+            (RedirectFrom::Both, (file, copy)) => {
+                stdout(job, file);
+                stderr(job, copy.expect("both outputs are redirected"));
             }
         }
     }
@@ -293,7 +307,70 @@ fn prepare<'a>(
     Ok(new_commands)
 }
 
+/// The files given as the standard input, output and error, if they are redirected
+type Streams = (Option<File>, Option<File>, Option<File>);
+
 impl<'b> Shell<'b> {
+    /// Run `f` with the standard streams of the shell redirected, as for the group of commands
+    /// `{ echo a; echo b } > file`. When a stream is redirected more than once, the last
+    /// redirection is used.
+    pub(crate) fn with_redirections<T>(
+        &mut self,
+        outputs: &[Redirection],
+        inputs: &[Input],
+        f: impl FnOnce(&mut Self) -> Result<T, IonError>,
+    ) -> Result<T, IonError> {
+        let substitutions = self.substitutions.len();
+        let result = self.open_redirections(outputs, inputs).and_then(|(stdin, stdout, stderr)| {
+            let previous_stdin = stdin.map(|file| self.stdin(file));
+            let previous_stdout = stdout.map(|file| self.stdout(file));
+            let previous_stderr = stderr.map(|file| self.stderr(file));
+            let result = f(self);
+            if let Some(file) = previous_stdin {
+                self.stdin(file);
+            }
+            if let Some(file) = previous_stdout {
+                self.stdout(file);
+            }
+            if let Some(file) = previous_stderr {
+                self.stderr(file);
+            }
+            result
+        });
+        self.finish_substitutions(substitutions);
+        result
+    }
+
+    /// Open the files of the redirections, as the standard input, output and error.
+    fn open_redirections(
+        &mut self,
+        outputs: &[Redirection],
+        inputs: &[Input],
+    ) -> Result<Streams, IonError> {
+        let (mut stdin, mut stdout, mut stderr) = (None, None, None);
+        for input in inputs {
+            let input = match input {
+                Input::File(file) => Input::File(self.get_string(file)?),
+                Input::HereString(string) => Input::HereString(self.get_string(string)?),
+            };
+            stdin = Some(input.get_infile()?);
+        }
+        for output in outputs {
+            let output = Redirection { file: self.get_string(&output.file)?, ..output.clone() };
+            let (file, copy) = open_output(&output).map_err(PipelineError::from)?;
+            match output.from {
+                RedirectFrom::None => (),
+                RedirectFrom::Stdout => stdout = Some(file),
+                RedirectFrom::Stderr => stderr = Some(file),
+                RedirectFrom::Both => {
+                    stdout = Some(file);
+                    stderr = copy;
+                }
+            }
+        }
+        Ok((stdin, stdout, stderr))
+    }
+
     /// For tee jobs
     fn exec_multi_out(
         items: &mut (Option<TeeItem>, Option<TeeItem>),
@@ -336,6 +413,14 @@ impl<'b> Shell<'b> {
         Status::SUCCESS
     }
 
+    /// Drop the files replacing the standard streams in a fork, whose standard streams are
+    /// already those of the job it runs, so that the commands it runs write to its pipes.
+    fn forget_streams(&mut self) {
+        self.stdin(None);
+        self.stdout(None);
+        self.stderr(None);
+    }
+
     fn exec_function<S: AsRef<str>>(&mut self, name: &str, args: &[S]) -> Result<Status, IonError> {
         if let Some(Value::Function(function)) = self.variables.get(name).cloned() {
             function.execute(self, args).map(|_| self.previous_status)
@@ -344,6 +429,13 @@ impl<'b> Shell<'b> {
         }
     }
 
+    /// Give a command which could not be found to the callback set for it, and fail with the
+    /// "command not found" error if it does not handle the command.
+    fn exec_not_found(&mut self, args: &[types::Str]) -> Result<Status, IonError> {
+        self.handle_command_not_found(args)
+            .ok_or_else(|| PipelineError::CommandNotFound(args[0].clone()).into())
+    }
+
     /// Executes a `RefinedJob` that was created in the `generate_commands` method.
     ///
     /// The aforementioned `RefinedJob` may be either a builtin or external command.
@@ -356,12 +448,22 @@ impl<'b> Shell<'b> {
         let code = match job.var {
             Variant::Builtin { main } => Ok(main(job.args(), self)),
             Variant::Function => self.exec_function(job.command(), job.args()),
+            Variant::NotFound => self.exec_not_found(job.args()),
             _ => panic!("exec job should not be able to be called on Cat or Tee jobs"),
         };
         streams::redirect(&stdin_bk, &Some(stdout_bk), &Some(stderr_bk))?;
         code
     }
 
+    /// Fail once the command substitution being run is past its deadline.
+    pub(crate) fn check_deadline(&self) -> Result<(), PipelineError> {
+        if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+            Err(PipelineError::TimedOut)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Given a pipeline, generates commands and executes them.
     ///
     /// The `Pipeline` structure contains a vector of `Job`s, and redirections to perform on the
@@ -382,6 +484,8 @@ impl<'b> Shell<'b> {
         &mut self,
         pipeline: Pipeline<RefinedJob<'b>>,
     ) -> Result<Status, IonError> {
+        self.check_deadline()?;
+
         // While active, the SIGTTOU signal will be ignored.
         let _sig_ignore = SignalHandler::new();
 
@@ -465,7 +569,15 @@ impl<'b> Shell<'b> {
                         }
                     }
 
-                    spawn_proc(self, parent, &mut last_pid, &mut current_pid, &mut pgid)?;
+                    let next_stdin = child.stdin.as_ref().map(AsRawFd::as_raw_fd);
+                    spawn_proc(
+                        self,
+                        parent,
+                        next_stdin,
+                        &mut last_pid,
+                        &mut current_pid,
+                        &mut pgid,
+                    )?;
 
                     last_pid = Some(current_pid);
                     parent = child;
@@ -474,7 +586,7 @@ impl<'b> Shell<'b> {
                     }
                 }
 
-                spawn_proc(self, parent, &mut last_pid, &mut current_pid, &mut pgid)?;
+                spawn_proc(self, parent, None, &mut last_pid, &mut current_pid, &mut pgid)?;
                 if self.opts.grab_tty {
                     unistd::tcsetpgrp(nix::libc::STDIN_FILENO, pgid.unwrap())
                         .map_err(PipelineError::TerminalGrabFailed)?;
@@ -485,7 +597,7 @@ impl<'b> Shell<'b> {
                 // returning the exit status of the last process in the queue.
                 // Watch the foreground group, dropping all commands that exit as they exit.
                 let status = self.watch_foreground(pgid.unwrap())?;
-                if status == Status::TERMINATED {
+                if status.as_os_code() == Status::TERMINATED.as_os_code() {
                     signal::killpg(pgid.unwrap(), signal::Signal::SIGTERM)
                         .map_err(PipelineError::TerminateJobsError)?;
                 } else {
@@ -503,6 +615,7 @@ impl<'b> Shell<'b> {
 fn spawn_proc(
     shell: &mut Shell<'_>,
     cmd: RefinedJob<'_>,
+    next_stdin: Option<RawFd>,
     last_pid: &mut Option<Pid>,
     current_pid: &mut Pid,
     group: &mut Option<Pid>,
@@ -536,20 +649,35 @@ fn spawn_proc(
             }
         }
         Variant::Builtin { main } => {
-            fork_exec_internal(stdout, stderr, stdin, *group, |_, _, _| main(&args, shell))
+            fork_exec_internal(stdout, stderr, stdin, next_stdin, *group, |_, _, _| {
+                shell.forget_streams();
+                main(&args, shell)
+            })
+        }
+        Variant::Function => {
+            fork_exec_internal(stdout, stderr, stdin, next_stdin, *group, |_, _, _| {
+                shell.forget_streams();
+                shell
+                    .exec_function(&args[0], &args)
+                    .unwrap_or_else(|why| Status::error(format!("{}", why)))
+            })
+        }
+        Variant::NotFound => {
+            fork_exec_internal(stdout, stderr, stdin, next_stdin, *group, |_, _, _| {
+                shell.forget_streams();
+                shell.exec_not_found(&args).unwrap_or_else(|why| {
+                    eprintln!("ion: {}", why);
+                    Status::from(&why)
+                })
+            })
         }
-        Variant::Function => fork_exec_internal(stdout, stderr, stdin, *group, |_, _, _| {
-            shell
-                .exec_function(&args[0], &args)
-                .unwrap_or_else(|why| Status::error(format!("{}", why)))
-        }),
         Variant::Cat { ref mut sources } => {
-            fork_exec_internal(stdout, None, stdin, *group, |_, _, mut stdin| {
+            fork_exec_internal(stdout, None, stdin, next_stdin, *group, |_, _, mut stdin| {
                 Shell::exec_multi_in(sources, &mut stdin)
             })
         }
         Variant::Tee { ref mut items } => {
-            fork_exec_internal(stdout, stderr, stdin, *group, |_, _, _| {
+            fork_exec_internal(stdout, stderr, stdin, next_stdin, *group, |_, _, _| {
                 Shell::exec_multi_out(items, redirection)
             })
         }
@@ -567,6 +695,7 @@ fn fork_exec_internal<F>(
     stdout: Option<File>,
     stderr: Option<File>,
     stdin: Option<File>,
+    next_stdin: Option<RawFd>,
     pgid: Option<Pid>,
     mut exec_action: F,
 ) -> Result<Pid, PipelineError>
@@ -579,8 +708,15 @@ where
                 signal::signal(signal::Signal::SIGINT, signal::SigHandler::SigIgn).unwrap();
                 signal::signal(signal::Signal::SIGHUP, signal::SigHandler::SigIgn).unwrap();
                 signal::signal(signal::Signal::SIGTERM, signal::SigHandler::SigIgn).unwrap();
+                // Stop quietly when the consumer exits early, as external commands do
+                signal::signal(signal::Signal::SIGPIPE, signal::SigHandler::SigDfl).unwrap();
             }
             signals::unblock();
+            // The fork inherited the read end of the pipe to the next command, which would
+            // otherwise keep the pipe open after that command exits.
+            if let Some(fd) = next_stdin {
+                let _ = unistd::close(fd);
+            }
 
             unistd::setpgid(Pid::this(), pgid.unwrap_or_else(Pid::this)).unwrap();
             streams::redirect(&stdin, &stdout, &stderr).unwrap();
@@ -590,3 +726,26 @@ where
         ForkResult::Parent { child } => Ok(child),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread, time::Duration};
+
+    #[test]
+    fn producers_stop_when_the_consumer_exits() {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut shell = Shell::new();
+            let script =
+                "fn produce\n  while true\n    echo y\n  end\nend\nproduce | head -n 3 > /dev/null";
+            let status = shell.execute_command(script.as_bytes()).unwrap();
+            let words = shell.expand_string("@(produce | head -n 2)").unwrap();
+            let _ = sender.send((status, words.join(" ")));
+        });
+        let (status, words) =
+            receiver.recv_timeout(Duration::from_secs(10)).expect("producer kept running");
+        assert!(status.is_success());
+        assert_eq!(words, "y y");
+    }
+}
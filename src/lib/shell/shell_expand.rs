@@ -1,42 +1,113 @@
 use super::{
-    pipe_exec::create_pipe, sys::NULL_PATH, variables::Value, IonError, PipelineError, Shell,
+    colors::Colors, pipe_exec::create_pipe, sys::NULL_PATH, variables::Value, IonError, IonResult,
+    PipelineError, Shell,
 };
 use crate::{
-    expansion::{Error, Expander, Result, Select},
+    builtins::Status,
+    expansion::{Error, Expander, GlobOptions, Result, Select, SelectWithSize},
     types,
 };
 use nix::unistd::{tcsetpgrp, Pid};
 #[cfg(target_os = "redox")]
 use redox_users::All;
-use std::{env, fs::File, io::Read};
+use scopes::Scoped;
+use std::{
+    env,
+    fs::File,
+    io::{self, Read},
+    ops::Bound,
+    time::Instant,
+};
 #[cfg(not(target_os = "redox"))]
 use users::os::unix::UserExt;
 
-impl<'a, 'b> Expander for Shell<'b> {
-    type Error = IonError;
+/// Query the user database for the home directory of `user`, or of the current user if empty.
+#[cfg(not(target_os = "redox"))]
+fn lookup_home(user: &str) -> Option<types::Str> {
+    let user = if user.is_empty() {
+        users::get_user_by_uid(users::get_current_uid())
+    } else {
+        users::get_user_by_name(user)
+    };
+    user.map(|user| user.home_dir().to_string_lossy().as_ref().into())
+}
 
-    /// Uses a subshell to expand a given command.
-    fn command(
+/// Query the user database for the home directory of `user`, or of the current user if empty.
+#[cfg(target_os = "redox")]
+fn lookup_home(user: &str) -> Option<types::Str> {
+    let users = redox_users::AllUsers::basic(redox_users::Config::default()).ok()?;
+    let user = if user.is_empty() {
+        redox_users::get_uid().ok().and_then(|id| users.get_by_id(id))
+    } else {
+        users.get_by_name(user)
+    };
+    user.map(|user| user.home.as_str().into())
+}
+
+/// Whether the bounds select no key at all, which `BTreeMap::range` would panic on.
+fn is_empty_key_range(start: &Bound<types::Str>, end: &Bound<types::Str>) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end))
+        | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        _ => false,
+    }
+}
+
+impl<'b> Shell<'b> {
+    /// Expand `input` as a command argument would be, and join the words it expands to with
+    /// spaces. This is handy to evaluate templates, such as prompts, on demand.
+    ///
+    /// The command substitutions of `input` are expanded as usual, running their commands.
+    pub fn expand(&mut self, input: &str) -> IonResult<types::Str> { Ok(self.get_string(input)?) }
+
+    /// Run `command` in a subshell, returning everything it wrote to the standard output, and to
+    /// the standard error if `stderr` is set.
+    fn capture_output(
         &mut self,
         command: &str,
         set_cmd_duration: bool,
-    ) -> Result<types::Str, Self::Error> {
+        stderr: bool,
+    ) -> Result<Vec<u8>, IonError> {
+        if !stderr {
+            if let Some(id) =
+                self.prefetched.iter().position(|(prefetched, _)| prefetched == command)
+            {
+                return Ok(self.prefetched.remove(id).1);
+            }
+        }
+
         let (mut reader, writer) = create_pipe()
             .map_err(|err| Error::Subprocess(Box::new(IonError::PipelineExecutionError(err))))?;
-        let null_file = File::open(NULL_PATH).map_err(|err| {
-            Error::Subprocess(Box::new(IonError::PipelineExecutionError(
-                PipelineError::CaptureFailed(err),
-            )))
-        })?;
+        let error_file =
+            if stderr { writer.try_clone() } else { File::open(NULL_PATH) }.map_err(|err| {
+                Error::Subprocess(Box::new(IonError::PipelineExecutionError(
+                    PipelineError::CaptureFailed(err),
+                )))
+            })?;
 
         // Store the previous default redirections
         let prev_stdout = self.stdout(writer);
-        let prev_stderr = self.stderr(null_file);
+        let prev_stderr = self.stderr(error_file);
+
+        let prev_deadline = self.deadline;
+        self.deadline = self.substitution_deadline();
 
         // Execute the command
         let result = self
-            .on_command(command.bytes(), set_cmd_duration)
-            .map_err(|err| Error::Subprocess(Box::new(err)));
+            .recurse(|shell| shell.on_command(command.bytes(), set_cmd_duration))
+            .map_err(|err| match err {
+                // Avoid wrapping the error again for each nested substitution
+                IonError::ExpansionError(Error::Subprocess(err))
+                    if matches!(*err, IonError::RecursionLimit(_)) =>
+                {
+                    Error::Subprocess(err)
+                }
+                err => Error::Subprocess(Box::new(err)),
+            });
+
+        self.deadline = prev_deadline;
 
         // Reset the pipes, droping the stdout
         self.stdout(prev_stdout);
@@ -45,21 +116,116 @@ impl<'a, 'b> Expander for Shell<'b> {
         // Ensure that the parent retains ownership of the terminal before exiting.
         let _ = tcsetpgrp(nix::libc::STDIN_FILENO, Pid::this());
 
+        if let Err(Error::Subprocess(ref err)) = result {
+            if let IonError::PipelineExecutionError(PipelineError::CommandNotFound(_)) = **err {
+                self.previous_status = Status::NO_SUCH_COMMAND;
+                if self.opts.lenient_substitutions && !self.opts.err_exit {
+                    eprintln!("ion: {}", err);
+                    return Ok(Vec::new());
+                }
+            }
+        }
         result?;
 
-        let mut string = String::with_capacity(1024);
-        match reader.read_to_string(&mut string) {
-            Ok(_) => Ok(string.into()),
+        let mut output = Vec::with_capacity(1024);
+        match reader.read_to_end(&mut output) {
+            Ok(_) => Ok(output),
             Err(why) => Err(Error::Subprocess(Box::new(PipelineError::CaptureFailed(why).into()))),
         }
     }
 
+    /// When a command substitution starting now must be done by. Nested substitutions cannot
+    /// outlive the substitution they are part of.
+    pub(crate) fn substitution_deadline(&self) -> Option<Instant> {
+        self.opts.command_timeout.map_or(self.deadline, |timeout| {
+            let deadline = Instant::now() + timeout;
+            Some(self.deadline.map_or(deadline, |prev| prev.min(deadline)))
+        })
+    }
+
+    /// Convert the output of a substitution, which must be valid UTF-8.
+    fn output_to_string(output: Vec<u8>) -> Result<types::Str, IonError> {
+        String::from_utf8(output).map(Into::into).map_err(|why| {
+            let why = io::Error::new(io::ErrorKind::InvalidData, why);
+            Error::Subprocess(Box::new(PipelineError::CaptureFailed(why).into()))
+        })
+    }
+}
+
+impl<'a, 'b> Expander for Shell<'b> {
+    type Error = IonError;
+
+    /// Uses a subshell to expand a given command.
+    fn command(
+        &mut self,
+        command: &str,
+        set_cmd_duration: bool,
+    ) -> Result<types::Str, Self::Error> {
+        let output = self.capture_output(command, set_cmd_duration, false)?;
+        Self::output_to_string(output)
+    }
+
+    fn command_with_stderr(&mut self, command: &str) -> Result<types::Str, Self::Error> {
+        let output = self.capture_output(command, true, true)?;
+        Self::output_to_string(output)
+    }
+
+    fn command_bytes(&mut self, command: &str) -> Result<Vec<u8>, Self::Error> {
+        self.capture_output(command, true, false)
+    }
+
+    fn cached_command(&mut self, command: &str) -> Result<types::Str, Self::Error> {
+        if let Some(output) = self.cached.get(command) {
+            return Ok(output.clone());
+        }
+        let output = self.command(command, true)?;
+        self.cached.insert(command.into(), output.clone());
+        Ok(output)
+    }
+
+    fn command_succeeds(&mut self, command: &str) -> Result<bool, Self::Error> {
+        self.command(command, false)?;
+        Ok(self.previous_status.is_success())
+    }
+
+    fn substitute_process(
+        &mut self,
+        command: &str,
+        input: bool,
+    ) -> Result<types::Str, Self::Error> {
+        self.start_substitution(command, input)
+            .map_err(|err| Error::Subprocess(Box::new(IonError::PipelineExecutionError(err))))
+    }
+
+    fn with_vars<T, F>(&mut self, vars: &[(&str, &str)], f: F) -> Result<T, Self::Error>
+    where
+        F: FnOnce(&mut Self) -> Result<T, Self::Error>,
+    {
+        let mut shell = self.enter_scope(false);
+        for (name, value) in vars {
+            shell.variables.shadow(name, *value);
+        }
+        f(&mut shell)
+    }
+
+    /// Only the `IFS` shell variable is used, not the one of the environment
+    fn field_separators(&self) -> Option<types::Str> {
+        match self.variables.get("IFS") {
+            Some(Value::Str(ifs)) => Some(ifs.clone()),
+            _ => None,
+        }
+    }
+
     /// Expand a string variable given if its quoted / unquoted
     fn string(&self, name: &str) -> Result<types::Str, Self::Error> {
-        if name == "?" {
-            Ok(self.previous_status.into())
-        } else {
-            self.variables().get_str(name).map_err(Into::into)
+        match name.split_once("::") {
+            _ if name == "?" => Ok(self.previous_status.into()),
+            // Disabled colors are still checked, so that the mistakes are not hidden
+            Some(("c" | "color", colors)) if !self.opts.color.is_enabled() => {
+                Colors::collect::<IonError>(colors)?;
+                Ok(types::Str::new())
+            }
+            _ => self.variables().get_str(name).map_err(Into::into),
         }
     }
 
@@ -69,7 +235,19 @@ impl<'a, 'b> Expander for Shell<'b> {
         name: &str,
         selection: &Select<types::Str>,
     ) -> Result<types::Args, Self::Error> {
-        match self.variables.get(name) {
+        if name == "?" {
+            let mut statuses = self.status_history.iter().map(|&status| types::Str::from(status));
+            return Ok(statuses.select(selection, self.status_history.len()));
+        }
+        let args;
+        let value = match self.variables.get(name) {
+            None if name == "args" => {
+                args = Value::Array(self.args.iter().cloned().map(Value::Str).collect());
+                Some(&args)
+            }
+            value => value,
+        };
+        match value {
             Some(Value::Array(array)) => match selection {
                 Select::All => Ok(array.iter().map(|x| format!("{}", x).into()).collect()),
                 Select::Index(ref id) => id
@@ -82,10 +260,8 @@ impl<'a, 'b> Expander for Shell<'b> {
                     .and_then(|(start, length)| {
                         if array.len() > start {
                             Some(
-                                array
-                                    .iter()
-                                    .skip(start)
-                                    .take(length)
+                                range
+                                    .step(array.iter().skip(start).take(length))
                                     .map(|var| format!("{}", var).into())
                                     .collect(),
                             )
@@ -94,7 +270,9 @@ impl<'a, 'b> Expander for Shell<'b> {
                         }
                     })
                     .ok_or(Error::InvalidRange { length: array.len(), range: *range }),
-                Select::Key(_) => Err(Error::InvalidIndex(selection.clone(), "array", name.into())),
+                Select::Key(_) | Select::KeyRange(..) => {
+                    Err(Error::InvalidIndex(selection.clone(), "array", name.into()))
+                }
             },
             Some(Value::HashMap(hmap)) => match selection {
                 Select::All => {
@@ -131,14 +309,21 @@ impl<'a, 'b> Expander for Shell<'b> {
                         .unwrap_or(&Value::Str("".into()))
                     )])
                 }
-                Select::Range(_) => {
+                Select::Range(_) | Select::KeyRange(..) => {
                     Err(Error::InvalidIndex(selection.clone(), "hashmap", name.into()))
                 }
             },
             Some(Value::BTreeMap(bmap)) => match selection {
-                Select::All => {
+                Select::All | Select::KeyRange(..) => {
                     let mut array = types::Args::new();
-                    for (key, value) in bmap.iter() {
+                    let pairs = match selection {
+                        Select::KeyRange(start, end) if is_empty_key_range(start, end) => None,
+                        Select::KeyRange(start, end) => {
+                            Some(bmap.range((start.clone(), end.clone())))
+                        }
+                        _ => Some(bmap.range::<types::Str, _>(..)),
+                    };
+                    for (key, value) in pairs.into_iter().flatten() {
                         array.push(key.clone());
                         let f = format!("{}", value);
                         match *value {
@@ -192,6 +377,15 @@ impl<'a, 'b> Expander for Shell<'b> {
         }
     }
 
+    fn map_key_at(&self, name: &str, position: usize) -> Result<Option<types::Str>, Self::Error> {
+        match self.variables.get(name) {
+            Some(Value::BTreeMap(map)) => Ok(map.keys().nth(position).cloned()),
+            Some(Value::HashMap(_)) => Err(Error::NotAnOrderedMap(name.into())),
+            Some(_) => Err(Error::NotAMap(name.into())),
+            None => Err(Error::VarNotFound(name.into())),
+        }
+    }
+
     fn map_values(&self, name: &str) -> Result<types::Args, Self::Error> {
         match self.variables.get(name) {
             Some(&Value::HashMap(ref map)) => {
@@ -205,6 +399,25 @@ impl<'a, 'b> Expander for Shell<'b> {
         }
     }
 
+    fn map_entries(&self, name: &str) -> Result<types::Args, Self::Error> {
+        match self.variables.get(name) {
+            Some(Value::HashMap(map)) => Ok(map
+                .iter()
+                .flat_map(|(key, value)| [key.clone(), value.to_string().into()])
+                .collect()),
+            Some(Value::BTreeMap(map)) => Ok(map
+                .iter()
+                .flat_map(|(key, value)| [key.clone(), value.to_string().into()])
+                .collect()),
+            Some(_) => Err(Error::NotAMap(name.into())),
+            None => Err(Error::VarNotFound(name.into())),
+        }
+    }
+
+    fn glob_options(&self) -> GlobOptions { self.opts.glob_options }
+
+    fn globbing(&self) -> bool { !self.opts.no_glob }
+
     fn tilde(&self, input: &str) -> Result<types::Str, Self::Error> {
         // Only if the first character is a tilde character will we perform expansions
         if !input.starts_with('~') {
@@ -235,35 +448,14 @@ impl<'a, 'b> Expander for Shell<'b> {
                     .map(|path| path.to_str().unwrap().into())
                     .ok_or(Error::OutOfStack(num))
                 } else {
-                    #[cfg(not(target_os = "redox"))]
-                    {
-                        let user = if tilde_prefix.is_empty() {
-                            users::get_user_by_uid(users::get_current_uid())
-                        } else {
-                            users::get_user_by_name(tilde_prefix)
-                        };
-                        match user {
-                            Some(user) => Ok(user.home_dir().to_string_lossy().as_ref().into()),
-                            None => Err(Error::HomeNotFound),
-                        }
-                    }
-                    #[cfg(target_os = "redox")]
-                    {
-                        if let Ok(users) =
-                            redox_users::AllUsers::basic(redox_users::Config::default())
-                        {
-                            let user = if tilde_prefix.is_empty() {
-                                redox_users::get_uid().ok().and_then(|id| users.get_by_id(id))
-                            } else {
-                                users.get_by_name(tilde_prefix)
-                            };
-                            match user {
-                                Some(user) => Ok(user.home.as_str().into()),
-                                None => Err(Error::HomeNotFound),
-                            }
-                        } else {
-                            Err(Error::HomeNotFound)
-                        }
+                    // `~` is `$HOME` when it is set, as in other shells
+                    match self.variables.get_str("HOME") {
+                        Ok(home) if tilde_prefix.is_empty() && !home.is_empty() => Ok(home),
+                        _ => self
+                            .home_dirs
+                            .borrow_mut()
+                            .get_or_lookup(tilde_prefix, lookup_home)
+                            .ok_or(Error::HomeNotFound),
                     }
                 }
             }
@@ -271,3 +463,221 @@ impl<'a, 'b> Expander for Shell<'b> {
         .map(|home: types::Str| home + rest)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, time::Duration};
+
+    #[test]
+    fn glob_options() {
+        let dir = env::temp_dir().join(format!("ion-glob-options-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in &["Foo.txt", "foo.md", ".hidden.txt"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let dir_str = dir.to_str().unwrap();
+        let glob = |shell: &mut Shell<'_>, pattern: &str| {
+            let mut paths = shell.expand_string(&format!("{}/{}", dir_str, pattern)).unwrap();
+            paths.iter_mut().for_each(|path| *path = path[dir_str.len() + 1..].into());
+            paths.sort();
+            paths
+        };
+
+        let mut shell = Shell::new();
+        assert_eq!(glob(&mut shell, "foo*"), args!["foo.md"]);
+        assert_eq!(glob(&mut shell, "*.txt"), args![".hidden.txt", "Foo.txt"]);
+
+        shell.opts_mut().glob_options.case_sensitive = false;
+        shell.opts_mut().glob_options.require_literal_leading_dot = true;
+        assert_eq!(glob(&mut shell, "foo*"), args!["Foo.txt", "foo.md"]);
+        assert_eq!(glob(&mut shell, "*.txt"), args!["Foo.txt"]);
+
+        // Patterns are kept as they are with globbing disabled, braces included
+        shell.opts_mut().no_glob = true;
+        assert_eq!(glob(&mut shell, "foo*"), args!["foo*"]);
+        assert_eq!(glob(&mut shell, "{foo,Foo}*"), args!["Foo*", "foo*"]);
+        crate::builtins::builtin_set(&args!["set", "+f"], &mut shell);
+        assert_eq!(glob(&mut shell, "foo*"), args!["Foo.txt", "foo.md"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn with_vars_is_scoped() {
+        let mut shell = Shell::new();
+        shell.variables_mut().set("acc", "outer");
+        let expanded = shell
+            .with_vars(&[("acc", "inner"), ("item", "x")], |shell| {
+                shell.expand_string("$acc-$item")
+            })
+            .unwrap();
+        assert_eq!(expanded, args!["inner-x"]);
+        assert_eq!(shell.variables().get_str("acc").unwrap().as_str(), "outer");
+        assert!(shell.variables().get("item").is_none());
+
+        let reduced = shell.expand_string("@reduce([a b c] '$acc$item' '>')").unwrap();
+        assert_eq!(reduced, args![">abc"]);
+
+        let mapped = shell.expand_string("@map([a b] 'echo $_-$_')").unwrap();
+        assert_eq!(mapped, args!["a-a", "b-b"]);
+        assert!(shell.variables().get("_").is_none());
+    }
+
+    #[test]
+    fn keys_at_positions() {
+        let mut shell = Shell::new();
+        shell.execute_command(&b"let map:bmap[str] = [c=3 a=1 b=2]"[..]).unwrap();
+        let key_at = |shell: &mut Shell<'_>, position| {
+            shell.expand(&format!("$keyat(map {})", position)).unwrap()
+        };
+        assert_eq!(key_at(&mut shell, 0).as_str(), "a");
+        assert_eq!(key_at(&mut shell, 2).as_str(), "c");
+        assert_eq!(key_at(&mut shell, 3).as_str(), "");
+
+        shell.execute_command(&b"let hash:hmap[str] = [a=1]"[..]).unwrap();
+        assert!(shell.expand("$keyat(hash 0)").is_err());
+        assert!(shell.expand("$keyat(map x)").is_err());
+    }
+
+    #[test]
+    fn repeated_tilde_expansion() {
+        let shell = Shell::new();
+        let expected = shell.tilde("~/dir").unwrap();
+        assert!(expected.ends_with("/dir"));
+        for _ in 0..10 {
+            assert_eq!(shell.tilde("~/dir").unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn tilde_follows_home() {
+        let mut shell = Shell::new();
+        shell.variables_mut().set("HOME", "/custom/home");
+        assert_eq!(shell.tilde("~/dir").unwrap().as_str(), "/custom/home/dir");
+        shell.variables_mut().set("HOME", "/other");
+        assert_eq!(shell.tilde("~").unwrap().as_str(), "/other");
+    }
+
+    #[test]
+    #[cfg(not(target_os = "redox"))]
+    fn tilde_user_expansion() {
+        let user = users::get_user_by_uid(users::get_current_uid()).unwrap();
+        let name = user.name().to_str().unwrap();
+        let home = user.home_dir().to_str().unwrap();
+
+        let shell = Shell::new();
+        assert_eq!(shell.tilde(&format!("~{}", name)).unwrap().as_str(), home);
+        let sub = format!("{}/sub/dir", home);
+        assert_eq!(shell.tilde(&format!("~{}/sub/dir", name)).unwrap().as_str(), sub);
+        assert!(matches!(shell.tilde("~no-such-user-for-ion/dir"), Err(Error::HomeNotFound)));
+    }
+
+    #[test]
+    fn encode_binary_output() {
+        let path = env::temp_dir().join(format!("ion-binary-output-{}", std::process::id()));
+        fs::write(&path, [0, 0xff, b'\n', 0x80]).unwrap();
+        let mut shell = Shell::new();
+        shell.variables_mut().set("path", path.to_str().unwrap());
+
+        assert_eq!(shell.command_bytes("cat $path").unwrap(), [0, 0xff, b'\n', 0x80]);
+        assert!(shell.command("cat $path", false).is_err());
+        assert_eq!(shell.expand_string("$hex($(cat $path))").unwrap(), args!["00ff0a80"]);
+        assert_eq!(shell.expand_string("$base64($(cat $path))").unwrap(), args!["AP8KgA=="]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn map_key_ranges() {
+        let mut shell = Shell::new();
+        shell
+            .execute_command(
+                &b"let bmap:bmap[int] = [b=2 a=1 ab=3 c=4]; let hmap:hmap[int] = [a=1 b=2]"[..],
+            )
+            .unwrap();
+        assert_eq!(shell.expand_string("@bmap['a'..'b']").unwrap(), args!["a", "1", "ab", "3"]);
+        assert_eq!(shell.expand_string("@bmap['ab'..='b']").unwrap(), args!["ab", "3", "b", "2"]);
+        assert_eq!(shell.expand_string("@bmap[..\"ab\"]").unwrap(), args!["a", "1"]);
+        assert_eq!(shell.expand_string("@bmap['b'..]").unwrap(), args!["b", "2", "c", "4"]);
+        assert!(shell.expand_string("@bmap['c'..'a']").unwrap().is_empty());
+        assert!(shell.expand_string("@bmap['a'..'a']").unwrap().is_empty());
+
+        // Exact keys still work, and ranges of keys are only valid on BTreeMaps
+        assert_eq!(shell.expand_string("@hmap['b']").unwrap(), args!["2"]);
+        assert!(shell.expand_string("@hmap['a'..'b']").is_err());
+    }
+
+    #[test]
+    fn capture_stderr() {
+        let mut shell = Shell::new();
+        let command = "echo out; sh -c 'echo err >&2'";
+        assert_eq!(shell.expand_string(&format!("$({})", command)).unwrap(), args!["out"]);
+        assert_eq!(shell.expand_string(&format!("$&({})", command)).unwrap(), args!["out\nerr"]);
+        assert_eq!(shell.expand_string(&format!("@&({})", command)).unwrap(), args!["out", "err"]);
+        // Nested substitutions still only capture their standard output by default
+        assert_eq!(shell.expand_string("$&(echo $(sh -c 'echo a >&2')b)").unwrap(), args!["b"]);
+    }
+
+    #[test]
+    fn array_process_splits_on_ifs() {
+        let mut shell = Shell::new();
+        let command = "@(printf 'a b\\nc  d\\n\\n')";
+        assert_eq!(shell.expand_string(command).unwrap(), args!["a", "b", "c", "d"]);
+        shell.variables_mut().set("IFS", "\n");
+        assert_eq!(shell.expand_string(command).unwrap(), args!["a b", "c  d"]);
+        assert_eq!(shell.expand_string(&format!("{}[1]", command)).unwrap(), args!["c  d"]);
+        shell.variables_mut().set("IFS", ":,");
+        assert_eq!(shell.expand_string("@(echo a:b,,c)").unwrap(), args!["a", "b", "c"]);
+        // $() is never split
+        assert_eq!(shell.expand_string("$(echo a:b)").unwrap(), args!["a:b"]);
+    }
+
+    #[test]
+    fn command_timeout() {
+        let mut shell = Shell::new();
+        shell.opts_mut().command_timeout = Some(Duration::from_millis(100));
+        assert_eq!(shell.expand_string("$(echo fast)").unwrap(), args!["fast"]);
+
+        let start = Instant::now();
+        match shell.expand_string("$(echo slow; sleep 10)") {
+            Err(Error::Subprocess(err)) => {
+                assert!(matches!(*err, IonError::PipelineExecutionError(PipelineError::TimedOut)))
+            }
+            result => panic!("expected a timeout, got {:?}", result),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // The timeout also stops loops of builtins running other commands
+        assert!(shell.expand_string("$(while true; sleep 0.05; end)").is_err());
+        assert!(shell.expand_string("$(while true; end)").is_err());
+        assert!(shell.expand_string("$(for i in 0..1000000000; let x = $i; end)").is_err());
+        assert_eq!(shell.expand_string("$(echo again)").unwrap(), args!["again"]);
+    }
+
+    #[test]
+    fn expand_templates() {
+        let mut shell = Shell::new();
+        shell.variables_mut().set("name", "world");
+        assert_eq!(shell.expand("hello $name").unwrap().as_str(), "hello world");
+        assert_eq!(shell.expand("$len($name) $(echo sub)").unwrap().as_str(), "5 sub");
+        assert_eq!(shell.expand("{a,b}").unwrap().as_str(), "a b");
+        assert_eq!(shell.expand("'$name'").unwrap().as_str(), "$name");
+        assert!(shell.expand("@missing[0]").is_err());
+    }
+
+    #[test]
+    fn cached_substitutions_run_once() {
+        let mut shell = Shell::new();
+        let script = "let runs = []\nfor i in 1..4\n  echo $cached($(let runs ++= $i; echo \
+                      once))\nend\necho @runs";
+        let (output, _) = shell.execute_command_captured(script.as_bytes()).unwrap();
+        assert_eq!(output.as_str(), "once\nonce\nonce\n1\n");
+
+        // The output is only kept for the command line being executed
+        let script = "let runs = []; echo $cached($(let runs ++= x)) $cached($(let runs ++= y))";
+        shell.execute_command(script.as_bytes()).unwrap();
+        shell.execute_command(script.as_bytes()).unwrap();
+        assert_eq!(shell.expand("@runs").unwrap().as_str(), "x y");
+    }
+}
@@ -0,0 +1,105 @@
+use super::{
+    directory_stack::{DirStackError, DirectoryStack},
+    variables::Variables,
+    Options, Shell,
+};
+use crate::builtins::Status;
+use std::io;
+
+/// A copy of the state of a shell, taken with [`Shell::snapshot`] and rolled back to with
+/// [`Shell::restore`].
+///
+/// It holds the variables, functions and aliases of every scope, the directory stack, the
+/// options and the last exit status. Background jobs are left out.
+#[derive(Clone)]
+pub struct Snapshot {
+    variables:       Variables,
+    directory_stack: DirectoryStack,
+    opts:            Options,
+    previous_status: Status,
+}
+
+impl<'a> Shell<'a> {
+    /// Capture the current state of the shell, so that speculative commands can be executed and
+    /// undone afterwards with [`Shell::restore`].
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            variables:       self.variables.clone(),
+            directory_stack: self.directory_stack.clone(),
+            opts:            self.opts.clone(),
+            previous_status: self.previous_status,
+        }
+    }
+
+    /// Roll the shell back to the state captured in `snapshot`, including the working
+    /// directory.
+    ///
+    /// Everything but the working directory is restored even if changing back to it fails.
+    pub fn restore(&mut self, snapshot: Snapshot) -> io::Result<()> {
+        self.variables.replace_scopes(snapshot.variables);
+        self.directory_stack = snapshot.directory_stack;
+        self.opts = snapshot.opts;
+        self.previous_status = snapshot.previous_status;
+
+        self.directory_stack.set_current_dir_by_index(0).map_err(|why| match why {
+            DirStackError::DirChangeFailure { cause, .. } => cause,
+            why => io::Error::new(io::ErrorKind::NotFound, why.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test_derive::serial;
+    use std::env;
+
+    #[test]
+    #[serial]
+    fn restore_rolls_back_state() {
+        let mut shell = Shell::new();
+        shell.execute_command(&b"let kept = before; fn greet\n  echo hi\nend"[..]).unwrap();
+        let cwd = env::current_dir().unwrap();
+        let snapshot = shell.snapshot();
+
+        let temp = env::temp_dir();
+        shell
+            .execute_command(
+                format!(
+                    "let kept = after; let added = new; fn greet\n  echo bye\nend\nfn \
+                     other\nend\ncd {}\nfalse",
+                    temp.display()
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        shell.opts_mut().err_exit = true;
+        assert_eq!(shell.variables().get_str("kept").unwrap().as_str(), "after");
+        assert_ne!(env::current_dir().unwrap(), cwd);
+
+        shell.restore(snapshot).unwrap();
+        assert_eq!(shell.variables().get_str("kept").unwrap().as_str(), "before");
+        assert!(shell.variables().get("added").is_none());
+        assert!(shell.get_func("other").is_none());
+        let (output, _) = shell.run_in_subshell("greet").unwrap();
+        assert_eq!(output.as_str(), "hi\n");
+        assert_eq!(env::current_dir().unwrap(), cwd);
+        assert!(!shell.opts().err_exit);
+        assert_eq!(shell.previous_status(), Status::SUCCESS);
+    }
+
+    #[test]
+    #[serial]
+    fn snapshot_can_be_reused() {
+        let mut shell = Shell::new();
+        shell.variables_mut().set("x", "1");
+        let snapshot = shell.snapshot();
+
+        for value in &["2", "3"] {
+            shell.variables_mut().set("x", *value);
+            shell.restore(snapshot.clone()).unwrap();
+            assert_eq!(shell.variables().get_str("x").unwrap().as_str(), "1");
+        }
+    }
+}
@@ -0,0 +1,475 @@
+use super::{pipe_exec::create_pipe, IonResult, PipelineError, Shell, Trap};
+use crate::{
+    builtins::Status,
+    expansion::{WordIterator, WordToken},
+    types,
+};
+#[cfg(not(target_os = "redox"))]
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::{
+    errno::Errno,
+    libc::c_int,
+    poll::{poll, PollFd, PollFlags},
+    sys::{
+        signal::{self, Signal},
+        wait::{self, WaitStatus},
+    },
+    unistd::{self, ForkResult, Pid},
+};
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::io::AsRawFd,
+    thread,
+    time::Instant,
+};
+
+/// A forked subshell, which is killed and reaped if it is dropped before being waited for, so
+/// that no process is left behind when the operation it belongs to fails or is dropped.
+#[derive(Debug)]
+pub struct Subshell {
+    pid:    Pid,
+    /// The process which forked the subshell. The copies of the guard in other forks leave the
+    /// subshell alone.
+    owner:  Pid,
+    reaped: bool,
+}
+
+impl Subshell {
+    fn new(pid: Pid) -> Self { Self { pid, owner: unistd::getpid(), reaped: false } }
+
+    /// Blocks until the subshell exits, returning its exit status.
+    fn wait(mut self) -> Result<Status, PipelineError> {
+        self.reaped = true;
+        Shell::wait_for_subshell(self.pid)
+    }
+}
+
+impl Drop for Subshell {
+    fn drop(&mut self) {
+        if !self.reaped && unistd::getpid() == self.owner {
+            let _ = signal::kill(self.pid, Signal::SIGKILL);
+            let _ = Shell::wait_for_subshell(self.pid);
+        }
+    }
+}
+
+/// Reads `reader` up to its end, failing with `TimedOut` if it is not done by `deadline`.
+fn read_until(reader: &mut File, deadline: Option<Instant>) -> Result<Vec<u8>, PipelineError> {
+    let mut output = Vec::with_capacity(1024);
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => {
+            reader.read_to_end(&mut output).map_err(PipelineError::CaptureFailed)?;
+            return Ok(output);
+        }
+    };
+
+    let mut buffer = [0; 4096];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now()).as_millis();
+        let mut fds = [PollFd::new(reader.as_raw_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, c_int::try_from(remaining).unwrap_or(c_int::MAX)) {
+            Ok(0) => return Err(PipelineError::TimedOut),
+            Ok(_) => (),
+            Err(Errno::EINTR) => continue,
+            Err(err) => return Err(PipelineError::CaptureFailed(err.into())),
+        }
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(output),
+            Ok(count) => output.extend_from_slice(&buffer[..count]),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(PipelineError::CaptureFailed(err)),
+        }
+    }
+}
+
+impl<'a> Shell<'a> {
+    /// Parses and executes the given Ion code in a forked subshell, capturing its standard
+    /// output.
+    ///
+    /// The subshell works on a copy of the shell's state, so variables, functions and
+    /// directory changes made by `code` are not visible from the calling shell. The captured
+    /// output is returned along with the exit status of the subshell.
+    pub fn run_in_subshell(&mut self, code: &str) -> IonResult<(types::Str, Status)> {
+        let (mut reader, writer) = create_pipe()?;
+
+        match unsafe { unistd::fork() }.map_err(PipelineError::CreateForkError)? {
+            ForkResult::Child => {
+                drop(reader);
+                self.exec_subshell(code, Some(writer))
+            }
+            ForkResult::Parent { child } => {
+                let child = Subshell::new(child);
+                // Drop our end of the writer so that reading stops when the child exits
+                drop(writer);
+
+                let mut output = String::with_capacity(1024);
+                let captured = reader.read_to_string(&mut output);
+                let status = child.wait()?;
+                captured.map_err(PipelineError::CaptureFailed)?;
+
+                Ok((output.into(), status))
+            }
+        }
+    }
+
+    /// Executes the command(s) in the current shell, the same way as
+    /// [`Shell::execute_command`], but captures what they write to the standard output
+    /// instead of letting it through.
+    ///
+    /// Unlike [`Shell::run_in_subshell`], changes made to the shell's state are kept. The
+    /// output is returned once the commands have exited. Jobs sent to the background are not
+    /// waited for: they write to the standard output which the capture replaced.
+    pub fn execute_command_captured<T: Read>(
+        &mut self,
+        command: T,
+    ) -> IonResult<(types::Str, Status)> {
+        let (mut reader, writer) = create_pipe()?;
+        // Read while the command runs, so that it does not block once the pipe is full
+        let output = thread::spawn(move || {
+            let mut output = String::with_capacity(1024);
+            reader.read_to_string(&mut output).map(|_| output)
+        });
+
+        let original = self.stdout.as_ref().map(File::try_clone).transpose();
+        let original = original.map_err(PipelineError::ClonePipeFailed)?;
+        let outer = self.capture.replace((writer.as_raw_fd(), original));
+        let previous = self.stdout(writer);
+        let result = self.execute_command(command);
+        let flushed = self.flush();
+        // Drop our end of the writer so that reading stops
+        drop(self.stdout(previous));
+        self.capture = outer;
+
+        let output = output.join().expect("the capture of the standard output panicked");
+        let status = result?;
+        flushed.map_err(PipelineError::CaptureFailed)?;
+        Ok((output.map_err(PipelineError::CaptureFailed)?.into(), status))
+    }
+
+    /// Runs the command substitutions found in `words` at once, each in its own subshell, and
+    /// keeps their output for when the words are expanded.
+    ///
+    /// The subshells still running past the timeout of the substitutions are killed.
+    pub(crate) fn prefetch_substitutions(&mut self, words: &[types::Str]) -> IonResult<()> {
+        let commands: Vec<_> = words
+            .iter()
+            .flat_map(|word| WordIterator::new(word, true))
+            // Syntax errors are reported when the words are expanded
+            .filter_map(Result::ok)
+            .filter_map(|token| match token {
+                WordToken::Process(command, false, _)
+                | WordToken::ArrayProcess(command, _, false, _) => Some(command),
+                _ => None,
+            })
+            .collect();
+        if commands.len() < 2 {
+            return Ok(());
+        }
+
+        let deadline = self.substitution_deadline();
+        let mut children = Vec::with_capacity(commands.len());
+        for command in commands {
+            let (reader, writer) = create_pipe()?;
+            match unsafe { unistd::fork() }.map_err(PipelineError::CreateForkError)? {
+                ForkResult::Child => {
+                    drop(reader);
+                    // The pipes of the other substitutions are of no use here
+                    children.clear();
+                    self.deadline = deadline;
+                    self.exec_subshell(command, Some(writer))
+                }
+                ForkResult::Parent { child } => {
+                    children.push((command, Subshell::new(child), reader))
+                }
+            }
+        }
+
+        // The subshells left are killed on failures, as the guards are dropped
+        for (command, child, mut reader) in children {
+            let output = read_until(&mut reader, deadline)?;
+            child.wait()?;
+            self.prefetched.push((command.into(), output));
+        }
+        Ok(())
+    }
+
+    /// Executes the given Ion code in a forked subshell, as for a `( ... )` group, and returns
+    /// its exit status. The subshell writes to the same outputs as the shell, but its changes
+    /// to the variables and the working directory are not visible from the shell.
+    pub(crate) fn execute_subshell(&mut self, code: &str) -> IonResult<Status> {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        match unsafe { unistd::fork() }.map_err(PipelineError::CreateForkError)? {
+            ForkResult::Child => {
+                // The `ERR` trap runs once for the whole subshell, in the shell
+                self.traps.remove(&Trap::Err);
+                self.exec_subshell(code, None)
+            }
+            ForkResult::Parent { child } => Ok(Subshell::new(child).wait()?),
+        }
+    }
+
+    /// Starts `command` in a subshell for a process substitution, and returns the path which
+    /// either feeds its standard input if `input` is set, or reads its standard output.
+    ///
+    /// The shell keeps its end of the pipe open until [`Shell::finish_substitutions`], so that
+    /// the path can still be opened by the commands of the pipeline.
+    #[cfg(not(target_os = "redox"))]
+    pub(crate) fn start_substitution(
+        &mut self,
+        command: &str,
+        input: bool,
+    ) -> Result<types::Str, PipelineError> {
+        let (reader, writer) = create_pipe()?;
+        let (kept, given) = if input { (writer, reader) } else { (reader, writer) };
+        // External commands open the path too, so they need to inherit the pipe
+        fcntl(kept.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))
+            .map_err(PipelineError::CreatePipeError)?;
+
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        match unsafe { unistd::fork() }.map_err(PipelineError::CreateForkError)? {
+            ForkResult::Child => {
+                drop(kept);
+                // Only the shell must keep the pipes of the other substitutions open
+                self.substitutions.clear();
+                if input {
+                    self.stdin(given);
+                    self.exec_subshell(command, None)
+                } else {
+                    self.exec_subshell(command, Some(given))
+                }
+            }
+            ForkResult::Parent { child } => {
+                let path = format!("/dev/fd/{}", kept.as_raw_fd());
+                self.substitutions.push((Subshell::new(child), kept));
+                Ok(path.into())
+            }
+        }
+    }
+
+    /// Redox has no `/dev/fd` paths to give for the pipes of process substitutions.
+    #[cfg(target_os = "redox")]
+    pub(crate) fn start_substitution(
+        &mut self,
+        _command: &str,
+        _input: bool,
+    ) -> Result<types::Str, PipelineError> {
+        Err(PipelineError::UnsupportedSubstitution)
+    }
+
+    /// Closes the pipes of the process substitutions started since there were `count` of them,
+    /// and waits for their processes to exit.
+    pub(crate) fn finish_substitutions(&mut self, count: usize) {
+        for (child, pipe) in self.substitutions.drain(count..) {
+            drop(pipe);
+            let _ = child.wait();
+        }
+    }
+
+    /// Runs the code within the child process and exits with its status.
+    fn exec_subshell(&mut self, code: &str, stdout: Option<File>) -> ! {
+        self.opts_mut().grab_tty = false;
+        if let Some(stdout) = stdout {
+            self.stdout(stdout);
+        }
+
+        let status = self.execute_command(code.as_bytes()).unwrap_or_else(|err| {
+            eprintln!("ion: {}", err);
+            Status::from(err)
+        });
+
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        unsafe { nix::libc::_exit(status.as_os_code()) }
+    }
+
+    /// Blocks until the subshell exits, returning its exit status.
+    fn wait_for_subshell(child: Pid) -> Result<Status, PipelineError> {
+        loop {
+            match wait::waitpid(child, None) {
+                Ok(WaitStatus::Exited(_, code)) => break Ok(Status::from_exit_code(code)),
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    break Ok(Status::signaled(signal as i32))
+                }
+                Ok(_) => (),
+                Err(nix::errno::Errno::EINTR) => (),
+                Err(err) => break Err(PipelineError::WaitPid(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test_derive::serial;
+    use std::{
+        env,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn captures_output() {
+        let mut shell = Shell::new();
+        let (output, status) = shell.run_in_subshell("echo hello; echo world").unwrap();
+        assert_eq!(output.as_str(), "hello\nworld\n");
+        assert_eq!(status, Status::SUCCESS);
+    }
+
+    #[test]
+    fn captures_script_output_in_current_shell() {
+        let mut shell = Shell::new();
+        let script = "let x = kept\necho $x\nfor i in [1 2]\n  /bin/echo $i\nend\nfalse";
+        let (output, status) = shell.execute_command_captured(script.as_bytes()).unwrap();
+        assert_eq!(output.as_str(), "kept\n1\n2\n");
+        assert_eq!(status, Status::FALSE);
+        assert_eq!(shell.variables().get_str("x").unwrap().as_str(), "kept");
+
+        // More output than a pipe holds does not block the script
+        let (output, _) = shell.execute_command_captured(&b"seq 1 20000"[..]).unwrap();
+        assert_eq!(output.lines().count(), 20000);
+    }
+
+    #[test]
+    fn capture_does_not_wait_for_background_jobs() {
+        let mut shell = Shell::new();
+        let start = Instant::now();
+        let script = &b"sleep 5 &
+sleep 5 &!
+echo done"[..];
+        let (output, status) = shell.execute_command_captured(script).unwrap();
+        assert_eq!(output.as_str(), "done\n");
+        assert_eq!(status, Status::SUCCESS);
+        assert!(start.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn returns_status() {
+        let mut shell = Shell::new();
+        let (output, status) = shell.run_in_subshell("echo failing; false").unwrap();
+        assert_eq!(output.as_str(), "failing\n");
+        assert_eq!(status, Status::FALSE);
+    }
+
+    #[test]
+    fn isolates_parent_state() {
+        let mut shell = Shell::new();
+        shell.variables_mut().set("x", "parent");
+
+        let (output, _) = shell.run_in_subshell("let x = child; let y = new; echo $x").unwrap();
+        assert_eq!(output.as_str(), "child\n");
+        assert_eq!(shell.variables().get_str("x").unwrap().as_str(), "parent");
+        assert!(shell.variables().get("y").is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn groups_isolate_parent_state() {
+        let mut shell = Shell::new();
+        shell.variables_mut().set("x", "parent");
+        let cwd = env::current_dir().unwrap();
+
+        let code = format!(
+            "(cd {}; let x = child; let y = new; sh -c 'exit 3')",
+            env::temp_dir().display()
+        );
+        shell.execute_command(code.as_bytes()).unwrap();
+        assert_eq!(shell.previous_status(), Status::from_exit_code(3));
+        assert_eq!(env::current_dir().unwrap(), cwd);
+        assert_eq!(shell.variables().get_str("x").unwrap().as_str(), "parent");
+        assert!(shell.variables().get("y").is_none());
+
+        let (output, _) =
+            shell.run_in_subshell("(let x = child; echo $x) && (false) || echo $x $?").unwrap();
+        assert_eq!(output.as_str(), "child\nparent 1\n");
+    }
+
+    #[test]
+    fn output_process_substitutions() {
+        let mut shell = Shell::new();
+        let dir = env::temp_dir().join(format!("ion-substitution-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let code = format!(
+            "cd {}; echo hello > >(cat > builtin); echo world | tee >(tr a-z A-Z > external) > \
+             /dev/null; {{ echo b; echo a; }} > >(sort > group)",
+            dir.display()
+        );
+        shell.run_in_subshell(&code).unwrap();
+
+        // The substituted commands have exited by the time the pipeline is done
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("builtin"), "hello\n");
+        assert_eq!(read("external"), "WORLD\n");
+        assert_eq!(read("group"), "a\nb\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn input_process_substitutions() {
+        let mut shell = Shell::new();
+        let mut output = |code: &str| shell.run_in_subshell(code).unwrap();
+
+        assert_eq!(output("cat <(echo one) <(echo two)").0.as_str(), "one\ntwo\n");
+        assert_eq!(output("cat < <(echo redirected)").0.as_str(), "redirected\n");
+        assert_eq!(output("echo $(wc -l < <(seq 1 3))").0.as_str(), "3\n");
+        assert_eq!(output("{ cat; } < <(echo group)").0.as_str(), "group\n");
+        let (_, status) = output("diff <(echo a) <(echo b) > /dev/null");
+        assert_eq!(status, Status::FALSE);
+        // The substituted commands are done once they are not read anymore
+        assert_eq!(output("head -c 4 <(yes)").0.as_str(), "y\ny\n");
+
+        shell.execute_command(&b"cat <(echo a) > /dev/null"[..]).unwrap();
+        assert!(shell.substitutions.is_empty());
+    }
+
+    #[test]
+    fn parallel_substitutions() {
+        let mut shell = Shell::new();
+        shell.opts_mut().parallel_substitutions = true;
+
+        let start = Instant::now();
+        let (output, status) = shell
+            .run_in_subshell("echo $(sleep 0.5; echo first) @(sleep 0.5; echo second third) last")
+            .unwrap();
+        assert_eq!(output.as_str(), "first second third last\n");
+        assert_eq!(status, Status::SUCCESS);
+        assert!(start.elapsed() < Duration::from_millis(900));
+
+        // Repeated substitutions are each run
+        let (output, _) = shell.run_in_subshell("echo $(echo a) $(echo a) @(echo b c)").unwrap();
+        assert_eq!(output.as_str(), "a a b c\n");
+    }
+
+    #[test]
+    fn dropped_subshells_are_killed_and_reaped() {
+        let child = match unsafe { unistd::fork() }.unwrap() {
+            ForkResult::Child => {
+                std::thread::sleep(Duration::from_secs(10));
+                unsafe { nix::libc::_exit(0) }
+            }
+            ForkResult::Parent { child } => child,
+        };
+
+        let start = Instant::now();
+        drop(Subshell::new(child));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // The child no longer exists, not even as a zombie
+        assert_eq!(signal::kill(child, None), Err(Errno::ESRCH));
+    }
+
+    #[test]
+    fn parallel_substitutions_time_out() {
+        let mut shell = Shell::new();
+        shell.opts_mut().parallel_substitutions = true;
+        shell.opts_mut().command_timeout = Some(Duration::from_millis(200));
+
+        let start = Instant::now();
+        let (_, status) = shell.run_in_subshell("echo $(sleep 10; echo a) $(echo b)").unwrap();
+        assert!(!status.is_success());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
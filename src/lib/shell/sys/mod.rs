@@ -1,5 +1,11 @@
-//! System specific shell variables for NULL_PATH
+//! System specific shell variables for NULL_PATH, and terminal handling
 
 #[cfg(unix)]
 /// NULL_PATH on Unix systems
 pub const NULL_PATH: &str = "/dev/null";
+
+#[cfg(unix)]
+mod terminal;
+
+#[cfg(unix)]
+pub(crate) use self::terminal::TerminalMode;
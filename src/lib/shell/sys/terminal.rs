@@ -0,0 +1,53 @@
+use nix::sys::termios::{self, LocalFlags, SetArg, Termios};
+use std::os::unix::io::RawFd;
+
+/// Changes the input mode of a terminal, restoring the previous mode when dropped, even if
+/// reading from the terminal failed in between.
+pub struct TerminalMode {
+    fd:       RawFd,
+    original: Termios,
+}
+
+impl TerminalMode {
+    /// Sets the terminal of `fd` to deliver input as soon as it is typed instead of line by
+    /// line when `canonical` is false, and to stop echoing input when `echo` is false.
+    pub fn new(fd: RawFd, canonical: bool, echo: bool) -> nix::Result<Self> {
+        let original = termios::tcgetattr(fd)?;
+        let mut mode = original.clone();
+        mode.local_flags.set(LocalFlags::ICANON, canonical);
+        mode.local_flags.set(LocalFlags::ECHO, echo);
+        if !canonical {
+            // Return from reads as soon as a single byte is available
+            mode.control_chars[termios::SpecialCharacterIndices::VMIN as usize] = 1;
+            mode.control_chars[termios::SpecialCharacterIndices::VTIME as usize] = 0;
+        }
+        termios::tcsetattr(fd, SetArg::TCSANOW, &mode)?;
+        Ok(Self { fd, original })
+    }
+}
+
+impl Drop for TerminalMode {
+    fn drop(&mut self) { let _ = termios::tcsetattr(self.fd, SetArg::TCSANOW, &self.original); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::{pty::openpty, unistd::close};
+
+    #[test]
+    fn restores_mode_on_drop() {
+        let pty = openpty(None, None).unwrap();
+        let flags = || termios::tcgetattr(pty.slave).unwrap().local_flags;
+        assert!(flags().contains(LocalFlags::ICANON | LocalFlags::ECHO));
+
+        {
+            let _mode = TerminalMode::new(pty.slave, false, false).unwrap();
+            assert!(!flags().intersects(LocalFlags::ICANON | LocalFlags::ECHO));
+        }
+        assert!(flags().contains(LocalFlags::ICANON | LocalFlags::ECHO));
+
+        close(pty.master).unwrap();
+        close(pty.slave).unwrap();
+    }
+}
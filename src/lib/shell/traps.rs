@@ -0,0 +1,229 @@
+use super::Shell;
+use crate::{builtins::Status, expansion::pipelines::Pipeline, shell::RefinedJob, types};
+use scopes::Scoped;
+use std::{fmt, str::FromStr};
+
+/// A condition on which the shell runs the Ion code set for it with [`Shell::set_trap`]
+///
+/// Unlike the callbacks, which run Rust code, traps are set from scripts with the `trap` builtin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Trap {
+    /// The shell exits, at the end of its input or because of the `exit` builtin
+    Exit,
+    /// A command fails, unless it is a condition such as the expression of an `if`, or the left
+    /// side of `&&` or `||`
+    Err,
+    /// A command is about to run, after its expansion
+    Debug,
+}
+
+impl Trap {
+    /// The name of the trap, as given to the `trap` builtin
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Exit => "EXIT",
+            Self::Err => "ERR",
+            Self::Debug => "DEBUG",
+        }
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.name()) }
+}
+
+impl FromStr for Trap {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, ()> {
+        match name {
+            "EXIT" | "0" => Ok(Self::Exit),
+            "ERR" => Ok(Self::Err),
+            "DEBUG" => Ok(Self::Debug),
+            _ => Err(()),
+        }
+    }
+}
+
+impl<'a> Shell<'a> {
+    /// Run `code` when `trap` happens, or stop running anything if `code` is `None`.
+    ///
+    /// The code set before for the trap is returned.
+    pub fn set_trap<T: Into<types::Str>>(
+        &mut self,
+        trap: Trap,
+        code: Option<T>,
+    ) -> Option<types::Str> {
+        match code {
+            Some(code) => self.traps.insert(trap, code.into()),
+            None => self.traps.remove(&trap),
+        }
+    }
+
+    /// Get the code that runs when `trap` happens.
+    #[must_use]
+    pub fn trap(&self, trap: Trap) -> Option<&str> { self.traps.get(&trap).map(AsRef::as_ref) }
+
+    /// Iterate over the traps that are set, with their code.
+    pub fn traps(&self) -> impl Iterator<Item = (Trap, &str)> {
+        self.traps.iter().map(|(&trap, code)| (trap, code.as_str()))
+    }
+
+    /// Run the code of the `EXIT` trap, if one is set. The trap is removed so that it only runs
+    /// once, and the previous status is kept so that the shell still exits with it.
+    ///
+    /// Shells are expected to call this right before exiting.
+    pub fn run_exit_trap(&mut self) {
+        if let Some(code) = self.traps.remove(&Trap::Exit) {
+            let status = self.previous_status;
+            if let Err(why) = self.execute_command(code.as_bytes()) {
+                eprintln!("ion: EXIT trap: {}", why);
+            }
+            self.previous_status = status;
+        }
+    }
+
+    /// Run the code of the `ERR` trap for a command that failed with `status`, which is the value
+    /// of `$?` while the trap runs. The failures of the trap itself are ignored as in a
+    /// condition, so that the trap does not run recursively.
+    pub(crate) fn run_err_trap(&mut self, status: Status) {
+        if let Some(code) = self.traps.get(&Trap::Err).cloned() {
+            let previous = self.previous_status;
+            self.previous_status = status;
+            self.variables.set_silently("?", status);
+
+            self.conditions += 1;
+            if let Err(why) = self.execute_command(code.as_bytes()) {
+                eprintln!("ion: ERR trap: {}", why);
+            }
+            self.conditions -= 1;
+
+            self.previous_status = previous;
+        }
+    }
+
+    /// Run the code of the `DEBUG` trap before `pipeline`, with the command in `$COMMAND`. The
+    /// commands of the trap do not run it again.
+    pub(crate) fn run_debug_trap(&mut self, pipeline: &Pipeline<RefinedJob<'_>>) {
+        if self.debugging {
+            return;
+        }
+        if let Some(code) = self.traps.get(&Trap::Debug).cloned() {
+            let status = self.previous_status;
+            self.debugging = true;
+            {
+                let mut shell = self.enter_scope(false);
+                shell.variables.shadow("COMMAND", pipeline.to_string());
+
+                if let Err(why) = shell.execute_command(code.as_bytes()) {
+                    eprintln!("ion: DEBUG trap: {}", why);
+                }
+            }
+            self.debugging = false;
+            self.previous_status = status;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_trap_runs_once() {
+        let mut shell = Shell::new();
+        shell
+            .execute_command(&b"let ran = ''; trap 'let ran = \"$ran+\"; false' EXIT"[..])
+            .unwrap();
+        assert_eq!(shell.trap(Trap::Exit), Some("let ran = \"$ran+\"; false"));
+        shell.set_previous_status(Status::from_exit_code(3));
+
+        shell.run_exit_trap();
+        assert_eq!(shell.variables().get_str("ran").unwrap().as_str(), "+");
+        // The status of the trap does not replace the exit status
+        assert_eq!(shell.previous_status(), Status::from_exit_code(3));
+        assert_eq!(shell.trap(Trap::Exit), None);
+
+        shell.run_exit_trap();
+        assert_eq!(shell.variables().get_str("ran").unwrap().as_str(), "+");
+    }
+
+    #[test]
+    fn err_trap_runs_on_failures() {
+        let mut shell = Shell::new();
+        shell.set_trap(Trap::Err, Some("echo failed $?; false"));
+        let mut output = |code: &str| shell.run_in_subshell(code).unwrap().0;
+
+        assert_eq!(output("false; echo next").as_str(), "failed 1\nnext\n");
+        assert_eq!(output("sh -c 'exit 3'; echo $?").as_str(), "failed 3\n3\n");
+        assert_eq!(output("true; echo ok").as_str(), "ok\n");
+        assert_eq!(output("true && false").as_str(), "failed 1\n");
+        // The function call does not run the trap again
+        assert_eq!(output("fn f\n  false\n  return 2\nend\nf").as_str(), "failed 1\n");
+        // A subshell runs the trap once, for its own status
+        assert_eq!(output("(false; true; sh -c 'exit 3'); echo next").as_str(), "failed 3\nnext\n");
+    }
+
+    #[test]
+    fn err_trap_skips_conditions() {
+        let mut shell = Shell::new();
+        shell.execute_command(&b"let failed = 0; trap 'let failed += 1' ERR"[..]).unwrap();
+        shell
+            .execute_command(
+                &b"if false\nelse if not true\nend\nwhile false\nend\nfalse || true\nfalse && \
+                   true\nnot false\nfn g\n  false || true\nend\ng"[..],
+            )
+            .unwrap();
+        assert_eq!(shell.variables().get_str("failed").unwrap().as_str(), "0");
+    }
+
+    #[test]
+    fn err_exit_skips_conditions() {
+        let mut shell = Shell::new();
+        shell.opts_mut().err_exit = true;
+        shell.execute_command(&b"if false\nend\nfalse || true\nnot false"[..]).unwrap();
+        assert!(shell.execute_command(&b"false"[..]).is_err());
+        let (output, _) = shell.run_in_subshell("(false); echo reached").unwrap();
+        assert_eq!(output.as_str(), "");
+        shell.execute_command(&b"(false) || true"[..]).unwrap();
+    }
+
+    #[test]
+    fn debug_trap_runs_before_commands() {
+        let mut shell = Shell::new();
+        shell.execute_command(&b"let count = 0; trap 'let count += 1; true' DEBUG"[..]).unwrap();
+        shell
+            .execute_command(&b"let x = 1\necho $x > /dev/null\ntrue; test 1 = 2\nfalse"[..])
+            .unwrap();
+        // `let` is not a command, and the `true` of the trap does not run it again
+        assert_eq!(shell.variables().get_str("count").unwrap().as_str(), "4");
+        // The trap does not change the status, and $COMMAND is only set while it runs
+        assert_eq!(shell.previous_status(), Status::FALSE);
+        assert!(shell.variables().get("COMMAND").is_none());
+    }
+
+    #[test]
+    fn debug_trap_receives_commands() {
+        let mut shell = Shell::new();
+        shell.set_trap(Trap::Debug, Some("echo \"+ $COMMAND\""));
+        let (output, _) =
+            shell.run_in_subshell("let x = a\necho $x\nfn f\n  echo b\nend\nf").unwrap();
+        assert_eq!(output.as_str(), "+ echo a\na\n+ f\n+ echo b\nb\n");
+    }
+
+    #[test]
+    fn set_and_remove_traps() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.set_trap(Trap::Exit, Some("echo bye")), None);
+        assert_eq!(shell.traps().collect::<Vec<_>>(), [(Trap::Exit, "echo bye")]);
+        assert_eq!(shell.set_trap::<&str>(Trap::Exit, None), Some("echo bye".into()));
+        assert_eq!(shell.traps().count(), 0);
+
+        assert_eq!("EXIT".parse(), Ok(Trap::Exit));
+        assert_eq!("0".parse(), Ok(Trap::Exit));
+        assert_eq!("ERR".parse(), Ok(Trap::Err));
+        assert_eq!("DEBUG".parse(), Ok(Trap::Debug));
+        assert_eq!("exit".parse::<Trap>(), Err(()));
+    }
+}
@@ -6,18 +6,26 @@ use crate::{
 };
 use nix::unistd::{geteuid, gethostname, getpid, getuid};
 use scopes::{Namespace, Scope, Scopes};
-use std::{env, ffi::CStr, rc::Rc};
+use std::{cell::RefCell, env, ffi::CStr, rc::Rc};
 use unicode_segmentation::UnicodeSegmentation;
 
 /// Contain a dynamically-typed variable value
 pub use types_rs::Value;
+/// A callback that is executed when a variable is set, with its name and new value
+pub type VarSetCallback = Box<dyn FnMut(&str, &Value<Rc<Function>>)>;
+
 /// A structure containing dynamically-typed values organised in scopes
-pub struct Variables(Scopes<types::Str, Value<Rc<Function>>>);
+#[derive(Clone)]
+pub struct Variables {
+    scopes: Scopes<types::Str, Value<Rc<Function>>>,
+    /// Called for each variable which is set, shared with the copies of the variables
+    on_set: Option<Rc<RefCell<VarSetCallback>>>,
+}
 
 impl Variables {
     /// Get all strings
     pub fn string_vars(&self) -> impl Iterator<Item = (&types::Str, &types::Str)> {
-        self.0.scopes().flat_map(|map| {
+        self.scopes.scopes().flat_map(|map| {
             map.iter().filter_map(|(key, val)| {
                 if let types_rs::Value::Str(val) = val {
                     Some((key, val))
@@ -30,7 +38,7 @@ impl Variables {
 
     /// Get all aliases
     pub fn aliases(&self) -> impl Iterator<Item = (&types::Str, &types::Str)> {
-        self.0.scopes().rev().flat_map(|map| {
+        self.scopes.scopes().rev().flat_map(|map| {
             map.iter().filter_map(|(key, possible_alias)| {
                 if let types_rs::Value::Alias(alias) = possible_alias {
                     Some((key, &**alias))
@@ -43,7 +51,7 @@ impl Variables {
 
     /// Get all the functions
     pub fn functions(&self) -> impl Iterator<Item = (&types::Str, &Rc<Function>)> {
-        self.0.scopes().rev().flat_map(|map| {
+        self.scopes.scopes().rev().flat_map(|map| {
             map.iter().filter_map(|(key, val)| {
                 if let types_rs::Value::Function(val) = val {
                     Some((key, val))
@@ -56,7 +64,7 @@ impl Variables {
 
     /// Get all the variables
     pub fn variables(&self) -> impl Iterator<Item = (&types::Str, &Value<Rc<Function>>)> {
-        self.0.scopes().rev().flat_map(|map| {
+        self.scopes.scopes().rev().flat_map(|map| {
             map.iter().filter_map(|(key, val)| match val {
                 val @ Value::Array(_)
                 | val @ Value::Str(_)
@@ -69,7 +77,7 @@ impl Variables {
 
     /// Get all the array values
     pub fn arrays(&self) -> impl Iterator<Item = (&types::Str, &types::Array<Rc<Function>>)> {
-        self.0.scopes().rev().flat_map(|map| {
+        self.scopes.scopes().rev().flat_map(|map| {
             map.iter().filter_map(|(key, val)| {
                 if let types_rs::Value::Array(val) = val {
                     Some((key, val))
@@ -82,25 +90,25 @@ impl Variables {
 
     /// Create a new scope. If namespace is true, variables won't be droppable across the scope
     /// boundary
-    pub fn new_scope(&mut self, namespace: bool) { self.0.new_scope(namespace) }
+    pub fn new_scope(&mut self, namespace: bool) { self.scopes.new_scope(namespace) }
 
     /// Exit the current scope
-    pub fn pop_scope(&mut self) { self.0.pop_scope() }
+    pub fn pop_scope(&mut self) { self.scopes.pop_scope() }
 
     pub(crate) fn pop_scopes(
         &mut self,
         index: usize,
     ) -> impl Iterator<Item = Scope<types::Str, Value<Rc<Function>>>> + '_ {
-        self.0.pop_scopes(index)
+        self.scopes.pop_scopes(index)
     }
 
     pub(crate) fn append_scopes(&mut self, scopes: Vec<Scope<types::Str, Value<Rc<Function>>>>) {
-        self.0.append_scopes(scopes)
+        self.scopes.append_scopes(scopes)
     }
 
     #[must_use]
     pub(crate) fn index_scope_for_var(&self, name: &str) -> Option<usize> {
-        self.0.index_scope_for_var(name)
+        self.scopes.index_scope_for_var(name)
     }
 
     /// Set a variable to a value in the current scope. If a variable already exists in a writable
@@ -108,19 +116,58 @@ impl Variables {
     /// shadowing other variables
     pub fn set<T: Into<Value<Rc<Function>>>>(&mut self, name: &str, value: T) {
         let value = value.into();
-        if let Some(val) = self.0.get_mut(name) {
+        self.notify(name, &value);
+        self.set_silently(name, value);
+    }
+
+    /// Set a variable as [`Variables::set`] does, without calling the callback. The shell sets
+    /// `$?` and `$CMD_DURATION` this way after each command.
+    pub(crate) fn set_silently<T: Into<Value<Rc<Function>>>>(&mut self, name: &str, value: T) {
+        let value = value.into();
+        if let Some(val) = self.scopes.get_mut(name) {
             let _ = std::mem::replace(val, value);
         } else {
-            self.0.set(name, value);
+            self.scopes.set(name, value);
+        }
+    }
+
+    /// Replace the callback called with the name and value of each variable which is set
+    pub(crate) fn set_on_set(&mut self, callback: Option<VarSetCallback>) {
+        self.on_set = callback.map(|callback| Rc::new(RefCell::new(callback)));
+    }
+
+    /// Replace the variables of every scope with those of `other`, keeping the callback
+    pub(crate) fn replace_scopes(&mut self, other: Self) { self.scopes = other.scopes; }
+
+    fn notify(&self, name: &str, value: &Value<Rc<Function>>) {
+        if let Some(callback) = &self.on_set {
+            (callback.borrow_mut())(name, value);
         }
     }
 
+    /// Call the callback with the new value of a variable which was modified in place, such as
+    /// an array after an assignment to one of its elements
+    pub(crate) fn notify_modified(&self, name: &str) {
+        if let Some(value) = self.get(name) {
+            self.notify(name, value);
+        }
+    }
+
+    /// Set a variable in the current scope, shadowing any variable of the same name in the
+    /// outer scopes instead of updating it.
+    pub(crate) fn shadow<T: Into<Value<Rc<Function>>>>(&mut self, name: &str, value: T) {
+        let value = value.into();
+        self.notify(name, &value);
+        self.scopes.set(name, value);
+    }
+
     /// Set a variable to a value in the top scope.
     /// If a variable already exists in any scope, it is updated and is put in the global scope.
     pub fn set_global<T: Into<Value<Rc<Function>>>>(&mut self, name: &str, value: T) {
         let value = value.into();
-        self.0.remove_variable(name);
-        self.0.set_global(name, value);
+        self.notify(name, &value);
+        self.scopes.remove_variable(name);
+        self.scopes.set_global(name, value);
     }
 
     /// Obtains the value for the **MWD** variable.
@@ -187,7 +234,7 @@ impl Variables {
             // Cannot mutate outer namespace
             return None;
         }
-        self.0.remove_variable(name)
+        self.scopes.remove_variable(name)
     }
 
     /// Get the string value associated with a name on the current scope. This includes fetching
@@ -224,9 +271,9 @@ impl Variables {
         }
     }
 
-    /// Get a variable on the current scope
-    #[must_use]
-    pub fn get(&self, mut name: &str) -> Option<&Value<Rc<Function>>> {
+    /// Splits the `global::` or `super::` prefixes off `name`, giving the namespace they
+    /// designate.
+    pub(crate) fn namespace(mut name: &str) -> (Namespace, &str) {
         const GLOBAL_NS: &str = "global::";
         const SUPER_NS: &str = "super::";
 
@@ -245,7 +292,38 @@ impl Variables {
         } else {
             Namespace::Any
         };
-        self.0.get(name, namespace)
+        (namespace, name)
+    }
+
+    /// Get a variable on the current scope
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Value<Rc<Function>>> {
+        let (namespace, name) = Self::namespace(name);
+        self.scopes.get(name, namespace)
+    }
+
+    /// Set a variable in the namespace designated by the prefixes of its name, as with `get`:
+    /// `global::name` sets it in the global namespace and `super::name` in the namespace of the
+    /// caller of the current function, so that it outlives the current function. Without a
+    /// prefix, this is the same as `set`.
+    ///
+    /// A variable which does not exist yet in the namespace is created in its outermost scope.
+    /// Returns false if the namespace does not exist, in which case nothing is set.
+    pub fn set_namespaced<T: Into<Value<Rc<Function>>>>(&mut self, name: &str, value: T) -> bool {
+        match Self::namespace(name) {
+            (Namespace::Any, name) => {
+                self.set(name, value);
+                true
+            }
+            (namespace, name) => {
+                let value = value.into();
+                let set = self.scopes.set_in(name, value.clone(), namespace);
+                if set {
+                    self.notify(name, &value);
+                }
+                set
+            }
+        }
     }
 
     /// Get a mutable access to a variable on the current scope
@@ -255,7 +333,7 @@ impl Variables {
             // Cannot mutate outer namespace
             return None;
         }
-        self.0.get_mut(name)
+        self.scopes.get_mut(name)
     }
 }
 
@@ -289,7 +367,7 @@ impl Default for Variables {
                 .as_ref(),
         );
 
-        Self(map)
+        Self { scopes: map, on_set: None }
     }
 }
 
@@ -325,6 +403,16 @@ pub(crate) mod tests {
             Ok(cmd.into())
         }
 
+        fn command_succeeds(&mut self, _cmd: &str) -> Result<bool, Self::Error> { Ok(true) }
+
+        fn substitute_process(
+            &mut self,
+            _cmd: &str,
+            _input: bool,
+        ) -> Result<types::Str, Self::Error> {
+            Ok(types::Str::new())
+        }
+
         fn tilde(&self, input: &str) -> Result<types::Str, Self::Error> { Ok(input.into()) }
 
         fn map_keys(&self, name: &str) -> Result<types::Args, Self::Error> {
@@ -334,6 +422,10 @@ pub(crate) mod tests {
         fn map_values(&self, name: &str) -> Result<types::Args, Self::Error> {
             Err(expansion::Error::VarNotFound(name.into()))
         }
+
+        fn map_entries(&self, name: &str) -> Result<types::Args, Self::Error> {
+            Err(expansion::Error::VarNotFound(name.into()))
+        }
     }
 
     #[test]
@@ -350,6 +442,28 @@ pub(crate) mod tests {
         assert_eq!("BAR", &expanded);
     }
 
+    #[test]
+    fn set_in_outer_namespaces() {
+        let mut variables = Variables::default();
+        // A function calling another one, within a block
+        variables.new_scope(true);
+        variables.new_scope(true);
+        variables.new_scope(false);
+        assert!(variables.set_namespaced("global::injected", "everywhere"));
+        assert!(variables.set_namespaced("super::returned", "to the caller"));
+        assert!(variables.set_namespaced("local", "here"));
+        assert!(!variables.set_namespaced("super::super::super::missing", "nowhere"));
+
+        variables.pop_scope();
+        assert!(variables.get("local").is_none());
+        variables.pop_scope();
+        assert_eq!(variables.get_str("returned").unwrap().as_str(), "to the caller");
+        variables.pop_scope();
+        assert!(variables.get("returned").is_none());
+        assert_eq!(variables.get_str("injected").unwrap().as_str(), "everywhere");
+        assert!(variables.get("missing").is_none());
+    }
+
     #[test]
     #[serial]
     fn minimal_directory_var_should_compact_path() {
@@ -1,6 +1,6 @@
-use self::binary::{builtins, InteractiveShell};
+use self::binary::{builtins, CtrlCBehavior, InteractiveShell};
 use atty::Stream;
-use ion_shell::{BackgroundEvent, BuiltinMap, IonError, PipelineError, Shell, Value};
+use ion_shell::{IonError, PipelineError, Shell};
 use liner::KeyBindings;
 use nix::{
     sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal},
@@ -10,7 +10,6 @@ use std::{
     fs,
     io::{stdin, BufReader},
     process,
-    sync::Arc,
 };
 
 use crate::binary::MAN_ION;
@@ -31,6 +30,8 @@ struct CommandLineArgs {
     version:          bool,
     /// Do not execute any commands, perform only syntax checking
     no_execute:       bool,
+    /// Do not expand glob patterns
+    no_glob:          bool,
     /// Use a fake interactive mode, where errors don't exit the shell
     fake_interactive: bool,
     /// Force interactive mode
@@ -39,6 +40,8 @@ struct CommandLineArgs {
     print_commands:   bool,
     /// Shortcut layout. Valid options: "vi", "emacs"
     key_bindings:     Option<KeyBindingsWrapper>,
+    /// What Ctrl-C does at the prompt. Valid options: "abort", "clear", "exit"
+    ctrl_c:           Option<CtrlCBehavior>,
     /// Evaluate given commands instead of reading from the commandline
     command:          Option<String>,
     /// Script arguments (@args). If the -c option is not specified,
@@ -52,6 +55,8 @@ pub enum ParsingError {
     ArgTwiceSet,
     #[error("invalid keybinding, see --help")]
     InvalidKeybinding,
+    #[error("invalid Ctrl-C behavior, see --help")]
+    InvalidCtrlC,
 }
 
 fn version() -> String { include!(concat!(env!("OUT_DIR"), "/version_string")).to_string() }
@@ -63,10 +68,12 @@ fn parse_args() -> Result<CommandLineArgs, ParsingError> {
     let mut version = false;
     let mut help = false;
     let mut no_execute = false;
+    let mut no_glob = false;
     let mut fake_interactive = false;
     let mut interactive = false;
     let mut print_commands = false;
     let mut key_bindings = None;
+    let mut ctrl_c = None;
     let mut command = None;
     let mut additional_arguments = Vec::new();
 
@@ -90,6 +97,12 @@ fn parse_args() -> Result<CommandLineArgs, ParsingError> {
                 }
                 no_execute = true;
             }
+            "-g" | "--no-glob" => {
+                if no_glob {
+                    arg_twice_set = true;
+                }
+                no_glob = true;
+            }
             "-f" | "--fake-interactive" => {
                 if fake_interactive {
                     arg_twice_set = true;
@@ -127,6 +140,17 @@ fn parse_args() -> Result<CommandLineArgs, ParsingError> {
                     }
                 }
             }
+            "--ctrl-c" => {
+                if ctrl_c.is_some() {
+                    arg_twice_set = true;
+                }
+                ctrl_c = match args.next().as_deref() {
+                    Some("abort") => Some(CtrlCBehavior::Abort),
+                    Some("clear") => Some(CtrlCBehavior::Clear),
+                    Some("exit") => Some(CtrlCBehavior::Exit),
+                    _ => return Err(ParsingError::InvalidCtrlC),
+                }
+            }
             "-c" => {
                 // convert Option<String< to Option<&str> due to type system limitation
                 if let Some(_p) = command.as_deref() {
@@ -150,10 +174,12 @@ fn parse_args() -> Result<CommandLineArgs, ParsingError> {
         help,
         version,
         no_execute,
+        no_glob,
         fake_interactive,
         interactive,
         print_commands,
         key_bindings,
+        ctrl_c,
         command,
         args: additional_arguments,
     })
@@ -183,6 +209,10 @@ fn main() {
             eprintln!("invalid keybinding, see --help");
             process::exit(1);
         }
+        Err(ParsingError::InvalidCtrlC) => {
+            eprintln!("invalid Ctrl-C behavior, see --help");
+            process::exit(1);
+        }
     };
 
     if command_line_args.help {
@@ -198,16 +228,15 @@ fn main() {
         process::exit(1);
     }
 
-    let mut builtins = BuiltinMap::default();
-    builtins
-        .with_unsafe()
+    let mut shell = Shell::with_interactive_defaults();
+    shell
+        .builtins_mut()
         .add("debug", &builtins::builtin_debug, "Toggle debug mode (print commands on exec)")
         .add("exec", &builtins::builtin_exec, "Replace the shell with the given command.")
         .add("exit", &builtins::builtin_exit, "Exits the current session")
         .add("suspend", &builtins::builtin_suspend, "Suspends the shell with a SIGTSTOP signal");
 
     let stdin_is_a_tty = atty::is(Stream::Stdin);
-    let mut shell = Shell::with_builtins(builtins);
 
     if stdin_is_a_tty {
         if let Err(err) = set_unique_pid() {
@@ -215,19 +244,8 @@ fn main() {
         }
     }
 
-    shell.set_background_event(Some(Arc::new(|njob, pid, kind| match kind {
-        BackgroundEvent::Added => eprintln!("ion: bg [{}] {}", njob, pid),
-        BackgroundEvent::Stopped => eprintln!("ion: ([{}] {}) Stopped", njob, pid),
-        BackgroundEvent::Resumed => eprintln!("ion: ([{}] {}) Running", njob, pid),
-        BackgroundEvent::Exited(status) => {
-            eprintln!("ion: ([{}] {}) exited with {}", njob, pid, status)
-        }
-        BackgroundEvent::Errored(error) => {
-            eprintln!("ion: ([{}] {}) errored: {}", njob, pid, error)
-        }
-    })));
-
     shell.opts_mut().no_exec = command_line_args.no_execute;
+    shell.opts_mut().no_glob = command_line_args.no_glob;
     shell.opts_mut().grab_tty = stdin_is_a_tty;
     if command_line_args.print_commands {
         shell.set_pre_command(Some(Box::new(|_shell, pipeline| {
@@ -237,46 +255,44 @@ fn main() {
     }
 
     let script_path = command_line_args.args.get(0).cloned();
-    shell.variables_mut().set(
-        "args",
-        Value::Array(
-            if script_path.is_some() {
-                command_line_args.args
-            } else {
-                vec![std::env::args().next().unwrap()]
-            }
-            .into_iter()
-            .map(|arg| Value::Str(arg.into()))
-            .collect(),
-        ),
-    );
+    let args = if script_path.is_some() {
+        command_line_args.args
+    } else {
+        vec![std::env::args().next().unwrap()]
+    };
 
     let err = if let Some(command) = command_line_args.command {
-        shell.execute_command(command.as_bytes())
+        shell.execute_script(command.as_bytes(), &args)
     } else if let Some(path) = script_path {
         match fs::File::open(&path) {
-            Ok(script) => shell.execute_command(std::io::BufReader::new(script)),
+            Ok(script) => shell.execute_script(BufReader::new(script), &args),
             Err(cause) => {
                 println!("ion: could not execute '{}': {}", path, cause);
                 process::exit(1);
             }
         }
-    } else if stdin_is_a_tty || command_line_args.interactive {
-        let mut interactive = InteractiveShell::new(shell);
-        if let Some(key_bindings) = command_line_args.key_bindings {
-            interactive.set_keybindings(key_bindings.0);
-        }
-        interactive.add_callbacks();
-        interactive.execute_interactive();
-    } else if command_line_args.fake_interactive {
-        let mut reader = BufReader::new(stdin());
-        loop {
-            if let Err(err) = shell.execute_command(&mut reader) {
-                eprintln!("ion: {}", err);
+    } else {
+        shell.set_args(args.into_iter().map(Into::into).collect());
+        if stdin_is_a_tty || command_line_args.interactive {
+            let mut interactive = InteractiveShell::new(shell);
+            if let Some(key_bindings) = command_line_args.key_bindings {
+                interactive.set_keybindings(key_bindings.0);
             }
+            if let Some(behavior) = command_line_args.ctrl_c {
+                interactive.set_ctrl_c_behavior(behavior);
+            }
+            interactive.add_callbacks();
+            interactive.execute_interactive();
+        } else if command_line_args.fake_interactive {
+            let mut reader = BufReader::new(stdin());
+            loop {
+                if let Err(err) = shell.execute_command(&mut reader) {
+                    eprintln!("ion: {}", err);
+                }
+            }
+        } else {
+            shell.execute_command(BufReader::new(stdin()))
         }
-    } else {
-        shell.execute_command(BufReader::new(stdin()))
     }
     .and_then(|_| shell.wait_for_background().map_err(Into::into));
     if let Err(IonError::PipelineExecutionError(PipelineError::Interrupted(_, signal))) = err {
@@ -285,9 +301,11 @@ fn main() {
         let _ = unsafe { nix::sys::signal::sigaction(signal, &action) };
         let _ = nix::sys::signal::raise(signal);
     }
+    shell.run_exit_trap();
+    let _ = shell.flush();
     if let Err(why) = err {
         eprintln!("ion: {}", why);
-        process::exit(1);
+        process::exit(why.exit_code());
     }
     process::exit(shell.previous_status().as_os_code());
 }